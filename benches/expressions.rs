@@ -0,0 +1,70 @@
+#[macro_use]
+extern crate criterion;
+extern crate ldscript_parser as lds;
+
+use criterion::{BenchmarkId, Criterion};
+
+/// `A = ALIGN(ALIGN(...ALIGN(., 4)..., 4), 4);`
+fn align_chain(depth: usize) -> String {
+    let mut text = String::from("A = ");
+    for _ in 0..depth {
+        text.push_str("ALIGN(");
+    }
+    text.push('.');
+    for _ in 0..depth {
+        text.push_str(", 4)");
+    }
+    text.push(';');
+    text
+}
+
+/// `A = ((...(1 + 2) * 3...) - 4);`
+fn nested_parens(depth: usize) -> String {
+    let mut text = String::from("A = ");
+    for _ in 0..depth {
+        text.push('(');
+    }
+    text.push('1');
+    for i in 0..depth {
+        text.push_str([" + 2)", " * 3)", " - 4)", " << 1)"][i % 4]);
+    }
+    text.push(';');
+    text
+}
+
+/// `A = 1 + 2 * 3 - ... ;` with a long flat operator chain
+fn long_chain(length: usize) -> String {
+    let mut text = String::from("A = 1");
+    for i in 0..length {
+        text.push_str([" + ", " * ", " | ", " == ", " && "][i % 5]);
+        text.push_str(&i.to_string());
+    }
+    text.push(';');
+    text
+}
+
+fn bench_expressions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("expressions");
+    for &depth in &[10, 50, 100] {
+        let text = align_chain(depth);
+        group.bench_with_input(BenchmarkId::new("align_chain", depth), &text, |b, text| {
+            b.iter(|| lds::parse(text).unwrap())
+        });
+        let text = nested_parens(depth);
+        group.bench_with_input(
+            BenchmarkId::new("nested_parens", depth),
+            &text,
+            |b, text| b.iter(|| lds::parse(text).unwrap()),
+        );
+    }
+    for &length in &[100, 1000] {
+        let text = long_chain(length);
+        group.bench_with_input(BenchmarkId::new("long_chain", length), &text, |b, text| {
+            b.iter(|| lds::parse(text).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_expressions);
+criterion_main!(benches);
@@ -0,0 +1,64 @@
+//! Formats linker scripts in place.
+//!
+//! Usage: `ldfmt [--check] FILE...`
+//!
+//! With `--check` the files are left untouched, the names of the ones that
+//! are not formatted are printed and the exit code is 1. Files that do not
+//! parse, or that have comments the formatter would drop, are reported and
+//! never rewritten.
+
+extern crate ldscript_parser as lds;
+
+use lds::format::{format_str, FormatOptions};
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let mut check = false;
+    let mut files = Vec::new();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--check" => check = true,
+            "-h" | "--help" => {
+                println!("Usage: ldfmt [--check] FILE...");
+                return;
+            }
+            _ => files.push(arg),
+        }
+    }
+
+    let options = FormatOptions::default();
+    let mut failed = false;
+    for file in &files {
+        let input = match fs::read_to_string(file) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                failed = true;
+                continue;
+            }
+        };
+        let text = match format_str(&input, &options) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                failed = true;
+                continue;
+            }
+        };
+        if text == input {
+            continue;
+        }
+        if check {
+            println!("{}", file);
+            failed = true;
+        } else if let Err(e) = fs::write(file, text) {
+            eprintln!("{}: {}", file, e);
+            failed = true;
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,524 @@
+//! Builders for constructing linker scripts in code.
+//!
+//! The builders produce the regular AST types with the optional parts left
+//! empty, so the result can be passed to the [`print`](../print/index.html)
+//! or [`format`](../format/index.html) modules. [`ScriptBuilder::build`]
+//! checks that the region names are unique and that every output section is
+//! placed into a defined region.
+//!
+//! ```
+//! extern crate ldscript_parser as lds;
+//!
+//! use lds::builder::{InputSectionBuilder, MemoryBuilder, OutputSectionBuilder, ScriptBuilder};
+//!
+//! fn main() {
+//!     let items = ScriptBuilder::new()
+//!         .entry("Reset_Handler")
+//!         .memory(
+//!             MemoryBuilder::new()
+//!                 .region_with_attributes("FLASH", "rx", 0x0800_0000, 0x10000)
+//!                 .region("RAM", 0x2000_0000, 0x5000),
+//!         )
+//!         .output_section(
+//!             OutputSectionBuilder::new(".text")
+//!                 .input(InputSectionBuilder::new().keep().section(".isr_vector"))
+//!                 .input(InputSectionBuilder::new().section(".text").section(".text*"))
+//!                 .region("FLASH"),
+//!         )
+//!         .output_section(
+//!             OutputSectionBuilder::new(".data")
+//!                 .input(InputSectionBuilder::new().section(".data"))
+//!                 .region("RAM")
+//!                 .lma_region("FLASH"),
+//!         )
+//!         .build()
+//!         .unwrap();
+//!
+//!     let text = lds::print::print(&items);
+//!     assert_eq!(lds::parse(&text).unwrap(), items);
+//! }
+//! ```
+
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use commands::Command;
+use core::fmt;
+use expressions::Expression;
+use memory::Region;
+use script::RootItem;
+use sections::{
+    DataType, OutputSectionCommand, OutputSectionConstraint, OutputSectionType, SectionCommand,
+    SectionPattern,
+};
+use statements::Statement;
+#[cfg(feature = "std")]
+use std::error::Error;
+
+/// Validation failure of [`ScriptBuilder::build`].
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum BuildError {
+    /// More than one region has this name
+    DuplicateRegion(String),
+    /// An output section is placed into a region that is not defined
+    UndefinedRegion { section: String, region: String },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildError::DuplicateRegion(ref name) => {
+                write!(f, "region `{}` is defined more than once", name)
+            }
+            BuildError::UndefinedRegion {
+                ref section,
+                ref region,
+            } => write!(
+                f,
+                "output section `{}` refers to undefined region `{}`",
+                section, region
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for BuildError {}
+
+/// Builds a list of root items.
+///
+/// Output sections and statements added with [`output_section`] and
+/// [`sections_statement`] go into the SECTIONS block at the end of the
+/// script, a new block is started if the last item is something else.
+///
+/// [`output_section`]: #method.output_section
+/// [`sections_statement`]: #method.sections_statement
+#[derive(Debug, Default, Clone)]
+pub struct ScriptBuilder<'a> {
+    items: Vec<RootItem<'a>>,
+}
+
+impl<'a> ScriptBuilder<'a> {
+    pub fn new() -> Self {
+        ScriptBuilder { items: Vec::new() }
+    }
+
+    /// Adds an `ENTRY(symbol)` command
+    pub fn entry<S>(self, symbol: S) -> Self
+    where
+        S: Into<Cow<'a, str>>,
+    {
+        self.command(Command::Call {
+            name: "ENTRY".into(),
+            arguments: vec![Expression::Ident(symbol.into())],
+            comments: Vec::new(),
+        })
+    }
+
+    pub fn command(mut self, command: Command<'a>) -> Self {
+        self.items.push(RootItem::Command(command));
+        self
+    }
+
+    pub fn statement(mut self, statement: Statement<'a>) -> Self {
+        self.items.push(RootItem::Statement(statement));
+        self
+    }
+
+    /// Adds a MEMORY block
+    pub fn memory(mut self, memory: MemoryBuilder<'a>) -> Self {
+        self.items.push(memory.build());
+        self
+    }
+
+    pub fn output_section(self, section: OutputSectionBuilder<'a>) -> Self {
+        self.section_command(section.build())
+    }
+
+    /// Adds a statement to the SECTIONS block, e.g. `. = ALIGN(4);`
+    pub fn sections_statement(self, statement: Statement<'a>) -> Self {
+        self.section_command(SectionCommand::Statement(statement))
+    }
+
+    fn section_command(mut self, command: SectionCommand<'a>) -> Self {
+        if let Some(&mut RootItem::Sections { ref mut list, .. }) = self.items.last_mut() {
+            list.push(command);
+            return self;
+        }
+        self.items.push(RootItem::Sections {
+            list: vec![command],
+            comments: Vec::new(),
+        });
+        self
+    }
+
+    /// Validates the script and returns its items
+    pub fn build(self) -> Result<Vec<RootItem<'a>>, BuildError> {
+        let mut regions: Vec<&str> = Vec::new();
+        for item in &self.items {
+            if let RootItem::Memory {
+                regions: ref list, ..
+            } = *item
+            {
+                for region in list {
+                    if regions.contains(&&*region.name) {
+                        return Err(BuildError::DuplicateRegion(region.name.to_string()));
+                    }
+                    regions.push(&region.name);
+                }
+            }
+        }
+        for item in &self.items {
+            if let RootItem::Sections { ref list, .. } = *item {
+                for command in list {
+                    if let SectionCommand::OutputSection {
+                        ref name,
+                        ref region,
+                        ref lma_region,
+                        ..
+                    } = *command
+                    {
+                        for region in region.iter().chain(lma_region) {
+                            if !regions.contains(&&**region) {
+                                return Err(BuildError::UndefinedRegion {
+                                    section: name.to_string(),
+                                    region: region.to_string(),
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(self.items)
+    }
+}
+
+/// Builds a MEMORY block.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryBuilder<'a> {
+    regions: Vec<Region<'a>>,
+}
+
+impl<'a> MemoryBuilder<'a> {
+    pub fn new() -> Self {
+        MemoryBuilder {
+            regions: Vec::new(),
+        }
+    }
+
+    pub fn region<S>(self, name: S, origin: u64, length: u64) -> Self
+    where
+        S: Into<Cow<'a, str>>,
+    {
+        self.push(name.into(), None, origin, length)
+    }
+
+    /// Adds a region with attributes such as `rx` or `!w`
+    pub fn region_with_attributes<S, A>(
+        self,
+        name: S,
+        attributes: A,
+        origin: u64,
+        length: u64,
+    ) -> Self
+    where
+        S: Into<Cow<'a, str>>,
+        A: Into<Cow<'a, str>>,
+    {
+        self.push(name.into(), Some(attributes.into()), origin, length)
+    }
+
+    fn push(
+        mut self,
+        name: Cow<'a, str>,
+        attributes: Option<Cow<'a, str>>,
+        origin: u64,
+        length: u64,
+    ) -> Self {
+        self.regions.push(Region {
+            name,
+            attributes,
+            origin,
+            length,
+            comments: Vec::new(),
+        });
+        self
+    }
+
+    pub fn build(self) -> RootItem<'a> {
+        RootItem::Memory {
+            regions: self.regions,
+            comments: Vec::new(),
+        }
+    }
+}
+
+/// Builds a `SectionCommand::OutputSection`.
+#[derive(Debug, Clone)]
+pub struct OutputSectionBuilder<'a> {
+    name: Cow<'a, str>,
+    vma_address: Option<Box<Expression<'a>>>,
+    s_type: Option<OutputSectionType>,
+    lma_address: Option<Box<Expression<'a>>>,
+    section_align: Option<Box<Expression<'a>>>,
+    align_with_input: bool,
+    subsection_align: Option<Box<Expression<'a>>>,
+    constraint: Option<OutputSectionConstraint>,
+    content: Vec<OutputSectionCommand<'a>>,
+    region: Option<Cow<'a, str>>,
+    lma_region: Option<Cow<'a, str>>,
+    fillexp: Option<Box<Expression<'a>>>,
+}
+
+impl<'a> OutputSectionBuilder<'a> {
+    pub fn new<S>(name: S) -> Self
+    where
+        S: Into<Cow<'a, str>>,
+    {
+        OutputSectionBuilder {
+            name: name.into(),
+            vma_address: None,
+            s_type: None,
+            lma_address: None,
+            section_align: None,
+            align_with_input: false,
+            subsection_align: None,
+            constraint: None,
+            content: Vec::new(),
+            region: None,
+            lma_region: None,
+            fillexp: None,
+        }
+    }
+
+    /// Sets the virtual address, the expression before the colon
+    pub fn vma<E: Into<Expression<'a>>>(mut self, address: E) -> Self {
+        self.vma_address = Some(Box::new(address.into()));
+        self
+    }
+
+    pub fn s_type(mut self, s_type: OutputSectionType) -> Self {
+        self.s_type = Some(s_type);
+        self
+    }
+
+    /// Sets the load address, `AT(address)`
+    pub fn lma<E: Into<Expression<'a>>>(mut self, address: E) -> Self {
+        self.lma_address = Some(Box::new(address.into()));
+        self
+    }
+
+    pub fn align<E: Into<Expression<'a>>>(mut self, align: E) -> Self {
+        self.section_align = Some(Box::new(align.into()));
+        self
+    }
+
+    pub fn align_with_input(mut self) -> Self {
+        self.align_with_input = true;
+        self
+    }
+
+    pub fn subalign<E: Into<Expression<'a>>>(mut self, align: E) -> Self {
+        self.subsection_align = Some(Box::new(align.into()));
+        self
+    }
+
+    pub fn constraint(mut self, constraint: OutputSectionConstraint) -> Self {
+        self.constraint = Some(constraint);
+        self
+    }
+
+    /// Places the section into a region, `> region`
+    pub fn region<S: Into<Cow<'a, str>>>(mut self, region: S) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+
+    /// Loads the section into a region, `AT> region`
+    pub fn lma_region<S: Into<Cow<'a, str>>>(mut self, region: S) -> Self {
+        self.lma_region = Some(region.into());
+        self
+    }
+
+    /// Sets the fill pattern of the section, `= fillexp`
+    pub fn fill<E: Into<Expression<'a>>>(mut self, fill: E) -> Self {
+        self.fillexp = Some(Box::new(fill.into()));
+        self
+    }
+
+    /// Appends a command to the body of the section
+    pub fn command(mut self, command: OutputSectionCommand<'a>) -> Self {
+        self.content.push(command);
+        self
+    }
+
+    pub fn input(self, input: InputSectionBuilder<'a>) -> Self {
+        self.command(input.build())
+    }
+
+    pub fn statement(self, statement: Statement<'a>) -> Self {
+        self.command(OutputSectionCommand::Statement(statement))
+    }
+
+    /// Appends a `BYTE`, `SHORT`, `LONG` or `QUAD` command
+    pub fn data<E: Into<Expression<'a>>>(self, d_type: DataType, value: E) -> Self {
+        self.command(OutputSectionCommand::Data {
+            d_type,
+            value: Box::new(value.into()),
+            comments: Vec::new(),
+        })
+    }
+
+    pub fn build(self) -> SectionCommand<'a> {
+        SectionCommand::OutputSection {
+            name: self.name,
+            vma_address: self.vma_address,
+            s_type: self.s_type,
+            lma_address: self.lma_address,
+            section_align: self.section_align,
+            align_with_input: self.align_with_input,
+            subsection_align: self.subsection_align,
+            constraint: self.constraint,
+            content: self.content,
+            region: self.region,
+            lma_region: self.lma_region,
+            fillexp: self.fillexp,
+            comments: Vec::new(),
+        }
+    }
+}
+
+/// Builds an input section description such as `KEEP(*(.text .text.*))`.
+///
+/// The file pattern defaults to `*`.
+#[derive(Debug, Clone)]
+pub struct InputSectionBuilder<'a> {
+    file: SectionPattern<'a>,
+    sections: Vec<SectionPattern<'a>>,
+    keep: bool,
+}
+
+impl<'a> Default for InputSectionBuilder<'a> {
+    fn default() -> Self {
+        InputSectionBuilder {
+            file: SectionPattern::Simple("*".into()),
+            sections: Vec::new(),
+            keep: false,
+        }
+    }
+}
+
+impl<'a> InputSectionBuilder<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn file<P: Into<SectionPattern<'a>>>(mut self, file: P) -> Self {
+        self.file = file.into();
+        self
+    }
+
+    pub fn section<P: Into<SectionPattern<'a>>>(mut self, section: P) -> Self {
+        self.sections.push(section.into());
+        self
+    }
+
+    /// Wraps the description into `KEEP(...)`
+    pub fn keep(mut self) -> Self {
+        self.keep = true;
+        self
+    }
+
+    pub fn build(self) -> OutputSectionCommand<'a> {
+        if self.keep {
+            OutputSectionCommand::KeepInputSection {
+                file: self.file,
+                sections: self.sections,
+                comments: Vec::new(),
+            }
+        } else {
+            OutputSectionCommand::InputSection {
+                file: self.file,
+                sections: self.sections,
+                comments: Vec::new(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use builder::*;
+    use print::print;
+
+    #[test]
+    fn test_output_section() {
+        let section = OutputSectionBuilder::new(".bss")
+            .vma(0x100)
+            .s_type(OutputSectionType::NoLoad)
+            .align(8)
+            .input(
+                InputSectionBuilder::new()
+                    .section(".bss")
+                    .section(SectionPattern::SortByName("COMMON".into())),
+            )
+            .data(DataType::Long, 0)
+            .fill(0xFF)
+            .build();
+        assert_eq!(
+            section.to_string(),
+            ".bss 0x100 (NOLOAD) : ALIGN(8)\n{\n  *(.bss SORT_BY_NAME(COMMON))\n  LONG(0)\n} = 255"
+        );
+    }
+
+    #[test]
+    fn test_script() {
+        let items = ScriptBuilder::new()
+            .memory(MemoryBuilder::new().region("ROM", 0, 0x1000))
+            .sections_statement(Statement::Assign {
+                name: ".".into(),
+                operator: ::AssignOperator::Equals,
+                expression: Box::new(0x10.into()),
+                comments: Vec::new(),
+            })
+            .output_section(OutputSectionBuilder::new(".text").region("ROM"))
+            .build()
+            .unwrap();
+        assert_eq!(
+            print(&items),
+            "MEMORY\n{\n  ROM : ORIGIN = 0x0, LENGTH = 0x1000\n}\nSECTIONS\n{\n  . = 16;\n  .text : {} > ROM\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_validation() {
+        let duplicate = ScriptBuilder::new()
+            .memory(MemoryBuilder::new().region("RAM", 0, 1))
+            .memory(MemoryBuilder::new().region("RAM", 1, 1))
+            .build();
+        assert_eq!(duplicate, Err(BuildError::DuplicateRegion("RAM".into())));
+
+        let undefined = ScriptBuilder::new()
+            .memory(MemoryBuilder::new().region("RAM", 0, 1))
+            .output_section(
+                OutputSectionBuilder::new(".data")
+                    .region("RAM")
+                    .lma_region("ROM"),
+            )
+            .build();
+        assert_eq!(
+            undefined,
+            Err(BuildError::UndefinedRegion {
+                section: ".data".into(),
+                region: "ROM".into(),
+            })
+        );
+        assert_eq!(
+            undefined.unwrap_err().to_string(),
+            "output section `.data` refers to undefined region `ROM`"
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
 use expressions::expression;
 use expressions::Expression;
 use idents::{pattern, symbol};
@@ -6,32 +8,93 @@ use nom::bytes::complete::tag;
 use nom::combinator::map;
 use nom::combinator::opt;
 use nom::multi::separated_list1;
-use nom::sequence::pair;
+use nom::sequence::{pair, preceded};
 use nom::IResult;
-use whitespace::{opt_space, space};
+use whitespace::{opt_space, space, Comment, Commented};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum InsertOrder {
     Before,
     After,
 }
 
-#[derive(Debug, PartialEq)]
-pub enum Command {
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
+pub enum Command<'a> {
     //Simple { name: String },
     Call {
-        name: String,
-        arguments: Vec<Expression>,
+        name: Cow<'a, str>,
+        arguments: Vec<Expression<'a>>,
+        comments: Vec<Comment<'a>>,
     },
     Include {
-        file: String,
+        file: Cow<'a, str>,
+        comments: Vec<Comment<'a>>,
     },
     Insert {
         order: InsertOrder,
-        section: String,
+        section: Cow<'a, str>,
+        comments: Vec<Comment<'a>>,
     },
 }
 
+impl<'a> Command<'a> {
+    /// Comments attached to this command
+    pub fn comments(&self) -> &[Comment<'a>] {
+        match *self {
+            Command::Call { ref comments, .. }
+            | Command::Include { ref comments, .. }
+            | Command::Insert { ref comments, .. } => comments,
+        }
+    }
+
+    /// Converts the command into one that owns all of its strings
+    pub fn into_owned(self) -> Command<'static> {
+        match self {
+            Command::Call {
+                name,
+                arguments,
+                comments,
+            } => Command::Call {
+                name: Cow::Owned(name.into_owned()),
+                arguments: arguments.into_iter().map(Expression::into_owned).collect(),
+                comments: comments.into_iter().map(Comment::into_owned).collect(),
+            },
+            Command::Include { file, comments } => Command::Include {
+                file: Cow::Owned(file.into_owned()),
+                comments: comments.into_iter().map(Comment::into_owned).collect(),
+            },
+            Command::Insert {
+                order,
+                section,
+                comments,
+            } => Command::Insert {
+                order,
+                section: Cow::Owned(section.into_owned()),
+                comments: comments.into_iter().map(Comment::into_owned).collect(),
+            },
+        }
+    }
+}
+
+impl<'a> Commented<'a> for Command<'a> {
+    fn comments_mut(&mut self) -> Option<&mut Vec<Comment<'a>>> {
+        match *self {
+            Command::Call {
+                ref mut comments, ..
+            }
+            | Command::Include {
+                ref mut comments, ..
+            }
+            | Command::Insert {
+                ref mut comments, ..
+            } => Some(comments),
+        }
+    }
+}
+
 fn inset_order(input: &str) -> IResult<&str, InsertOrder> {
     alt((
         map(tag("BEFORE"), |_| InsertOrder::Before),
@@ -39,42 +102,51 @@ fn inset_order(input: &str) -> IResult<&str, InsertOrder> {
     ))(input)
 }
 
-fn call(input: &str) -> IResult<&str, Command> {
+fn call(input: &str) -> IResult<&str, Command<'_>> {
     let (input, name) = symbol(input)?;
     let (input, _) = wsc!(tag("("))(input)?;
     let (input, args) = separated_list1(alt((space, wsc!(tag(",")))), expression)(input)?;
-    let (input, _) = pair(wsc!(tag(")")), opt(tag(";")))(input)?;
+    let (input, _) = pair(opt_space, tag(")"))(input)?;
+    let (input, _) = opt(preceded(opt_space, tag(";")))(input)?;
     Ok((
         input,
         Command::Call {
             name: name.into(),
             arguments: args,
+            comments: Vec::new(),
         },
     ))
 }
 
-fn include(input: &str) -> IResult<&str, Command> {
+fn include(input: &str) -> IResult<&str, Command<'_>> {
     let (input, _) = pair(tag("INCLUDE"), space)(input)?;
     let (input, file) = pattern(input)?;
-    let (input, _) = pair(opt_space, opt(tag(";")))(input)?;
-    Ok((input, Command::Include { file: file.into() }))
+    let (input, _) = opt(preceded(opt_space, tag(";")))(input)?;
+    Ok((
+        input,
+        Command::Include {
+            file: file.into(),
+            comments: Vec::new(),
+        },
+    ))
 }
 
-fn insert(input: &str) -> IResult<&str, Command> {
+fn insert(input: &str) -> IResult<&str, Command<'_>> {
     let (input, _) = tag("INSERT")(input)?;
     let (input, order) = wsc!(inset_order)(input)?;
     let (input, section) = symbol(input)?;
-    let (input, _) = pair(opt_space, opt(tag(";")))(input)?;
+    let (input, _) = opt(preceded(opt_space, tag(";")))(input)?;
     Ok((
         input,
         Command::Insert {
             order,
             section: section.into(),
+            comments: Vec::new(),
         },
     ))
 }
 
-pub fn command(input: &str) -> IResult<&str, Command> {
+pub fn command(input: &str) -> IResult<&str, Command<'_>> {
     alt((include, call, insert))(input)
 }
 
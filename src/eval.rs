@@ -0,0 +1,590 @@
+//! Evaluation of expressions.
+//!
+//! [`Expression::eval`] computes the value of an expression the way ld does:
+//! arithmetic wraps around, comparisons and the logical operators produce 0
+//! or 1, and dividing by zero is an error. Symbols and the builtins that
+//! take a name, such as `ORIGIN(RAM)` or `SIZEOF(.text)`, are looked up in
+//! an [`Env`] that the caller implements. [`Expression::eval_in`] computes
+//! with the address width of the target, see [`EvalContext`].
+//!
+//! ```
+//! extern crate ldscript_parser as lds;
+//!
+//! use lds::eval::Env;
+//! use lds::{Expression, Script, Statement};
+//!
+//! struct Regions<'s>(&'s Script<'static>);
+//!
+//! impl<'s> Env for Regions<'s> {
+//!     fn symbol(&self, _name: &str) -> Option<u64> {
+//!         None
+//!     }
+//!
+//!     fn builtin(&self, function: &str, name: &str) -> Option<u64> {
+//!         let region = self.0.region(name)?;
+//!         match function {
+//!             "ORIGIN" => Some(region.origin),
+//!             "LENGTH" => Some(region.length),
+//!             _ => None,
+//!         }
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let script = Script::from(lds::parse("
+//!         MEMORY { RAM : ORIGIN = 0x1C00, LENGTH = 0x1000 }
+//!         __stack = ORIGIN(RAM) + LENGTH(RAM);
+//!     ").unwrap());
+//!     let stack = script.statements().find_map(|stmt| match *stmt {
+//!         Statement::Assign { ref expression, .. } => Some(expression),
+//!         _ => None,
+//!     });
+//!     assert_eq!(stack.unwrap().eval(&Regions(&script)), Ok(0x2C00));
+//! }
+//! ```
+
+use alloc::string::{String, ToString};
+use core::cmp::{self, Ordering};
+use core::fmt;
+use expressions::{BinaryOperator, Expression, UnaryOperator};
+#[cfg(feature = "std")]
+use std::error::Error;
+
+/// Values of the symbols and builtins an expression refers to.
+pub trait Env {
+    /// Value of a symbol, `.` is the location counter. Returns `None` if the
+    /// symbol is not defined.
+    fn symbol(&self, name: &str) -> Option<u64>;
+
+    /// Value of a builtin that takes a name instead of an expression, e.g.
+    /// `ORIGIN(RAM)`, `SIZEOF(.text)` or `CONSTANT(MAXPAGESIZE)`. Returns
+    /// `None` if the name is not known.
+    fn builtin(&self, function: &str, name: &str) -> Option<u64> {
+        let _ = (function, name);
+        None
+    }
+}
+
+/// Failure of [`Expression::eval`].
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum EvalError {
+    /// The symbol has no value in the environment
+    UndefinedSymbol(String),
+    /// A builtin such as `ORIGIN(RAM)` has no value in the environment
+    UndefinedBuiltin { function: String, name: String },
+    /// The function is not a linker builtin
+    UnknownFunction(String),
+    /// A builtin is called with the wrong number or kind of arguments
+    InvalidArguments(String),
+    /// The right side of `/` or `%` is zero
+    DivisionByZero,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EvalError::UndefinedSymbol(ref name) => {
+                write!(f, "undefined symbol `{}` referenced in expression", name)
+            }
+            EvalError::UndefinedBuiltin {
+                ref function,
+                ref name,
+            } => write!(f, "`{}` of undefined `{}`", function, name),
+            EvalError::UnknownFunction(ref name) => write!(f, "unknown function `{}`", name),
+            EvalError::InvalidArguments(ref name) => write!(f, "invalid arguments to `{}`", name),
+            EvalError::DivisionByZero => f.write_str("division by zero"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for EvalError {}
+
+/// Functions that [`Expression::eval`] knows
+const BUILTINS: &[&str] = &[
+    "ABSOLUTE",
+    "ADDR",
+    "ALIGN",
+    "ALIGNOF",
+    "BLOCK",
+    "CONSTANT",
+    "DATA_SEGMENT_ALIGN",
+    "DATA_SEGMENT_END",
+    "DATA_SEGMENT_RELRO_END",
+    "DEFINED",
+    "LENGTH",
+    "LOADADDR",
+    "LOG2CEIL",
+    "MAX",
+    "MIN",
+    "NEXT",
+    "ORIGIN",
+    "SEGMENT_START",
+    "SIZEOF",
+];
+
+/// Width and signedness of the addresses of the target.
+///
+/// Every intermediate result is truncated to `width` bits, so `-1` is
+/// `0xFFFF` for a 16-bit target. Like ld, `/` and `%` always treat the
+/// values as two's complement numbers. ld compares addresses as unsigned
+/// numbers, with `signed` set the comparisons, `>>`, `MIN` and `MAX` treat
+/// the values as two's complement numbers as well.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct EvalContext {
+    width: u32,
+    signed: bool,
+}
+
+impl Default for EvalContext {
+    fn default() -> Self {
+        EvalContext {
+            width: 64,
+            signed: false,
+        }
+    }
+}
+
+impl EvalContext {
+    /// Unsigned context for addresses of `width` bits
+    pub fn new(width: u32) -> Self {
+        assert!(width > 0 && width <= 64, "invalid address width {}", width);
+        EvalContext {
+            width,
+            signed: false,
+        }
+    }
+
+    /// Treats the values as two's complement numbers
+    pub fn signed(mut self, signed: bool) -> Self {
+        self.signed = signed;
+        self
+    }
+
+    /// Number of bits in an address, between 1 and 64
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Whether the values are two's complement numbers
+    pub fn is_signed(&self) -> bool {
+        self.signed
+    }
+
+    /// Context for the architecture named in `OUTPUT_ARCH`, e.g. `msp430`,
+    /// `msp430x`, `arm` or `i386:x86-64`. Returns `None` for architectures
+    /// that are not known.
+    pub fn from_arch(arch: &str) -> Option<Self> {
+        let arch = arch.to_ascii_lowercase();
+        let family = arch.split(':').next().unwrap_or("");
+        let width = if family.starts_with("msp430") {
+            if arch.ends_with('x') {
+                20
+            } else {
+                16
+            }
+        } else if family == "aarch64" || arch == "i386:x86-64" || arch == "riscv:rv64" {
+            64
+        } else if family.starts_with("arm") || family == "i386" || arch == "riscv:rv32" {
+            32
+        } else {
+            return None;
+        };
+        Some(EvalContext::new(width))
+    }
+
+    /// Truncates a value to the address width
+    pub fn truncate(&self, value: u64) -> u64 {
+        if self.width >= 64 {
+            value
+        } else {
+            value & ((1 << self.width) - 1)
+        }
+    }
+
+    /// Reads a truncated value as a two's complement number
+    pub fn to_signed(&self, value: u64) -> i64 {
+        let shift = 64 - self.width;
+        ((value << shift) as i64) >> shift
+    }
+
+    fn compare(&self, left: u64, right: u64) -> Ordering {
+        if self.signed {
+            self.to_signed(left).cmp(&self.to_signed(right))
+        } else {
+            left.cmp(&right)
+        }
+    }
+
+    fn divide(&self, left: u64, right: u64, remainder: bool) -> Result<u64, EvalError> {
+        if right == 0 {
+            return Err(EvalError::DivisionByZero);
+        }
+        let (left, right) = (self.to_signed(left), self.to_signed(right));
+        Ok(if remainder {
+            left.wrapping_rem(right) as u64
+        } else {
+            left.wrapping_div(right) as u64
+        })
+    }
+
+    fn shift_right(&self, left: u64, right: u64) -> u64 {
+        match (self.signed, right >= u64::from(self.width)) {
+            (false, true) => 0,
+            (false, false) => left >> right,
+            (true, true) => (self.to_signed(left) >> 63) as u64,
+            (true, false) => (self.to_signed(left) >> right) as u64,
+        }
+    }
+}
+
+/// Whether the first argument of the builtin is the name of a symbol,
+/// section, region or constant rather than an expression
+pub(crate) fn takes_name(function: &str) -> bool {
+    matches!(
+        function,
+        "ADDR"
+            | "ALIGNOF"
+            | "CONSTANT"
+            | "DEFINED"
+            | "LENGTH"
+            | "LOADADDR"
+            | "ORIGIN"
+            | "SEGMENT_START"
+            | "SIZEOF"
+    )
+}
+
+/// Rounds `value` up to a multiple of `align`, like ld's `align_n`
+fn align(value: u64, align: u64) -> u64 {
+    if align <= 1 {
+        return value;
+    }
+    value.wrapping_add(align - 1) / align * align
+}
+
+fn log2ceil(value: u64) -> u64 {
+    if value <= 1 {
+        0
+    } else {
+        u64::from(64 - (value - 1).leading_zeros())
+    }
+}
+
+fn bool_value(value: bool) -> u64 {
+    if value {
+        1
+    } else {
+        0
+    }
+}
+
+fn symbol(env: &dyn Env, name: &str) -> Result<u64, EvalError> {
+    env.symbol(name)
+        .ok_or_else(|| EvalError::UndefinedSymbol(name.to_string()))
+}
+
+/// The name argument of `DEFINED(sym)`, `ORIGIN(RAM)` and the like
+fn name_argument<'e>(function: &str, argument: &'e Expression) -> Result<&'e str, EvalError> {
+    match *argument {
+        Expression::Ident(ref name) => Ok(name),
+        _ => Err(EvalError::InvalidArguments(function.to_string())),
+    }
+}
+
+fn call(
+    function: &str,
+    arguments: &[Expression],
+    env: &dyn Env,
+    ctx: &EvalContext,
+) -> Result<u64, EvalError> {
+    let eval = |expr: &Expression| expr.eval_in(env, ctx);
+    match (function, arguments) {
+        ("ABSOLUTE", [value]) | ("DATA_SEGMENT_END", [value]) => eval(value),
+        ("DATA_SEGMENT_RELRO_END", [_, value]) => eval(value),
+        ("ALIGN", [value]) | ("BLOCK", [value]) | ("NEXT", [value]) => {
+            Ok(align(symbol(env, ".")?, eval(value)?))
+        }
+        ("ALIGN", [value, alignment]) => Ok(align(eval(value)?, eval(alignment)?)),
+        ("DATA_SEGMENT_ALIGN", [max_page, _]) => {
+            let max_page = eval(max_page)?;
+            let dot = symbol(env, ".")?;
+            Ok(align(dot, max_page).wrapping_add(dot & max_page.wrapping_sub(1)))
+        }
+        ("MAX", [a, b]) => Ok(cmp::max_by(eval(a)?, eval(b)?, |a, b| ctx.compare(*a, *b))),
+        ("MIN", [a, b]) => Ok(cmp::min_by(eval(a)?, eval(b)?, |a, b| ctx.compare(*a, *b))),
+        ("LOG2CEIL", [value]) => Ok(log2ceil(eval(value)?)),
+        ("DEFINED", [name]) => Ok(bool_value(
+            env.symbol(name_argument(function, name)?).is_some(),
+        )),
+        ("SEGMENT_START", [name, default]) => {
+            match env.builtin(function, name_argument(function, name)?) {
+                Some(value) => Ok(value),
+                None => eval(default),
+            }
+        }
+        ("ADDR", [name])
+        | ("LOADADDR", [name])
+        | ("SIZEOF", [name])
+        | ("ALIGNOF", [name])
+        | ("ORIGIN", [name])
+        | ("LENGTH", [name])
+        | ("CONSTANT", [name]) => {
+            let name = name_argument(function, name)?;
+            env.builtin(function, name)
+                .ok_or_else(|| EvalError::UndefinedBuiltin {
+                    function: function.to_string(),
+                    name: name.to_string(),
+                })
+        }
+        _ if BUILTINS.contains(&function) => Err(EvalError::InvalidArguments(function.to_string())),
+        _ => Err(EvalError::UnknownFunction(function.to_string())),
+    }
+}
+
+fn binary(
+    left: u64,
+    operator: BinaryOperator,
+    right: u64,
+    ctx: &EvalContext,
+) -> Result<u64, EvalError> {
+    let order = ctx.compare(left, right);
+    Ok(match operator {
+        BinaryOperator::LogicOr => bool_value(left != 0 || right != 0),
+        BinaryOperator::LogicAnd => bool_value(left != 0 && right != 0),
+        BinaryOperator::BitwiseOr => left | right,
+        BinaryOperator::BitwiseAnd => left & right,
+        BinaryOperator::Equals => bool_value(left == right),
+        BinaryOperator::NotEquals => bool_value(left != right),
+        BinaryOperator::Lesser => bool_value(order == Ordering::Less),
+        BinaryOperator::Greater => bool_value(order == Ordering::Greater),
+        BinaryOperator::LesserOrEquals => bool_value(order != Ordering::Greater),
+        BinaryOperator::GreaterOrEquals => bool_value(order != Ordering::Less),
+        BinaryOperator::ShiftRight => ctx.shift_right(left, right),
+        BinaryOperator::ShiftLeft if right >= u64::from(ctx.width) => 0,
+        BinaryOperator::ShiftLeft => left << right,
+        BinaryOperator::Plus => left.wrapping_add(right),
+        BinaryOperator::Minus => left.wrapping_sub(right),
+        BinaryOperator::Multiply => left.wrapping_mul(right),
+        BinaryOperator::Divide => ctx.divide(left, right, false)?,
+        BinaryOperator::Remainder => ctx.divide(left, right, true)?,
+    })
+}
+
+impl<'a> Expression<'a> {
+    /// Computes the value of the expression for a 64-bit target. The right
+    /// side of `&&` and `||` and the untaken branch of `?:` are not
+    /// evaluated, so they may refer to undefined symbols.
+    pub fn eval(&self, env: &dyn Env) -> Result<u64, EvalError> {
+        self.eval_in(env, &EvalContext::default())
+    }
+
+    /// Computes the value of the expression with the address width and
+    /// signedness of `ctx`. The result is truncated to the address width.
+    pub fn eval_in(&self, env: &dyn Env, ctx: &EvalContext) -> Result<u64, EvalError> {
+        let value = match *self {
+            Expression::Ident(ref name) => symbol(env, name)?,
+            Expression::Number(value) => value,
+            Expression::Call {
+                ref function,
+                ref arguments,
+            } => call(function, arguments, env, ctx)?,
+            Expression::UnaryOp {
+                operator,
+                ref right,
+            } => {
+                let right = right.eval_in(env, ctx)?;
+                match operator {
+                    UnaryOperator::LogicNot => bool_value(right == 0),
+                    UnaryOperator::Minus => right.wrapping_neg(),
+                    UnaryOperator::BitwiseNot => !right,
+                }
+            }
+            Expression::BinaryOp {
+                ref left,
+                operator,
+                ref right,
+            } => {
+                let left = left.eval_in(env, ctx)?;
+                match operator {
+                    BinaryOperator::LogicOr if left != 0 => 1,
+                    BinaryOperator::LogicAnd if left == 0 => 0,
+                    _ => binary(left, operator, right.eval_in(env, ctx)?, ctx)?,
+                }
+            }
+            Expression::TernaryOp {
+                ref condition,
+                ref left,
+                ref right,
+            } => {
+                if condition.eval_in(env, ctx)? != 0 {
+                    left.eval_in(env, ctx)?
+                } else {
+                    right.eval_in(env, ctx)?
+                }
+            }
+        };
+        Ok(ctx.truncate(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use eval::*;
+    use expressions::expression;
+
+    struct Msp430;
+
+    impl Env for Msp430 {
+        fn symbol(&self, name: &str) -> Option<u64> {
+            match name {
+                "." => Some(0x1C05),
+                "__data_start" => Some(0x1C00),
+                _ => None,
+            }
+        }
+
+        fn builtin(&self, function: &str, name: &str) -> Option<u64> {
+            match (function, name) {
+                ("ORIGIN", "RAM") => Some(0x1C00),
+                ("LENGTH", "RAM") => Some(0x1000),
+                ("SIZEOF", ".data") => Some(0x12),
+                _ => None,
+            }
+        }
+    }
+
+    fn eval(input: &str) -> Result<u64, EvalError> {
+        let (rest, expr) = expression(input).unwrap();
+        assert_eq!(rest, "");
+        expr.eval(&Msp430)
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval("7 / 2"), Ok(3));
+        assert_eq!(eval("7 % 4"), Ok(3));
+        assert_eq!(eval("-8 / 2"), Ok(-4i64 as u64));
+        assert_eq!(eval("-7 % 2"), Ok(-1i64 as u64));
+        assert_eq!(eval("0x8000000000000000 / 2"), Ok(0xC000000000000000));
+        assert_eq!(eval("0 - 1"), Ok(u64::MAX));
+        assert_eq!(eval("-1"), Ok(u64::MAX));
+        assert_eq!(eval("~0xF0 & 0xFF"), Ok(0x0F));
+        assert_eq!(eval("1 << 4 | 1"), Ok(0x11));
+        assert_eq!(eval("0x100 >> 4"), Ok(0x10));
+        assert_eq!(eval("1 << 64"), Ok(0));
+        assert_eq!(eval("!0"), Ok(1));
+        assert_eq!(eval("!5"), Ok(0));
+        assert_eq!(eval("2 < 3"), Ok(1));
+        assert_eq!(eval("2 >= 3"), Ok(0));
+        assert_eq!(eval("4 == 4"), Ok(1));
+        assert_eq!(eval("4 != 4"), Ok(0));
+        assert_eq!(eval("4 && 2"), Ok(1));
+        assert_eq!(eval("4 || 0"), Ok(1));
+        assert_eq!(eval("0 || 0"), Ok(0));
+        assert_eq!(eval("1 ? 2 : 3"), Ok(2));
+        assert_eq!(eval("0 ? 2 : 3"), Ok(3));
+        assert_eq!(eval("1 / 0"), Err(EvalError::DivisionByZero));
+        assert_eq!(eval("1 % (2 - 2)"), Err(EvalError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_symbols() {
+        assert_eq!(eval("__data_start + 2"), Ok(0x1C02));
+        assert_eq!(eval(". - __data_start"), Ok(5));
+        assert_eq!(
+            eval("_end"),
+            Err(EvalError::UndefinedSymbol("_end".to_string()))
+        );
+        assert_eq!(eval("0 && _end"), Ok(0));
+        assert_eq!(eval("1 || _end"), Ok(1));
+        assert_eq!(eval("DEFINED(_end) ? _end : 0"), Ok(0));
+        assert_eq!(eval("DEFINED(__data_start)"), Ok(1));
+    }
+
+    #[test]
+    fn test_builtins() {
+        assert_eq!(eval("ORIGIN(RAM) + LENGTH(RAM)"), Ok(0x2C00));
+        assert_eq!(eval("ALIGN(2)"), Ok(0x1C06));
+        assert_eq!(eval("ALIGN(0x1C01, 0x100)"), Ok(0x1D00));
+        assert_eq!(eval("ABSOLUTE(SIZEOF(.data))"), Ok(0x12));
+        assert_eq!(eval("MAX(1, 2) + MIN(1, 2)"), Ok(3));
+        assert_eq!(eval("LOG2CEIL(0x11)"), Ok(5));
+        assert_eq!(eval("SEGMENT_START(text, 0x8000)"), Ok(0x8000));
+        assert_eq!(
+            eval("LENGTH(ROM)"),
+            Err(EvalError::UndefinedBuiltin {
+                function: "LENGTH".to_string(),
+                name: "ROM".to_string(),
+            })
+        );
+        assert_eq!(
+            eval("ORIGIN(1)"),
+            Err(EvalError::InvalidArguments("ORIGIN".to_string()))
+        );
+        assert_eq!(
+            eval("MAX(1)"),
+            Err(EvalError::InvalidArguments("MAX".to_string()))
+        );
+        assert_eq!(
+            eval("foo(1)"),
+            Err(EvalError::UnknownFunction("foo".to_string()))
+        );
+    }
+
+    fn eval_in(input: &str, ctx: &EvalContext) -> Result<u64, EvalError> {
+        let (_, expr) = expression(input).unwrap();
+        expr.eval_in(&Msp430, ctx)
+    }
+
+    #[test]
+    fn test_width() {
+        let msp430 = EvalContext::from_arch("msp430").unwrap();
+        assert_eq!(msp430.width(), 16);
+        assert_eq!(eval_in("-0x100 & 0xFFFF", &msp430), Ok(0xFF00));
+        assert_eq!(eval_in("-1", &msp430), Ok(0xFFFF));
+        assert_eq!(eval_in("0xFFFF + 1", &msp430), Ok(0));
+        assert_eq!(eval_in("-0x100 < 0xFFFF", &msp430), Ok(1));
+        assert_eq!(eval("-0x100 < 0xFFFF"), Ok(0));
+        assert_eq!(eval_in("1 << 16", &msp430), Ok(0));
+        assert_eq!(eval_in("ORIGIN(RAM) - 0x2000", &msp430), Ok(0xFC00));
+
+        let msp430x = EvalContext::from_arch("msp430X").unwrap();
+        assert_eq!(eval_in("-1", &msp430x), Ok(0xF_FFFF));
+        assert_eq!(eval_in("0xFFFFF + 2", &msp430x), Ok(1));
+
+        assert_eq!(EvalContext::from_arch("arm"), Some(EvalContext::new(32)));
+        assert_eq!(
+            EvalContext::from_arch("armv7e-m").map(|c| c.width()),
+            Some(32)
+        );
+        assert_eq!(
+            EvalContext::from_arch("i386:x86-64").map(|c| c.width()),
+            Some(64)
+        );
+        assert_eq!(EvalContext::from_arch("vax"), None);
+    }
+
+    #[test]
+    fn test_signed() {
+        let ctx = EvalContext::new(16).signed(true);
+        assert_eq!(eval_in("-0x100 < 0", &ctx), Ok(1));
+        assert_eq!(eval_in("0x8000 > 0x7FFF", &ctx), Ok(0));
+        assert_eq!(eval_in("-8 / 2", &ctx), Ok(0xFFFC));
+        assert_eq!(eval_in("-7 % 2", &ctx), Ok(0xFFFF));
+        assert_eq!(eval_in("-0x100 >> 4", &ctx), Ok(0xFFF0));
+        assert_eq!(eval_in("MAX(-1, 1)", &ctx), Ok(1));
+        assert_eq!(eval_in("MIN(-1, 1)", &ctx), Ok(0xFFFF));
+        assert_eq!(ctx.to_signed(0xFF00), -0x100);
+
+        let unsigned = EvalContext::new(16);
+        assert_eq!(eval_in("-0x100 < 0", &unsigned), Ok(0));
+        assert_eq!(eval_in("-8 / 2", &unsigned), Ok(0xFFFC));
+        assert_eq!(eval_in("-7 % 2", &unsigned), Ok(0xFFFF));
+        assert_eq!(eval_in("-0x100 >> 4", &unsigned), Ok(0x0FF0));
+        assert_eq!(eval_in("MAX(-1, 1)", &unsigned), Ok(0xFFFF));
+    }
+}
@@ -1,23 +1,29 @@
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 use idents::symbol;
 use nom::{
     branch::alt,
     bytes::complete::tag,
     combinator::map,
-    multi::{fold_many0, separated_list0},
-    sequence::{delimited, pair},
-    IResult,
+    multi::separated_list0,
+    sequence::{delimited, pair, preceded},
+    Err, IResult,
 };
 use numbers::number;
+use options::check_depth;
 use whitespace::opt_space;
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum UnaryOperator {
     LogicNot,
     Minus,
     BitwiseNot,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum BinaryOperator {
     LogicOr,
     LogicAnd,
@@ -38,46 +44,115 @@ pub enum BinaryOperator {
     Remainder,
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum Expression {
-    Ident(String),
+impl BinaryOperator {
+    /// Binding power of the operator, higher values bind tighter. The
+    /// expression parser climbs these levels.
+    pub fn precedence(&self) -> u8 {
+        match *self {
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Remainder => 8,
+            BinaryOperator::Plus | BinaryOperator::Minus => 7,
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => 6,
+            BinaryOperator::Equals
+            | BinaryOperator::NotEquals
+            | BinaryOperator::Lesser
+            | BinaryOperator::Greater
+            | BinaryOperator::LesserOrEquals
+            | BinaryOperator::GreaterOrEquals => 5,
+            BinaryOperator::BitwiseAnd => 4,
+            BinaryOperator::BitwiseOr => 3,
+            BinaryOperator::LogicAnd => 2,
+            BinaryOperator::LogicOr => 1,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
+pub enum Expression<'a> {
+    Ident(Cow<'a, str>),
     Number(u64),
     Call {
-        function: String,
-        arguments: Vec<Expression>,
+        function: Cow<'a, str>,
+        arguments: Vec<Expression<'a>>,
     },
     UnaryOp {
         operator: UnaryOperator,
-        right: Box<Expression>,
+        right: Box<Expression<'a>>,
     },
     BinaryOp {
-        left: Box<Expression>,
+        left: Box<Expression<'a>>,
         operator: BinaryOperator,
-        right: Box<Expression>,
+        right: Box<Expression<'a>>,
     },
     TernaryOp {
-        condition: Box<Expression>,
-        left: Box<Expression>,
-        right: Box<Expression>,
+        condition: Box<Expression<'a>>,
+        left: Box<Expression<'a>>,
+        right: Box<Expression<'a>>,
     },
 }
 
-fn value_ident(input: &str) -> IResult<&str, Expression> {
-    map(symbol, |x: &str| Expression::Ident(x.into()))(input)
+impl<'a> From<u64> for Expression<'a> {
+    fn from(value: u64) -> Self {
+        Expression::Number(value)
+    }
 }
 
-fn value_number(input: &str) -> IResult<&str, Expression> {
-    map(number, |x| Expression::Number(x))(input)
+impl<'a> Expression<'a> {
+    /// Converts the expression into one that owns all of its strings
+    pub fn into_owned(self) -> Expression<'static> {
+        match self {
+            Expression::Ident(name) => Expression::Ident(Cow::Owned(name.into_owned())),
+            Expression::Number(n) => Expression::Number(n),
+            Expression::Call {
+                function,
+                arguments,
+            } => Expression::Call {
+                function: Cow::Owned(function.into_owned()),
+                arguments: arguments.into_iter().map(Expression::into_owned).collect(),
+            },
+            Expression::UnaryOp { operator, right } => Expression::UnaryOp {
+                operator,
+                right: Box::new(right.into_owned()),
+            },
+            Expression::BinaryOp {
+                left,
+                operator,
+                right,
+            } => Expression::BinaryOp {
+                left: Box::new(left.into_owned()),
+                operator,
+                right: Box::new(right.into_owned()),
+            },
+            Expression::TernaryOp {
+                condition,
+                left,
+                right,
+            } => Expression::TernaryOp {
+                condition: Box::new(condition.into_owned()),
+                left: Box::new(left.into_owned()),
+                right: Box::new(right.into_owned()),
+            },
+        }
+    }
 }
 
-fn value_nested(input: &str) -> IResult<&str, Expression> {
-    delimited(tag("("), wsc!(expression), tag(")"))(input)
+fn value_ident(input: &str) -> IResult<&str, Expression<'_>> {
+    map(symbol, |x| Expression::Ident(x.into()))(input)
 }
 
-fn value_call(input: &str) -> IResult<&str, Expression> {
+fn value_number(input: &str) -> IResult<&str, Expression<'_>> {
+    map(number, Expression::Number)(input)
+}
+
+fn value_nested(input: &str, depth: usize) -> IResult<&str, Expression<'_>> {
+    delimited(tag("("), wsc!(|i| expr(i, depth + 1)), tag(")"))(input)
+}
+
+fn value_call(input: &str, depth: usize) -> IResult<&str, Expression<'_>> {
     let (input, func) = symbol(input)?;
     let (input, _) = wsc!(tag("("))(input)?;
-    let (input, args) = separated_list0(wsc!(tag(",")), expression)(input)?;
+    let (input, args) = separated_list0(wsc!(tag(",")), |i| expr(i, depth + 1))(input)?;
     let (input, _) = pair(opt_space, tag(")"))(input)?;
     Ok((
         input,
@@ -88,193 +163,125 @@ fn value_call(input: &str) -> IResult<&str, Expression> {
     ))
 }
 
-pub fn value(input: &str) -> IResult<&str, Expression> {
-    alt((value_nested, value_call, value_number, value_ident))(input)
+fn value(input: &str, depth: usize) -> IResult<&str, Expression<'_>> {
+    alt((
+        |i| value_nested(i, depth),
+        |i| value_call(i, depth),
+        value_number,
+        value_ident,
+    ))(input)
 }
 
-fn expr_unary_op(input: &str) -> IResult<&str, Expression> {
-    let (input, op) = alt((tag("-"), tag("!"), tag("~")))(input)?;
-    let (input, _) = opt_space(input)?;
-    let (input, right) = expr_level_1(input)?;
-    Ok((
-        input,
-        Expression::UnaryOp {
-            operator: match op {
-                "-" => UnaryOperator::Minus,
-                "!" => UnaryOperator::LogicNot,
-                "~" => UnaryOperator::BitwiseNot,
-                _ => panic!("Invalid operator"),
-            },
-            right: Box::new(right),
-        },
-    ))
+fn unary_operator(input: &str) -> IResult<&str, UnaryOperator> {
+    alt((
+        map(tag("-"), |_| UnaryOperator::Minus),
+        map(tag("!"), |_| UnaryOperator::LogicNot),
+        map(tag("~"), |_| UnaryOperator::BitwiseNot),
+    ))(input)
 }
 
-fn expr_level_1(input: &str) -> IResult<&str, Expression> {
-    alt((expr_unary_op, value))(input)
+fn expr_unary(input: &str, depth: usize) -> IResult<&str, Expression<'_>> {
+    match unary_operator(input) {
+        Ok((input, operator)) => {
+            check_depth(input, depth + 1)?;
+            let (input, _) = opt_space(input)?;
+            let (input, right) = expr_unary(input, depth + 1)?;
+            Ok((
+                input,
+                Expression::UnaryOp {
+                    operator,
+                    right: Box::new(right),
+                },
+            ))
+        }
+        Err(Err::Error(_)) => value(input, depth),
+        Err(e) => Err(e),
+    }
 }
 
-fn expr_level_2(input: &str) -> IResult<&str, Expression> {
-    let (input, first) = expr_level_1(input)?;
-    let (input, fold) = fold_many0(
-        pair(wsc!(alt((tag("*"), tag("/"), tag("%")))), expr_level_1),
-        || first.clone(),
-        |prev, new: (&str, Expression)| Expression::BinaryOp {
-            left: Box::new(prev),
-            operator: match new.0 {
-                "*" => BinaryOperator::Multiply,
-                "/" => BinaryOperator::Divide,
-                "%" => BinaryOperator::Remainder,
-                _ => panic!("Invalid operator"),
-            },
-            right: Box::new(new.1),
-        },
-    )(input)?;
-    Ok((input, fold))
+/// Operators that are prefixes of other ones come after them
+fn binary_operator(input: &str) -> IResult<&str, BinaryOperator> {
+    alt((
+        map(tag("||"), |_| BinaryOperator::LogicOr),
+        map(tag("&&"), |_| BinaryOperator::LogicAnd),
+        map(tag("=="), |_| BinaryOperator::Equals),
+        map(tag("!="), |_| BinaryOperator::NotEquals),
+        map(tag("<="), |_| BinaryOperator::LesserOrEquals),
+        map(tag(">="), |_| BinaryOperator::GreaterOrEquals),
+        map(tag("<<"), |_| BinaryOperator::ShiftLeft),
+        map(tag(">>"), |_| BinaryOperator::ShiftRight),
+        map(tag("|"), |_| BinaryOperator::BitwiseOr),
+        map(tag("&"), |_| BinaryOperator::BitwiseAnd),
+        map(tag("<"), |_| BinaryOperator::Lesser),
+        map(tag(">"), |_| BinaryOperator::Greater),
+        map(tag("+"), |_| BinaryOperator::Plus),
+        map(tag("-"), |_| BinaryOperator::Minus),
+        map(tag("*"), |_| BinaryOperator::Multiply),
+        map(tag("/"), |_| BinaryOperator::Divide),
+        map(tag("%"), |_| BinaryOperator::Remainder),
+    ))(input)
 }
 
-fn expr_level_3(input: &str) -> IResult<&str, Expression> {
-    let (input, first) = expr_level_2(input)?;
-    let (input, fold) = fold_many0(
-        pair(wsc!(alt((tag("+"), tag("-")))), expr_level_2),
-        || first.clone(),
-        |prev, new: (&str, Expression)| Expression::BinaryOp {
-            left: Box::new(prev),
-            operator: match new.0 {
-                "+" => BinaryOperator::Plus,
-                "-" => BinaryOperator::Minus,
-                _ => panic!("Invalid operator"),
-            },
-            right: Box::new(new.1),
-        },
-    )(input)?;
-    Ok((input, fold))
+/// Precedence climbing over the binary operators. Parses operators that
+/// bind at least as tight as `min_precedence`, all of them are left
+/// associative. An operator without a valid right operand is left in the
+/// input.
+fn expr_binary(input: &str, min_precedence: u8, depth: usize) -> IResult<&str, Expression<'_>> {
+    let (mut input, mut left) = expr_unary(input, depth)?;
+    loop {
+        let (rest, operator) = match preceded(opt_space, binary_operator)(input) {
+            Ok(result) => result,
+            Err(Err::Error(_)) => break,
+            Err(e) => return Err(e),
+        };
+        let precedence = operator.precedence();
+        if precedence < min_precedence {
+            break;
+        }
+        let (rest, _) = opt_space(rest)?;
+        let (rest, right) = match expr_binary(rest, precedence + 1, depth) {
+            Ok(result) => result,
+            Err(Err::Error(_)) => break,
+            Err(e) => return Err(e),
+        };
+        left = Expression::BinaryOp {
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+        };
+        input = rest;
+    }
+    Ok((input, left))
 }
 
-fn expr_level_4(input: &str) -> IResult<&str, Expression> {
-    let (input, first) = expr_level_3(input)?;
-    let (input, fold) = fold_many0(
-        pair(wsc!(alt((tag("<<"), tag(">>")))), expr_level_3),
-        || first.clone(),
-        |prev, new: (&str, Expression)| Expression::BinaryOp {
-            left: Box::new(prev),
-            operator: match new.0 {
-                "<<" => BinaryOperator::ShiftLeft,
-                ">>" => BinaryOperator::ShiftRight,
-                _ => panic!("Invalid operator"),
-            },
-            right: Box::new(new.1),
-        },
-    )(input)?;
-    Ok((input, fold))
+fn ternary_branches(input: &str, depth: usize) -> IResult<&str, (Expression<'_>, Expression<'_>)> {
+    let (input, _) = wsc!(tag("?"))(input)?;
+    let (input, left) = expr(input, depth + 1)?;
+    let (input, _) = wsc!(tag(":"))(input)?;
+    let (input, right) = expr(input, depth + 1)?;
+    Ok((input, (left, right)))
 }
 
-fn expr_level_5(input: &str) -> IResult<&str, Expression> {
-    let (input, first) = expr_level_4(input)?;
-    let (input, fold) = fold_many0(
-        pair(
-            wsc!(alt((
-                tag("=="),
-                tag("!="),
-                tag("<="),
-                tag(">="),
-                tag("<"),
-                tag(">")
-            ))),
-            expr_level_4,
-        ),
-        || first.clone(),
-        |prev, new: (&str, Expression)| Expression::BinaryOp {
-            left: Box::new(prev),
-            operator: match new.0 {
-                "==" => BinaryOperator::Equals,
-                "!=" => BinaryOperator::NotEquals,
-                "<=" => BinaryOperator::LesserOrEquals,
-                ">=" => BinaryOperator::GreaterOrEquals,
-                "<" => BinaryOperator::Lesser,
-                ">" => BinaryOperator::Greater,
-                _ => panic!("Invalid operator"),
+/// Parses an expression nested `depth` levels deep into another one
+fn expr(input: &str, depth: usize) -> IResult<&str, Expression<'_>> {
+    check_depth(input, depth)?;
+    let (input, condition) = expr_binary(input, 1, depth)?;
+    match ternary_branches(input, depth) {
+        Ok((rest, (left, right))) => Ok((
+            rest,
+            Expression::TernaryOp {
+                condition: Box::new(condition),
+                left: Box::new(left),
+                right: Box::new(right),
             },
-            right: Box::new(new.1),
-        },
-    )(input)?;
-    Ok((input, fold))
-}
-
-fn expr_level_6(input: &str) -> IResult<&str, Expression> {
-    let (input, first) = expr_level_5(input)?;
-    let (input, fold) = fold_many0(
-        pair(wsc!(tag("&")), expr_level_5),
-        || first.clone(),
-        |prev, new: (&str, Expression)| Expression::BinaryOp {
-            left: Box::new(prev),
-            operator: BinaryOperator::BitwiseAnd,
-            right: Box::new(new.1),
-        },
-    )(input)?;
-    Ok((input, fold))
-}
-
-fn expr_level_7(input: &str) -> IResult<&str, Expression> {
-    let (input, first) = expr_level_6(input)?;
-    let (input, fold) = fold_many0(
-        pair(wsc!(tag("|")), expr_level_6),
-        || first.clone(),
-        |prev, new: (&str, Expression)| Expression::BinaryOp {
-            left: Box::new(prev),
-            operator: BinaryOperator::BitwiseOr,
-            right: Box::new(new.1),
-        },
-    )(input)?;
-    Ok((input, fold))
-}
-
-fn expr_level_8(input: &str) -> IResult<&str, Expression> {
-    let (input, first) = expr_level_7(input)?;
-    let (input, fold) = fold_many0(
-        pair(wsc!(tag("&&")), expr_level_7),
-        || first.clone(),
-        |prev, new: (&str, Expression)| Expression::BinaryOp {
-            left: Box::new(prev),
-            operator: BinaryOperator::LogicAnd,
-            right: Box::new(new.1),
-        },
-    )(input)?;
-    Ok((input, fold))
-}
-
-fn expr_level_9(input: &str) -> IResult<&str, Expression> {
-    let (input, first) = expr_level_8(input)?;
-    let (input, fold) = fold_many0(
-        pair(wsc!(tag("||")), expr_level_8),
-        || first.clone(),
-        |prev, new: (&str, Expression)| Expression::BinaryOp {
-            left: Box::new(prev),
-            operator: BinaryOperator::LogicOr,
-            right: Box::new(new.1),
-        },
-    )(input)?;
-    Ok((input, fold))
-}
-
-fn expr_ternary_op(input: &str) -> IResult<&str, Expression> {
-    let (input, cond) = expr_level_9(input)?;
-    let (input, _) = wsc!(tag("?"))(input)?;
-    let (input, left) = expression(input)?;
-    let (input, _) = wsc!(tag(":"))(input)?;
-    let (input, right) = expression(input)?;
-    Ok((
-        input,
-        Expression::TernaryOp {
-            condition: Box::new(cond),
-            left: Box::new(left),
-            right: Box::new(right),
-        },
-    ))
+        )),
+        Err(Err::Error(_)) => Ok((input, condition)),
+        Err(e) => Err(e),
+    }
 }
 
-pub fn expression(input: &str) -> IResult<&str, Expression> {
-    alt((expr_ternary_op, expr_level_9))(input)
+pub fn expression(input: &str) -> IResult<&str, Expression<'_>> {
+    expr(input, 0)
 }
 
 #[cfg(test)]
@@ -304,4 +311,99 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_precedence() {
+        let ident = |name: &'static str| Box::new(Expression::Ident(name.into()));
+        let binary = |left, operator, right| Expression::BinaryOp {
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+        };
+        assert_done!(
+            expression("a - b - c"),
+            binary(
+                binary(*ident("a"), BinaryOperator::Minus, *ident("b")),
+                BinaryOperator::Minus,
+                *ident("c")
+            )
+        );
+        assert_done!(
+            expression("a || b && c | d & e == f << g + h * i"),
+            binary(
+                *ident("a"),
+                BinaryOperator::LogicOr,
+                binary(
+                    *ident("b"),
+                    BinaryOperator::LogicAnd,
+                    binary(
+                        *ident("c"),
+                        BinaryOperator::BitwiseOr,
+                        binary(
+                            *ident("d"),
+                            BinaryOperator::BitwiseAnd,
+                            binary(
+                                *ident("e"),
+                                BinaryOperator::Equals,
+                                binary(
+                                    *ident("f"),
+                                    BinaryOperator::ShiftLeft,
+                                    binary(
+                                        *ident("g"),
+                                        BinaryOperator::Plus,
+                                        binary(*ident("h"), BinaryOperator::Multiply, *ident("i"))
+                                    )
+                                )
+                            )
+                        )
+                    )
+                )
+            )
+        );
+        assert_done!(
+            expression("-a * ~b"),
+            binary(
+                Expression::UnaryOp {
+                    operator: UnaryOperator::Minus,
+                    right: ident("a"),
+                },
+                BinaryOperator::Multiply,
+                Expression::UnaryOp {
+                    operator: UnaryOperator::BitwiseNot,
+                    right: ident("b"),
+                }
+            )
+        );
+        assert_done!(
+            expression("a ? b : c ? d : e"),
+            Expression::TernaryOp {
+                condition: ident("a"),
+                left: ident("b"),
+                right: Box::new(Expression::TernaryOp {
+                    condition: ident("c"),
+                    left: ident("d"),
+                    right: ident("e"),
+                }),
+            }
+        );
+        // operators without a right operand are left in the input
+        assert_eq!(expression("a + b *").unwrap().0, " *");
+        assert_eq!(expression("a & &b").unwrap().0, " & &b");
+        assert_eq!(expression("a ? b").unwrap().0, " ? b");
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        let depth = 100;
+        let align = format!("{}.{}", "ALIGN(".repeat(depth), ", 4)".repeat(depth));
+        assert_done!(expression(&align));
+        let nested = format!("{}1{}", "(".repeat(depth), " + 1)".repeat(depth));
+        assert_done!(expression(&nested));
+
+        let depth = 100_000;
+        assert_fail!(expression(&"(".repeat(depth)));
+        assert_fail!(expression(&"-~!".repeat(depth)));
+        assert_fail!(expression(&"a ? ".repeat(depth)));
+        assert_fail!(expression(&"ALIGN(".repeat(depth)));
+    }
 }
@@ -0,0 +1,428 @@
+//! Syntax tree traversal that transforms owned AST nodes.
+//!
+//! Each method of the [`Fold`] trait is a hook that can be overridden to
+//! replace the corresponding type of node. By default, every method rebuilds
+//! the node from its folded children with the free function of the same name.
+//!
+//! ```
+//! extern crate ldscript_parser as lds;
+//!
+//! use lds::fold::{self, Fold};
+//! use lds::Expression;
+//!
+//! struct ResolveRam;
+//!
+//! impl<'a> Fold<'a> for ResolveRam {
+//!     fn fold_expression(&mut self, node: Expression<'a>) -> Expression<'a> {
+//!         match node {
+//!             Expression::Call { ref function, .. } if function == "ORIGIN" => {
+//!                 Expression::Number(0x2400)
+//!             }
+//!             other => fold::fold_expression(self, other),
+//!         }
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let items = lds::parse("__stack = ORIGIN(RAM) + 0x2000;").unwrap();
+//!     let items: Vec<_> = items
+//!         .into_iter()
+//!         .map(|item| ResolveRam.fold_root_item(item))
+//!         .collect();
+//!     assert_eq!(items, lds::parse("__stack = 0x2400 + 0x2000;").unwrap());
+//! }
+//! ```
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use commands::{Command, InsertOrder};
+use expressions::{BinaryOperator, Expression, UnaryOperator};
+use memory::Region;
+use script::RootItem;
+use sections::{
+    DataType, OutputSectionCommand, OutputSectionConstraint, OutputSectionType, SectionCommand,
+    SectionPattern,
+};
+use statements::{AssignOperator, Statement};
+use whitespace::{Comment, CommentPosition};
+
+/// Traversal to transform the nodes of an owned syntax tree.
+pub trait Fold<'a> {
+    fn fold_root_item(&mut self, node: RootItem<'a>) -> RootItem<'a> {
+        fold_root_item(self, node)
+    }
+    fn fold_statement(&mut self, node: Statement<'a>) -> Statement<'a> {
+        fold_statement(self, node)
+    }
+    fn fold_command(&mut self, node: Command<'a>) -> Command<'a> {
+        fold_command(self, node)
+    }
+    fn fold_region(&mut self, node: Region<'a>) -> Region<'a> {
+        fold_region(self, node)
+    }
+    fn fold_section_command(&mut self, node: SectionCommand<'a>) -> SectionCommand<'a> {
+        fold_section_command(self, node)
+    }
+    fn fold_output_section_command(
+        &mut self,
+        node: OutputSectionCommand<'a>,
+    ) -> OutputSectionCommand<'a> {
+        fold_output_section_command(self, node)
+    }
+    fn fold_section_pattern(&mut self, node: SectionPattern<'a>) -> SectionPattern<'a> {
+        fold_section_pattern(self, node)
+    }
+    fn fold_expression(&mut self, node: Expression<'a>) -> Expression<'a> {
+        fold_expression(self, node)
+    }
+    fn fold_comment(&mut self, node: Comment<'a>) -> Comment<'a> {
+        fold_comment(self, node)
+    }
+    fn fold_comment_position(&mut self, node: CommentPosition) -> CommentPosition {
+        node
+    }
+    fn fold_insert_order(&mut self, node: InsertOrder) -> InsertOrder {
+        node
+    }
+    fn fold_assign_operator(&mut self, node: AssignOperator) -> AssignOperator {
+        node
+    }
+    fn fold_unary_operator(&mut self, node: UnaryOperator) -> UnaryOperator {
+        node
+    }
+    fn fold_binary_operator(&mut self, node: BinaryOperator) -> BinaryOperator {
+        node
+    }
+    fn fold_data_type(&mut self, node: DataType) -> DataType {
+        node
+    }
+    fn fold_output_section_type(&mut self, node: OutputSectionType) -> OutputSectionType {
+        node
+    }
+    fn fold_output_section_constraint(
+        &mut self,
+        node: OutputSectionConstraint,
+    ) -> OutputSectionConstraint {
+        node
+    }
+}
+
+fn fold_box<'a, F>(f: &mut F, mut node: Box<Expression<'a>>) -> Box<Expression<'a>>
+where
+    F: Fold<'a> + ?Sized,
+{
+    *node = f.fold_expression(*node);
+    node
+}
+
+fn fold_comments<'a, F>(f: &mut F, comments: Vec<Comment<'a>>) -> Vec<Comment<'a>>
+where
+    F: Fold<'a> + ?Sized,
+{
+    comments.into_iter().map(|c| f.fold_comment(c)).collect()
+}
+
+pub fn fold_root_item<'a, F>(f: &mut F, node: RootItem<'a>) -> RootItem<'a>
+where
+    F: Fold<'a> + ?Sized,
+{
+    match node {
+        RootItem::Statement(stmt) => RootItem::Statement(f.fold_statement(stmt)),
+        RootItem::Command(cmd) => RootItem::Command(f.fold_command(cmd)),
+        RootItem::Memory { regions, comments } => RootItem::Memory {
+            regions: regions.into_iter().map(|r| f.fold_region(r)).collect(),
+            comments: fold_comments(f, comments),
+        },
+        RootItem::Sections { list, comments } => RootItem::Sections {
+            list: list
+                .into_iter()
+                .map(|c| f.fold_section_command(c))
+                .collect(),
+            comments: fold_comments(f, comments),
+        },
+    }
+}
+
+pub fn fold_statement<'a, F>(f: &mut F, node: Statement<'a>) -> Statement<'a>
+where
+    F: Fold<'a> + ?Sized,
+{
+    match node {
+        Statement::Assign {
+            name,
+            operator,
+            expression,
+            comments,
+        } => Statement::Assign {
+            name,
+            operator: f.fold_assign_operator(operator),
+            expression: fold_box(f, expression),
+            comments: fold_comments(f, comments),
+        },
+        Statement::Hidden {
+            name,
+            expression,
+            comments,
+        } => Statement::Hidden {
+            name,
+            expression: fold_box(f, expression),
+            comments: fold_comments(f, comments),
+        },
+        Statement::Provide {
+            name,
+            expression,
+            comments,
+        } => Statement::Provide {
+            name,
+            expression: fold_box(f, expression),
+            comments: fold_comments(f, comments),
+        },
+        Statement::ProvideHidden {
+            name,
+            expression,
+            comments,
+        } => Statement::ProvideHidden {
+            name,
+            expression: fold_box(f, expression),
+            comments: fold_comments(f, comments),
+        },
+        Statement::Assert {
+            expr,
+            text,
+            comments,
+        } => Statement::Assert {
+            expr: fold_box(f, expr),
+            text,
+            comments: fold_comments(f, comments),
+        },
+    }
+}
+
+pub fn fold_command<'a, F>(f: &mut F, node: Command<'a>) -> Command<'a>
+where
+    F: Fold<'a> + ?Sized,
+{
+    match node {
+        Command::Call {
+            name,
+            arguments,
+            comments,
+        } => Command::Call {
+            name,
+            arguments: arguments
+                .into_iter()
+                .map(|e| f.fold_expression(e))
+                .collect(),
+            comments: fold_comments(f, comments),
+        },
+        Command::Include { file, comments } => Command::Include {
+            file,
+            comments: fold_comments(f, comments),
+        },
+        Command::Insert {
+            order,
+            section,
+            comments,
+        } => Command::Insert {
+            order: f.fold_insert_order(order),
+            section,
+            comments: fold_comments(f, comments),
+        },
+    }
+}
+
+pub fn fold_region<'a, F>(f: &mut F, node: Region<'a>) -> Region<'a>
+where
+    F: Fold<'a> + ?Sized,
+{
+    Region {
+        comments: fold_comments(f, node.comments),
+        ..node
+    }
+}
+
+pub fn fold_section_command<'a, F>(f: &mut F, node: SectionCommand<'a>) -> SectionCommand<'a>
+where
+    F: Fold<'a> + ?Sized,
+{
+    match node {
+        SectionCommand::Statement(stmt) => SectionCommand::Statement(f.fold_statement(stmt)),
+        SectionCommand::Command(cmd) => SectionCommand::Command(f.fold_command(cmd)),
+        SectionCommand::OutputSection {
+            name,
+            vma_address,
+            s_type,
+            lma_address,
+            section_align,
+            align_with_input,
+            subsection_align,
+            constraint,
+            content,
+            region,
+            lma_region,
+            fillexp,
+            comments,
+        } => SectionCommand::OutputSection {
+            name,
+            vma_address: vma_address.map(|e| fold_box(f, e)),
+            s_type: s_type.map(|t| f.fold_output_section_type(t)),
+            lma_address: lma_address.map(|e| fold_box(f, e)),
+            section_align: section_align.map(|e| fold_box(f, e)),
+            align_with_input,
+            subsection_align: subsection_align.map(|e| fold_box(f, e)),
+            constraint: constraint.map(|c| f.fold_output_section_constraint(c)),
+            content: content
+                .into_iter()
+                .map(|c| f.fold_output_section_command(c))
+                .collect(),
+            region,
+            lma_region,
+            fillexp: fillexp.map(|e| fold_box(f, e)),
+            comments: fold_comments(f, comments),
+        },
+    }
+}
+
+pub fn fold_output_section_command<'a, F>(
+    f: &mut F,
+    node: OutputSectionCommand<'a>,
+) -> OutputSectionCommand<'a>
+where
+    F: Fold<'a> + ?Sized,
+{
+    match node {
+        OutputSectionCommand::Statement(stmt) => {
+            OutputSectionCommand::Statement(f.fold_statement(stmt))
+        }
+        OutputSectionCommand::Fill { expr, comments } => OutputSectionCommand::Fill {
+            expr: fold_box(f, expr),
+            comments: fold_comments(f, comments),
+        },
+        OutputSectionCommand::Data {
+            d_type,
+            value,
+            comments,
+        } => OutputSectionCommand::Data {
+            d_type: f.fold_data_type(d_type),
+            value: fold_box(f, value),
+            comments: fold_comments(f, comments),
+        },
+        OutputSectionCommand::InputSection {
+            file,
+            sections,
+            comments,
+        } => OutputSectionCommand::InputSection {
+            file: f.fold_section_pattern(file),
+            sections: sections
+                .into_iter()
+                .map(|p| f.fold_section_pattern(p))
+                .collect(),
+            comments: fold_comments(f, comments),
+        },
+        OutputSectionCommand::KeepInputSection {
+            file,
+            sections,
+            comments,
+        } => OutputSectionCommand::KeepInputSection {
+            file: f.fold_section_pattern(file),
+            sections: sections
+                .into_iter()
+                .map(|p| f.fold_section_pattern(p))
+                .collect(),
+            comments: fold_comments(f, comments),
+        },
+    }
+}
+
+pub fn fold_section_pattern<'a, F>(f: &mut F, node: SectionPattern<'a>) -> SectionPattern<'a>
+where
+    F: Fold<'a> + ?Sized,
+{
+    match node {
+        SectionPattern::ExcludeFile { files, pattern } => SectionPattern::ExcludeFile {
+            files,
+            pattern: Box::new(f.fold_section_pattern(*pattern)),
+        },
+        other => other,
+    }
+}
+
+pub fn fold_expression<'a, F>(f: &mut F, node: Expression<'a>) -> Expression<'a>
+where
+    F: Fold<'a> + ?Sized,
+{
+    match node {
+        Expression::Call {
+            function,
+            arguments,
+        } => Expression::Call {
+            function,
+            arguments: arguments
+                .into_iter()
+                .map(|e| f.fold_expression(e))
+                .collect(),
+        },
+        Expression::UnaryOp { operator, right } => Expression::UnaryOp {
+            operator: f.fold_unary_operator(operator),
+            right: fold_box(f, right),
+        },
+        Expression::BinaryOp {
+            left,
+            operator,
+            right,
+        } => Expression::BinaryOp {
+            left: fold_box(f, left),
+            operator: f.fold_binary_operator(operator),
+            right: fold_box(f, right),
+        },
+        Expression::TernaryOp {
+            condition,
+            left,
+            right,
+        } => Expression::TernaryOp {
+            condition: fold_box(f, condition),
+            left: fold_box(f, left),
+            right: fold_box(f, right),
+        },
+        other => other,
+    }
+}
+
+pub fn fold_comment<'a, F>(f: &mut F, node: Comment<'a>) -> Comment<'a>
+where
+    F: Fold<'a> + ?Sized,
+{
+    Comment {
+        position: f.fold_comment_position(node.position),
+        ..node
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fold::*;
+    use script::parse;
+
+    struct Negate;
+
+    impl<'a> Fold<'a> for Negate {
+        fn fold_binary_operator(&mut self, node: BinaryOperator) -> BinaryOperator {
+            match node {
+                BinaryOperator::Plus => BinaryOperator::Minus,
+                BinaryOperator::Minus => BinaryOperator::Plus,
+                other => other,
+            }
+        }
+    }
+
+    #[test]
+    fn test_fold() {
+        let (_, items) = parse("SECTIONS { .a : { . = . + 1; } > RAM }").unwrap();
+        let (_, expected) = parse("SECTIONS { .a : { . = . - 1; } > RAM }").unwrap();
+        let items: Vec<_> = items
+            .into_iter()
+            .map(|item| Negate.fold_root_item(item))
+            .collect();
+        assert_eq!(items, expected);
+    }
+}
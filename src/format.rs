@@ -0,0 +1,526 @@
+//! Opinionated formatting of linker scripts.
+//!
+//! Unlike the plain output of the [`print`](../print/index.html) module, the
+//! formatter lines up the columns of MEMORY regions, separates blocks with
+//! blank lines and wraps long input section lists. The layout is controlled
+//! by [`FormatOptions`]; comments stay attached to the nodes they belong to.
+//! When the text is formatted with [`format_str`], numbers keep their
+//! spelling and blank lines between nodes are kept.
+//!
+//! ```
+//! extern crate ldscript_parser as lds;
+//!
+//! use lds::format::{check, format_str, FormatOptions};
+//!
+//! fn main() {
+//!     let input = "MEMORY { ROM (rx) : ORIGIN = 0, LENGTH = 4K RAM : ORIGIN = 0x20000000, LENGTH = 0x0400 }";
+//!     let options = FormatOptions::default();
+//!     let text = format_str(input, &options).unwrap();
+//!     assert_eq!(
+//!         text,
+//!         "MEMORY\n\
+//!          {\n    \
+//!              ROM (rx) : ORIGIN = 0,          LENGTH = 4K\n    \
+//!              RAM      : ORIGIN = 0x20000000, LENGTH = 0x0400\n\
+//!          }\n"
+//!     );
+//!     assert_eq!(check(&text, &options), Ok(true));
+//! }
+//! ```
+
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeMap as Map;
+use alloc::collections::VecDeque;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::ops::Range;
+use incremental::parse_list;
+use memory::region;
+use numbers::number;
+use print::Printer;
+use script::{root_item, RootItem};
+use sections::{output_section_command, section_command, SectionCommand};
+#[cfg(feature = "std")]
+use std::collections::HashMap as Map;
+use whitespace::{comment, leading_comments, opt_space};
+
+/// Placement of the opening brace of MEMORY, SECTIONS and output section
+/// blocks.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum BraceStyle {
+    /// The brace goes on a line of its own, as in the default GNU ld scripts
+    NextLine,
+    /// The brace ends the line that opens the block
+    SameLine,
+}
+
+/// Layout settings of the formatter.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct FormatOptions {
+    /// Number of columns of one indentation level
+    pub indent_width: usize,
+    /// Indent with one tab per level instead of spaces
+    pub hard_tabs: bool,
+    pub brace_style: BraceStyle,
+    /// Input section lists longer than this are wrapped, `0` disables
+    /// wrapping
+    pub max_width: usize,
+    /// Line up the colon, `ORIGIN` and `LENGTH` of the MEMORY regions
+    pub align_memory: bool,
+    /// Put an empty line around MEMORY and SECTIONS blocks, and around
+    /// output sections with a body
+    pub blank_lines: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            indent_width: 4,
+            hard_tabs: false,
+            brace_style: BraceStyle::NextLine,
+            max_width: 100,
+            align_memory: true,
+            blank_lines: true,
+        }
+    }
+}
+
+/// What the printer keeps of the text a tree was parsed from: the spelling
+/// of the numbers and the blank lines between nodes.
+pub(crate) struct Source {
+    /// Spellings of each value in source order, the printer takes them one
+    /// after another
+    literals: RefCell<Map<u64, VecDeque<String>>>,
+    /// Whether a blank line comes before each leading comment and each node
+    /// with comments, in source order
+    blank_lines: Vec<bool>,
+}
+
+impl Source {
+    /// Collects what the printer needs of `text`, given the root items
+    /// parsed from it and their ranges
+    pub(crate) fn new(text: &str, items: &[RootItem], ranges: &[Range<usize>]) -> Self {
+        Source {
+            literals: RefCell::new(literals(text)),
+            blank_lines: blank_lines(text, items, ranges).unwrap_or_default(),
+        }
+    }
+
+    /// Returns the next spelling of `value` in the source
+    pub(crate) fn literal(&self, value: u64) -> Option<String> {
+        self.literals.borrow_mut().get_mut(&value)?.pop_front()
+    }
+
+    /// Whether a blank line comes before the leading comment or node at
+    /// `index`
+    pub(crate) fn blank_line(&self, index: usize) -> bool {
+        self.blank_lines.get(index).copied().unwrap_or(false)
+    }
+}
+
+/// Spellings of the numbers of a text, outside of comments and strings
+fn literals(text: &str) -> Map<u64, VecDeque<String>> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '.' || c == '$';
+    let mut literals: Map<u64, VecDeque<String>> = Map::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if let Ok((next, _)) = comment(rest) {
+            rest = next;
+            continue;
+        }
+        let end = if c == '"' {
+            rest[1..].find('"').map_or(rest.len(), |end| end + 2)
+        } else {
+            let end = rest.find(|c| !is_word(c)).unwrap_or(rest.len());
+            end.max(c.len_utf8())
+        };
+        let word = &rest[..end];
+        if c.is_ascii_digit() {
+            if let Ok(("", value)) = number(word) {
+                literals
+                    .entry(value)
+                    .or_default()
+                    .push_back(word.to_string());
+            }
+        }
+        rest = &rest[end..];
+    }
+    literals
+}
+
+/// Adds whether an empty line comes before each comment at the start of
+/// `text`, and before the text that follows them
+fn push_blank_lines(lines: &mut Vec<bool>, text: &str) {
+    let mut rest = text;
+    loop {
+        let end = rest
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(rest.len());
+        lines.push(rest[..end].matches('\n').count() > 1);
+        match comment(&rest[end..]) {
+            Ok((next, _)) => rest = next,
+            Err(_) => return,
+        }
+    }
+}
+
+/// Offset of the body of the block that starts at `start`, after its
+/// opening brace
+pub(crate) fn block_body(text: &str, start: usize) -> Option<usize> {
+    let (mut rest, _) = leading_comments(&text[start..]).ok()?;
+    while let Some(c) = rest.chars().next() {
+        if let Ok((next, _)) = comment(rest) {
+            rest = next;
+            continue;
+        }
+        if c == '"' {
+            let end = rest[1..].find('"')?;
+            rest = &rest[end + 2..];
+            continue;
+        }
+        rest = &rest[c.len_utf8()..];
+        if c == '{' {
+            return Some(text.len() - rest.len());
+        }
+    }
+    None
+}
+
+/// Blank lines before the leading comments and the nodes with comments of a
+/// script, in the order the printer writes them
+fn blank_lines(text: &str, items: &[RootItem], ranges: &[Range<usize>]) -> Option<Vec<bool>> {
+    let mut lines = Vec::new();
+    let mut blank = |range: &Range<usize>| push_blank_lines(&mut lines, &text[range.clone()]);
+    for (item, range) in items.iter().zip(ranges) {
+        blank(range);
+        match *item {
+            RootItem::Memory { .. } => {
+                let body = block_body(text, range.start)?;
+                let (_, (_, ranges)) = parse_list(&text[body..], body, region, true).ok()?;
+                ranges.iter().for_each(&mut blank);
+            }
+            RootItem::Sections { .. } => {
+                let body = block_body(text, range.start)?;
+                let (_, (commands, ranges)) =
+                    parse_list(&text[body..], body, section_command, true).ok()?;
+                for (command, range) in commands.iter().zip(&ranges) {
+                    blank(range);
+                    let content = match *command {
+                        SectionCommand::OutputSection { ref content, .. } => content,
+                        _ => continue,
+                    };
+                    if content.is_empty() {
+                        continue;
+                    }
+                    let body = block_body(text, range.start)?;
+                    let (_, (nodes, ranges)) =
+                        parse_list(&text[body..], body, output_section_command, true).ok()?;
+                    if nodes.len() != content.len() {
+                        return None;
+                    }
+                    ranges.iter().for_each(&mut blank);
+                }
+            }
+            RootItem::Statement(_) | RootItem::Command(_) => {}
+        }
+    }
+    Some(lines)
+}
+
+/// Formats a parsed linker script.
+pub fn format(items: &[RootItem], options: &FormatOptions) -> String {
+    let mut out = String::new();
+    Printer::new(&mut out, options).items(items).unwrap();
+    out
+}
+
+/// Number of comments in the text of a script. A `/*` glued to a name is
+/// part of a file name pattern like `lib/*.o` rather than a comment.
+fn count_comments(text: &str) -> usize {
+    let mut count = 0;
+    let mut previous = ' ';
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("/*") && (previous.is_whitespace() || "{}();,=:".contains(previous)) {
+            match rest[2..].find("*/") {
+                Some(end) => rest = &rest[end + 4..],
+                None => break,
+            }
+            count += 1;
+            previous = ' ';
+            continue;
+        }
+        if c == '"' {
+            match rest[1..].find('"') {
+                Some(end) => rest = &rest[end + 2..],
+                None => break,
+            }
+        } else {
+            rest = &rest[c.len_utf8()..];
+        }
+        previous = c;
+    }
+    count
+}
+
+/// Parses and formats the text of a linker script. Unlike
+/// [`parse`](../fn.parse.html), the whole input has to be valid.
+///
+/// The syntax tree has no place for comments inside a statement or command,
+/// like the one in `A = 1 + /* one */ 1;`. Rather than dropping them, the
+/// text is not formatted and an error is returned.
+pub fn format_str(input: &str, options: &FormatOptions) -> Result<String, String> {
+    // the ranges of the items are needed to find the blank lines
+    let parsed = parse_list(input, 0, root_item, true)
+        .and_then(|(rest, parsed)| opt_space(rest).map(|(rest, _)| (rest, parsed)));
+    match parsed {
+        // there is no node to attach the comments of an empty script to
+        Ok(("", (ref items, _))) if items.is_empty() => Ok(leading_comments(input)
+            .map(|(_, comments)| comments.iter().map(|c| format!("/*{}*/\n", c)).collect())
+            .unwrap_or_default()),
+        Ok(("", (items, ranges))) => {
+            let source = Source::new(input, &items, &ranges);
+            let mut out = String::new();
+            Printer::new(&mut out, options)
+                .source(&source)
+                .items(&items)
+                .unwrap();
+            let lost = count_comments(input).saturating_sub(count_comments(&out));
+            if lost > 0 {
+                return Err(format!(
+                    "formatting would drop {} comment(s) inside statements or commands",
+                    lost
+                ));
+            }
+            Ok(out)
+        }
+        Ok((rest, _)) => Err(format!(
+            "Parsing failed, unexpected input: {:?}",
+            rest.lines().next().unwrap_or_default()
+        )),
+        Err(e) => Err(format!("Parsing failed, error: {:?}", e)),
+    }
+}
+
+/// Returns whether the text of a linker script is already formatted.
+pub fn check(input: &str, options: &FormatOptions) -> Result<bool, String> {
+    format_str(input, options).map(|text| text == input)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use format::*;
+    use script::parse;
+    use std::fs::File;
+    use std::io::Read;
+    use utils::corpus_sample;
+
+    fn options() -> FormatOptions {
+        FormatOptions::default()
+    }
+
+    #[test]
+    fn test_memory() {
+        let text = format_str(
+            "MEMORY {
+                SFR : ORIGIN = 0x0000, LENGTH = 0x0010 /* special registers */
+                INFOA (rx) : ORIGIN = 0x10C0, LENGTH = 0x0040
+            }",
+            &options(),
+        );
+        assert_eq!(
+            text.unwrap(),
+            "MEMORY
+{
+    SFR        : ORIGIN = 0x0000, LENGTH = 0x0010 /* special registers */
+    INFOA (rx) : ORIGIN = 0x10C0, LENGTH = 0x0040
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_brace_style() {
+        let input = "MEMORY{RAM:ORIGIN=0,LENGTH=1K}A=1;SECTIONS{.data:{*(.data)}>RAM .bss:{}}";
+        let same_line = FormatOptions {
+            indent_width: 2,
+            brace_style: BraceStyle::SameLine,
+            ..options()
+        };
+        assert_eq!(
+            format_str(input, &same_line).unwrap(),
+            "MEMORY {
+  RAM : ORIGIN = 0, LENGTH = 1K
+}
+
+A = 1;
+
+SECTIONS {
+  .data : {
+    *(.data)
+  } > RAM
+
+  .bss : {}
+}
+"
+        );
+        let tabs = FormatOptions {
+            hard_tabs: true,
+            blank_lines: false,
+            ..options()
+        };
+        assert_eq!(
+            format_str("SECTIONS{.data:{*(.data)}}", &tabs).unwrap(),
+            "SECTIONS\n{\n\t.data :\n\t{\n\t\t*(.data)\n\t}\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_wrap() {
+        let input = "SECTIONS { .text : { KEEP(*(.text .text.* .gnu.linkonce.t.* .glue_7 .glue_7t .vfp11_veneer)) } }";
+        let narrow = FormatOptions {
+            max_width: 40,
+            ..options()
+        };
+        let text = format_str(input, &narrow).unwrap();
+        assert_eq!(
+            text,
+            "SECTIONS
+{
+    .text :
+    {
+        KEEP(*(.text .text.*
+            .gnu.linkonce.t.* .glue_7
+            .glue_7t .vfp11_veneer))
+    }
+}
+"
+        );
+        assert_eq!(parse(&text).unwrap().1, parse(input).unwrap().1);
+
+        let text = format_str(input, &options()).unwrap();
+        assert!(text
+            .contains("KEEP(*(.text .text.* .gnu.linkonce.t.* .glue_7 .glue_7t .vfp11_veneer))"));
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "/* header */
+SECTIONS
+{
+    /* code */
+    .text : { *(.text) /* all code */ } > ROM
+    /* end of sections */
+}
+/* end of file */
+";
+        let text = format_str(input, &options()).unwrap();
+        assert_eq!(
+            text,
+            "/* header */
+SECTIONS
+{
+    /* code */
+    .text :
+    {
+        *(.text) /* all code */
+    } > ROM
+    /* end of sections */
+}
+/* end of file */
+"
+        );
+        assert_eq!(check(&text, &options()), Ok(true));
+    }
+
+    #[test]
+    fn test_source() {
+        let input = "/* header */
+
+/* more */
+A = 0x0010 + 16;
+B = 16;
+
+SECTIONS
+{
+    .data :
+    {
+        *(.data)
+
+        /* small data */
+
+        *(.sdata)
+    }
+}
+";
+        assert_eq!(format_str(input, &options()).unwrap(), input);
+
+        let mut contents = String::new();
+        File::open("tests/msp430f5529.ld")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        let text = format_str(&contents, &options()).unwrap();
+        assert!(text.contains(
+            "    INFOA      : ORIGIN = 0x1980,     LENGTH = 0x0080 /* END=0x19FF, size 128 */\n"
+        ));
+        assert!(text.contains("    HIROM (rx) : ORIGIN = 0x00010000, LENGTH = 0x000143FF\n"));
+        assert!(text.contains("        *(.lower.data.* .lower.data)\n\n        . = ALIGN(2);\n"));
+        assert_eq!(check(&text, &options()), Ok(true));
+    }
+
+    #[test]
+    fn test_check() {
+        assert_eq!(check("A = 1;\n", &options()), Ok(true));
+        assert_eq!(check("A=1;", &options()), Ok(false));
+        assert!(check("A = 1; }", &options()).is_err());
+        assert_eq!(check("", &options()), Ok(true));
+        assert_eq!(
+            format_str(" /* a */  /* b */\n", &options()),
+            Ok("/* a */\n/* b */\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_inner_comments() {
+        for input in &[
+            "SECTIONS { .text /* a */ : { *(.text) } }",
+            "MEMORY /* m */ { RAM : ORIGIN = 0, LENGTH = 1K }",
+            "A = 1 + /* x */ 2;",
+            "SECTIONS { .data : { *(.data) } /* before region */ > RAM }",
+        ] {
+            assert_eq!(
+                format_str(input, &options()),
+                Err("formatting would drop 1 comment(s) inside statements or commands".to_string()),
+                "{}",
+                input
+            );
+            assert!(check(input, &options()).is_err());
+        }
+        assert_eq!(
+            format_str("SECTIONS { .t : { lib/*.o(.t) } } /* end */", &options()),
+            Ok(
+                "SECTIONS\n{\n    .t :\n    {\n        lib/*.o(.t)\n    }\n} /* end */\n"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_idempotent() {
+        for path in corpus_sample() {
+            let mut file = File::open(&path).unwrap();
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).unwrap();
+            let (_, items) = parse(&contents).unwrap();
+            let text = format(&items, &options());
+            let (_, again) = parse(&text).unwrap();
+            assert_eq!(format(&again, &options()), text, "{:?}", path);
+            assert!(items == again, "{:?} changed when formatted", path);
+            let text = format_str(&contents, &options()).unwrap();
+            assert_eq!(check(&text, &options()), Ok(true), "{:?}", path);
+        }
+    }
+}
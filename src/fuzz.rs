@@ -0,0 +1,534 @@
+//! `Arbitrary` implementations that build valid syntax trees.
+//!
+//! Names, patterns and numbers follow the lexical rules of the parser and
+//! avoid keywords, so every generated tree can be printed and parsed back
+//! into an equal one. Nesting and list lengths are kept small.
+
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use arbitrary::{Arbitrary, Result, Unstructured};
+use commands::{Command, InsertOrder};
+use expressions::{BinaryOperator, Expression, UnaryOperator};
+use memory::Region;
+use script::{RootItem, Script};
+use sections::{
+    DataType, OutputSectionCommand, OutputSectionConstraint, OutputSectionType, SectionCommand,
+    SectionPattern,
+};
+use statements::{AssignOperator, Statement};
+use whitespace::{Comment, CommentPosition};
+
+/// Deepest expression that is generated
+const MAX_DEPTH: usize = 4;
+/// Longest list of nodes that is generated
+const MAX_LEN: usize = 4;
+
+const SYMBOLS: &[&str] = &[
+    ".",
+    "_stack",
+    "_estack",
+    "__bss_start",
+    "__bss_end",
+    "_etext",
+    "__data_load",
+    "end",
+    "__interrupt_vector_1",
+];
+
+const SECTIONS: &[&str] = &[
+    ".text",
+    ".data",
+    ".bss",
+    ".rodata",
+    ".vectors",
+    ".stack",
+    ".heap",
+    "/DISCARD/",
+];
+
+const REGIONS: &[&str] = &["RAM", "ROM", "FLASH", "SRAM", "INFOA", "BSL", "ram-1"];
+
+const FUNCTIONS: &[&str] = &[
+    "ABSOLUTE", "ADDR", "ALIGN", "DEFINED", "LENGTH", "LOADADDR", "MAX", "MIN", "ORIGIN", "SIZEOF",
+];
+
+const COMMANDS: &[&str] = &[
+    "ENTRY",
+    "OUTPUT_ARCH",
+    "OUTPUT_FORMAT",
+    "SEARCH_DIR",
+    "INPUT",
+    "GROUP",
+    "EXTERN",
+];
+
+const PATTERN_PARTS: &[&str] = &[
+    "*", "?", ".text", ".data", ".bss", ".rodata", ".", "crt", "lib", "[0-9]", ".o", ".a:", "_",
+];
+
+/// Length of a list with at least `min` nodes
+fn len(u: &mut Unstructured, min: usize) -> Result<usize> {
+    u.int_in_range(min..=MAX_LEN)
+}
+
+/// Appends up to `max` characters of `chars` to `out`
+fn push_chars(u: &mut Unstructured, out: &mut String, chars: &str, max: usize) -> Result<()> {
+    let chars: Vec<char> = chars.chars().collect();
+    for _ in 0..u.int_in_range(0..=max)? {
+        out.push(*u.choose(&chars)?);
+    }
+    Ok(())
+}
+
+/// A name that matches `symbol` in `idents.rs`. It starts with a lower case
+/// letter, `_` or `.` so it is never a keyword.
+fn fresh_name(u: &mut Unstructured, minus: bool) -> Result<String> {
+    let mut name = String::new();
+    let first: Vec<char> = "abcdefghijklmnopqrstuvwxyz_.".chars().collect();
+    name.push(*u.choose(&first)?);
+    let rest = if minus {
+        "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_.-"
+    } else {
+        "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_."
+    };
+    push_chars(u, &mut name, rest, 8)?;
+    Ok(name)
+}
+
+/// A known name from `list` or a fresh one
+fn name<'a>(u: &mut Unstructured, list: &[&str], minus: bool) -> Result<Cow<'a, str>> {
+    if u.ratio(1, 2)? {
+        Ok(Cow::Owned((*u.choose(list)?).into()))
+    } else {
+        fresh_name(u, minus).map(Cow::Owned)
+    }
+}
+
+/// A symbol name, `.` is only returned when `dot` is set
+fn symbol<'a>(u: &mut Unstructured, dot: bool) -> Result<Cow<'a, str>> {
+    let symbols = if dot { SYMBOLS } else { &SYMBOLS[1..] };
+    let name = name(u, symbols, false)?;
+    if !dot && name == "." {
+        return Ok(Cow::Borrowed("_"));
+    }
+    Ok(name)
+}
+
+fn section_name<'a>(u: &mut Unstructured) -> Result<Cow<'a, str>> {
+    let name = name(u, SECTIONS, true)?;
+    if name == "." {
+        return Ok(Cow::Borrowed(".text"));
+    }
+    Ok(name)
+}
+
+fn region_name<'a>(u: &mut Unstructured) -> Result<Cow<'a, str>> {
+    let name = name(u, REGIONS, true)?;
+    if name == "." {
+        return Ok(Cow::Borrowed("RAM"));
+    }
+    Ok(name)
+}
+
+/// A file or section pattern that matches `pattern` in `idents.rs`
+fn pattern<'a>(u: &mut Unstructured) -> Result<Cow<'a, str>> {
+    let mut pattern = String::new();
+    for _ in 0..u.int_in_range(1..=3)? {
+        pattern.push_str(u.choose(PATTERN_PARTS)?);
+    }
+    Ok(Cow::Owned(pattern))
+}
+
+/// Text of a quoted string, without the quotes
+fn text<'a>(u: &mut Unstructured) -> Result<Cow<'a, str>> {
+    let mut text = String::new();
+    push_chars(
+        u,
+        &mut text,
+        "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 _.,:!",
+        16,
+    )?;
+    Ok(Cow::Owned(text))
+}
+
+/// Comments before or after a node
+fn comments<'a>(u: &mut Unstructured) -> Result<Vec<Comment<'a>>> {
+    let mut comments = Vec::new();
+    if u.ratio(1, 8)? {
+        comments.push(Comment {
+            text: Cow::Owned(format!(" {} ", text(u)?)),
+            position: u.arbitrary()?,
+        });
+    }
+    Ok(comments)
+}
+
+fn expression<'a>(u: &mut Unstructured, depth: usize) -> Result<Expression<'a>> {
+    let leaf = depth >= MAX_DEPTH || u.is_empty();
+    Ok(match u.int_in_range(0..=if leaf { 1 } else { 5 })? {
+        0 => Expression::Ident(symbol(u, true)?),
+        1 => Expression::Number(u.arbitrary()?),
+        2 => Expression::Call {
+            function: name(u, FUNCTIONS, false)?,
+            arguments: {
+                let mut arguments = Vec::new();
+                for _ in 0..u.int_in_range(0..=3)? {
+                    arguments.push(expression(u, depth + 1)?);
+                }
+                arguments
+            },
+        },
+        3 => Expression::UnaryOp {
+            operator: u.arbitrary()?,
+            right: Box::new(expression(u, depth + 1)?),
+        },
+        4 => Expression::BinaryOp {
+            left: Box::new(expression(u, depth + 1)?),
+            operator: u.arbitrary()?,
+            right: Box::new(expression(u, depth + 1)?),
+        },
+        _ => Expression::TernaryOp {
+            condition: Box::new(expression(u, depth + 1)?),
+            left: Box::new(expression(u, depth + 1)?),
+            right: Box::new(expression(u, depth + 1)?),
+        },
+    })
+}
+
+fn optional_expression<'a>(u: &mut Unstructured) -> Result<Option<Box<Expression<'a>>>> {
+    if u.ratio(1, 3)? {
+        Ok(Some(Box::new(expression(u, 0)?)))
+    } else {
+        Ok(None)
+    }
+}
+
+fn section_pattern<'a>(u: &mut Unstructured, depth: usize) -> Result<SectionPattern<'a>> {
+    let leaf = depth >= 2;
+    Ok(match u.int_in_range(0..=if leaf { 5 } else { 6 })? {
+        0 | 1 => SectionPattern::Simple(pattern(u)?),
+        2 => SectionPattern::SortByName(pattern(u)?),
+        3 => SectionPattern::SortByAlignment(pattern(u)?),
+        4 => SectionPattern::SortByInitPriority(pattern(u)?),
+        5 => SectionPattern::SortNone(pattern(u)?),
+        _ => SectionPattern::ExcludeFile {
+            files: {
+                let mut files = Vec::new();
+                for _ in 0..len(u, 1)? {
+                    files.push(pattern(u)?);
+                }
+                files
+            },
+            pattern: Box::new(section_pattern(u, depth + 1)?),
+        },
+    })
+}
+
+/// Makes an arbitrary value from a list of variants without data
+macro_rules! choose_impl {
+    ($ty:ident, $($variant:ident),+) => {
+        impl<'a> Arbitrary<'a> for $ty {
+            fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+                Ok(*u.choose(&[$($ty::$variant),+])?)
+            }
+        }
+    };
+}
+
+choose_impl!(UnaryOperator, LogicNot, Minus, BitwiseNot);
+choose_impl!(
+    BinaryOperator,
+    LogicOr,
+    LogicAnd,
+    BitwiseOr,
+    BitwiseAnd,
+    Equals,
+    NotEquals,
+    Lesser,
+    Greater,
+    LesserOrEquals,
+    GreaterOrEquals,
+    ShiftRight,
+    ShiftLeft,
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    Remainder
+);
+choose_impl!(
+    AssignOperator,
+    Equals,
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    ShiftLeft,
+    ShiftRight,
+    And,
+    Or
+);
+choose_impl!(InsertOrder, Before, After);
+choose_impl!(DataType, Byte, Short, Long, Quad);
+choose_impl!(OutputSectionType, NoLoad, DSect, Copy, Info, Overlay);
+choose_impl!(OutputSectionConstraint, OnlyIfRo, OnlyIfRw);
+// never dangling, since those can only follow the last node of a block
+choose_impl!(CommentPosition, Leading, Trailing);
+
+impl<'a> Arbitrary<'a> for Comment<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Comment {
+            text: text(u)?,
+            position: u.arbitrary()?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Expression<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        expression(u, 0)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Statement<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=4)? {
+            0 => Statement::Assign {
+                name: symbol(u, true)?,
+                operator: u.arbitrary()?,
+                expression: Box::new(u.arbitrary()?),
+                comments: comments(u)?,
+            },
+            1 => Statement::Hidden {
+                name: symbol(u, false)?,
+                expression: Box::new(u.arbitrary()?),
+                comments: comments(u)?,
+            },
+            2 => Statement::Provide {
+                name: symbol(u, false)?,
+                expression: Box::new(u.arbitrary()?),
+                comments: comments(u)?,
+            },
+            3 => Statement::ProvideHidden {
+                name: symbol(u, false)?,
+                expression: Box::new(u.arbitrary()?),
+                comments: comments(u)?,
+            },
+            _ => Statement::Assert {
+                expr: Box::new(u.arbitrary()?),
+                text: text(u)?,
+                comments: comments(u)?,
+            },
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Command<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=2)? {
+            0 => Command::Call {
+                name: Cow::Owned((*u.choose(COMMANDS)?).into()),
+                arguments: {
+                    let mut arguments = Vec::new();
+                    for _ in 0..len(u, 1)? {
+                        arguments.push(u.arbitrary()?);
+                    }
+                    arguments
+                },
+                comments: comments(u)?,
+            },
+            1 => Command::Include {
+                file: pattern(u)?,
+                comments: comments(u)?,
+            },
+            _ => Command::Insert {
+                order: u.arbitrary()?,
+                section: section_name(u)?,
+                comments: comments(u)?,
+            },
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Region<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Region {
+            name: region_name(u)?,
+            attributes: if u.ratio(1, 2)? {
+                let mut attributes = String::new();
+                push_chars(u, &mut attributes, "rwxailRWXAIL", 3)?;
+                attributes.push(*u.choose(&['r', 'w', 'x', 'a', 'i', 'l', '!'])?);
+                Some(Cow::Owned(attributes))
+            } else {
+                None
+            },
+            origin: u.arbitrary()?,
+            length: u.arbitrary()?,
+            comments: comments(u)?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for SectionPattern<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        section_pattern(u, 0)
+    }
+}
+
+impl<'a> Arbitrary<'a> for OutputSectionCommand<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=4)? {
+            0 => OutputSectionCommand::Statement(u.arbitrary()?),
+            1 => OutputSectionCommand::Fill {
+                expr: Box::new(u.arbitrary()?),
+                comments: comments(u)?,
+            },
+            2 => OutputSectionCommand::Data {
+                d_type: u.arbitrary()?,
+                value: Box::new(u.arbitrary()?),
+                comments: comments(u)?,
+            },
+            _ => {
+                let file = u.arbitrary()?;
+                let mut sections = Vec::new();
+                for _ in 0..len(u, 0)? {
+                    sections.push(u.arbitrary()?);
+                }
+                if u.ratio(1, 3)? {
+                    OutputSectionCommand::KeepInputSection {
+                        file,
+                        sections,
+                        comments: comments(u)?,
+                    }
+                } else {
+                    OutputSectionCommand::InputSection {
+                        file,
+                        sections,
+                        comments: comments(u)?,
+                    }
+                }
+            }
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for SectionCommand<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=3)? {
+            0 => SectionCommand::Statement(u.arbitrary()?),
+            1 => SectionCommand::Command(u.arbitrary()?),
+            _ => SectionCommand::OutputSection {
+                name: section_name(u)?,
+                vma_address: optional_expression(u)?,
+                s_type: u.arbitrary()?,
+                lma_address: optional_expression(u)?,
+                section_align: optional_expression(u)?,
+                align_with_input: u.arbitrary()?,
+                subsection_align: optional_expression(u)?,
+                constraint: u.arbitrary()?,
+                content: {
+                    let mut content = Vec::new();
+                    for _ in 0..len(u, 0)? {
+                        content.push(u.arbitrary()?);
+                    }
+                    content
+                },
+                region: if u.ratio(1, 2)? {
+                    Some(region_name(u)?)
+                } else {
+                    None
+                },
+                lma_region: if u.ratio(1, 3)? {
+                    Some(region_name(u)?)
+                } else {
+                    None
+                },
+                fillexp: optional_expression(u)?,
+                comments: comments(u)?,
+            },
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for RootItem<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=3)? {
+            0 => RootItem::Statement(u.arbitrary()?),
+            1 => RootItem::Command(u.arbitrary()?),
+            2 => RootItem::Memory {
+                regions: {
+                    let mut regions = Vec::new();
+                    for _ in 0..len(u, 1)? {
+                        regions.push(u.arbitrary()?);
+                    }
+                    regions
+                },
+                comments: comments(u)?,
+            },
+            _ => RootItem::Sections {
+                list: {
+                    let mut list = Vec::new();
+                    for _ in 0..len(u, 1)? {
+                        list.push(u.arbitrary()?);
+                    }
+                    list
+                },
+                comments: comments(u)?,
+            },
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Script<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut items = Vec::new();
+        for _ in 0..len(u, 0)? {
+            items.push(u.arbitrary()?);
+        }
+        Ok(Script { items })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use alloc::vec::Vec;
+    use arbitrary::{Arbitrary, Unstructured};
+    use script::Script;
+    use whitespace::{Comment, CommentPosition};
+
+    #[test]
+    fn test_roundtrip() {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut data = Vec::new();
+        for _ in 0..2000 {
+            data.clear();
+            for _ in 0..512 {
+                // xorshift64
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                data.push(state as u8);
+            }
+            let mut u = Unstructured::new(&data);
+            let script = Script::arbitrary(&mut u).unwrap();
+            let text = script.to_string();
+            match ::parse(&text) {
+                Ok(items) => assert_eq!(items, script.items, "{}", text),
+                Err(e) => panic!("{}\n{}", e, text),
+            }
+        }
+    }
+
+    #[test]
+    fn test_comments() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut u = Unstructured::new(&data);
+        for _ in 0..64 {
+            let comment = Comment::arbitrary(&mut u).unwrap();
+            assert_ne!(comment.position, CommentPosition::Dangling);
+        }
+    }
+}
@@ -0,0 +1,465 @@
+//! Incremental reparsing for editors.
+//!
+//! A [`Document`] keeps the text of a script together with its items and the
+//! byte range each of them was parsed from. After an edit only the root items
+//! touched by it are parsed again, or a single output section when the edit
+//! stays inside one SECTIONS block. The other items are kept as they are.
+//!
+//! ```
+//! extern crate ldscript_parser as lds;
+//!
+//! use lds::incremental::{Document, Reparsed};
+//!
+//! fn main() {
+//!     let mut doc = Document::parse("A = 1;\nSECTIONS { .text : { *(.text) } }\n").unwrap();
+//!     let start = doc.text().find(".text)").unwrap();
+//!     let reparsed = doc.edit(start..start + 5, ".code").unwrap();
+//!     assert_eq!(reparsed, Reparsed::SectionCommands { item: 1, commands: 0..1 });
+//!     assert_eq!(doc.items(), &lds::parse(doc.text()).unwrap()[..]);
+//! }
+//! ```
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+use nom::bytes::complete::tag;
+use nom::sequence::tuple;
+use nom::{Err, IResult};
+use script::{root_item, RootItem};
+use sections::{section_command, SectionCommand};
+use whitespace::{commented, dangling_comments, leading_comments, opt_space, Commented};
+
+/// The part of the tree that an edit replaced.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Reparsed {
+    /// The whole text was parsed again
+    Full,
+    /// Root items in this range of indexes were parsed again
+    Items(Range<usize>),
+    /// Commands in this range of indexes of the SECTIONS block at `item`
+    /// were parsed again
+    SectionCommands { item: usize, commands: Range<usize> },
+}
+
+/// Range of a root item, with the ranges of its commands for a SECTIONS
+/// block. The ranges of neighbouring nodes touch, the leading whitespace
+/// and comments belong to the node that follows them.
+#[derive(Debug, Clone)]
+struct ItemSpan {
+    range: Range<usize>,
+    commands: Vec<Range<usize>>,
+}
+
+/// A script that can be edited without parsing all of it again.
+#[derive(Debug, Clone)]
+pub struct Document {
+    text: String,
+    items: Vec<RootItem<'static>>,
+    spans: Vec<ItemSpan>,
+    error: Option<String>,
+}
+
+/// Parses nodes with [`commented`] as long as possible. The ranges of the
+/// nodes are relative to `offset`. With `dangling` the comments after the
+/// last node are attached to it.
+pub(crate) fn parse_list<'a, O, F>(
+    input: &'a str,
+    offset: usize,
+    mut f: F,
+    dangling: bool,
+) -> IResult<&'a str, (Vec<O>, Vec<Range<usize>>)>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, O>,
+    O: Commented<'a>,
+{
+    let position = |rest: &str| offset + input.len() - rest.len();
+    let mut rest = input;
+    let mut nodes = Vec::new();
+    let mut ranges = Vec::new();
+    loop {
+        match commented(&mut f)(rest) {
+            Ok((next, node)) if next.len() < rest.len() => {
+                nodes.push(node);
+                ranges.push(position(rest)..position(next));
+                rest = next;
+            }
+            Err(Err::Error(_)) | Ok(_) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    if dangling {
+        if let Some(last) = nodes.last_mut() {
+            rest = dangling_comments(rest, last)?.0;
+            let end = position(rest);
+            ranges.last_mut().unwrap().end = end;
+        }
+    }
+    Ok((rest, (nodes, ranges)))
+}
+
+/// Ranges of the commands of a SECTIONS block that starts at `offset`
+fn command_ranges(text: &str, offset: usize) -> Vec<Range<usize>> {
+    let input = &text[offset..];
+    let body: IResult<&str, _> = leading_comments(input)
+        .and_then(|(rest, _)| tuple((tag("SECTIONS"), opt_space, tag("{")))(rest));
+    let body = match body {
+        Ok((body, _)) => body,
+        Err(_) => return Vec::new(),
+    };
+    let offset = offset + input.len() - body.len();
+    match parse_list(body, offset, section_command, true) {
+        Ok((_, (_, ranges))) => ranges,
+        Err(_) => Vec::new(),
+    }
+}
+
+fn item_spans(text: &str, items: &[RootItem], ranges: Vec<Range<usize>>) -> Vec<ItemSpan> {
+    items
+        .iter()
+        .zip(ranges)
+        .map(|(item, range)| ItemSpan {
+            commands: match *item {
+                RootItem::Sections { .. } => command_ranges(text, range.start),
+                _ => Vec::new(),
+            },
+            range,
+        })
+        .collect()
+}
+
+/// Returns the indexes of the first and the last range touched by the edit.
+/// An edit on the line where the previous node ends may add a trailing
+/// comment to it, so that node is included as well.
+fn affected(text: &str, ranges: &[Range<usize>], edit: &Range<usize>) -> Option<(usize, usize)> {
+    let mut first = ranges.iter().position(|r| r.end >= edit.start)?;
+    let last = ranges.iter().rposition(|r| r.start <= edit.end)?;
+    if first > 0 && !text[ranges[first - 1].end..edit.start].contains('\n') {
+        first -= 1;
+    }
+    Some((first, last.max(first)))
+}
+
+/// Whether the node after a reparsed range could be glued to it, e.g. a
+/// pattern of `INCLUDE` that is directly followed by another item
+fn touches_next(text: &str, end: usize) -> bool {
+    text[end..]
+        .chars()
+        .next()
+        .is_some_and(|c| !c.is_whitespace())
+}
+
+/// Whether a comment starts in the text but does not end in it, so it
+/// would swallow the text after a reparsed range
+fn open_comment(text: &str) -> bool {
+    let mut rest = text;
+    while let Some(start) = rest.find("/*") {
+        match rest[start + 2..].find("*/") {
+            Some(end) => rest = &rest[start + 2 + end + 2..],
+            None => return true,
+        }
+    }
+    false
+}
+
+fn shift(range: &mut Range<usize>, delta: isize) {
+    range.start = (range.start as isize + delta) as usize;
+    range.end = (range.end as isize + delta) as usize;
+}
+
+impl Document {
+    /// Parses the whole text. Unlike [`parse`](../fn.parse.html), the whole
+    /// input has to be valid.
+    pub fn parse<S: Into<String>>(text: S) -> Result<Document, String> {
+        let mut doc = Document {
+            text: text.into(),
+            items: Vec::new(),
+            spans: Vec::new(),
+            error: None,
+        };
+        doc.reparse_all()?;
+        Ok(doc)
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Items of the last text that was parsed successfully
+    pub fn items(&self) -> &[RootItem<'static>] {
+        &self.items
+    }
+
+    /// The error of the last edit, if the current text does not parse. The
+    /// next edit parses the whole text again.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    fn reparse_all(&mut self) -> Result<Reparsed, String> {
+        let result = match parse_list(&self.text, 0, root_item, true) {
+            Ok((rest, (items, ranges))) => match opt_space(rest) {
+                Ok(("", _)) => Ok((items, ranges)),
+                _ => Err(format!(
+                    "Parsing failed, unexpected input: {:?}",
+                    rest.lines().next().unwrap_or_default()
+                )),
+            },
+            Err(e) => Err(format!("Parsing failed, error: {:?}", e)),
+        };
+        match result {
+            Ok((items, ranges)) => {
+                self.spans = item_spans(&self.text, &items, ranges);
+                self.items = items.into_iter().map(RootItem::into_owned).collect();
+                self.error = None;
+                Ok(Reparsed::Full)
+            }
+            Err(e) => {
+                self.error = Some(e.clone());
+                Err(e)
+            }
+        }
+    }
+
+    /// Replaces the bytes in `range` with `replacement` and updates the
+    /// items. If the new text does not parse, the items are left unchanged
+    /// and the error is returned.
+    pub fn edit(&mut self, range: Range<usize>, replacement: &str) -> Result<Reparsed, String> {
+        if range.start > range.end
+            || range.end > self.text.len()
+            || !self.text.is_char_boundary(range.start)
+            || !self.text.is_char_boundary(range.end)
+        {
+            return Err(format!("Invalid edit range {:?}", range));
+        }
+        let old_text = self.text.clone();
+        self.text.replace_range(range.clone(), replacement);
+        if self.error.is_some() || self.spans.is_empty() {
+            return self.reparse_all();
+        }
+        let delta = replacement.len() as isize - range.len() as isize;
+        match self.reparse_commands(&old_text, &range, delta) {
+            Some(reparsed) => Ok(reparsed),
+            None => match self.reparse_items(&old_text, &range, delta) {
+                Some(reparsed) => Ok(reparsed),
+                None => self.reparse_all(),
+            },
+        }
+    }
+
+    /// Reparses root items, returns `None` if the changed text does not
+    /// consist of whole items
+    fn reparse_items(
+        &mut self,
+        old_text: &str,
+        edit: &Range<usize>,
+        delta: isize,
+    ) -> Option<Reparsed> {
+        let ranges: Vec<_> = self.spans.iter().map(|s| s.range.clone()).collect();
+        let (first, mut last) = affected(old_text, &ranges, edit)?;
+        let start = ranges[first].start;
+        while last + 1 < ranges.len() && touches_next(old_text, ranges[last].end) {
+            last += 1;
+        }
+        let end = (ranges[last].end as isize + delta) as usize;
+        let is_last = last + 1 == ranges.len();
+
+        if open_comment(&self.text[start..end]) {
+            return None;
+        }
+        let (rest, (items, new_ranges)) =
+            parse_list(&self.text[start..end], start, root_item, is_last).ok()?;
+        if !rest.is_empty() || items.is_empty() {
+            return None;
+        }
+        let spans = item_spans(&self.text, &items, new_ranges);
+        let count = items.len();
+        for span in &mut self.spans[last + 1..] {
+            shift(&mut span.range, delta);
+            for command in &mut span.commands {
+                shift(command, delta);
+            }
+        }
+        self.spans.splice(first..last + 1, spans);
+        self.items
+            .splice(first..last + 1, items.into_iter().map(RootItem::into_owned));
+        Some(Reparsed::Items(first..first + count))
+    }
+
+    /// Reparses commands of a SECTIONS block, returns `None` if the edit is
+    /// not inside the body of a single block
+    fn reparse_commands(
+        &mut self,
+        old_text: &str,
+        edit: &Range<usize>,
+        delta: isize,
+    ) -> Option<Reparsed> {
+        let item = self
+            .spans
+            .iter()
+            .position(|s| s.range.start <= edit.start && edit.end <= s.range.end)?;
+        let ranges = self.spans[item].commands.clone();
+        if edit.start < ranges.first()?.start || edit.end > ranges.last()?.end {
+            return None;
+        }
+        let (first, mut last) = affected(old_text, &ranges, edit)?;
+        let start = ranges[first].start;
+        while last + 1 < ranges.len() && touches_next(old_text, ranges[last].end) {
+            last += 1;
+        }
+        let end = (ranges[last].end as isize + delta) as usize;
+        let is_last = last + 1 == ranges.len();
+
+        if open_comment(&self.text[start..end]) {
+            return None;
+        }
+        let (rest, (commands, new_ranges)) =
+            parse_list(&self.text[start..end], start, section_command, is_last).ok()?;
+        if !rest.is_empty() || commands.is_empty() {
+            return None;
+        }
+        let count = commands.len();
+        if let RootItem::Sections { ref mut list, .. } = self.items[item] {
+            list.splice(
+                first..last + 1,
+                commands.into_iter().map(SectionCommand::into_owned),
+            );
+        }
+        let span = &mut self.spans[item];
+        span.range.end = (span.range.end as isize + delta) as usize;
+        for command in &mut span.commands[last + 1..] {
+            shift(command, delta);
+        }
+        span.commands.splice(first..last + 1, new_ranges);
+        for span in &mut self.spans[item + 1..] {
+            shift(&mut span.range, delta);
+            for command in &mut span.commands {
+                shift(command, delta);
+            }
+        }
+        Some(Reparsed::SectionCommands {
+            item,
+            commands: first..first + count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use incremental::*;
+    use script::parse;
+    use std::fs::File;
+    use std::io::Read;
+
+    fn check(doc: &Document) {
+        let (rest, items) = parse(doc.text()).unwrap();
+        assert_eq!(rest, "");
+        let items: Vec<_> = items.into_iter().map(RootItem::into_owned).collect();
+        assert_eq!(doc.items(), &items[..], "{:?}", doc.text());
+    }
+
+    fn edit(doc: &mut Document, find: &str, replacement: &str) -> Reparsed {
+        let start = doc.text().find(find).unwrap();
+        let reparsed = doc.edit(start..start + find.len(), replacement).unwrap();
+        check(doc);
+        reparsed
+    }
+
+    #[test]
+    fn test_edit() {
+        let mut doc = Document::parse(
+            "A = 1;
+MEMORY { RAM : ORIGIN = 0, LENGTH = 1K }
+SECTIONS
+{
+    .text : { *(.text) }
+    .data : { *(.data) } > RAM
+    /* end */
+}
+B = 2;
+",
+        )
+        .unwrap();
+        check(&doc);
+
+        assert_eq!(edit(&mut doc, "1K", "2K"), Reparsed::Items(1..2));
+        assert_eq!(
+            edit(&mut doc, "*(.data)", "*(.data .data.*)"),
+            Reparsed::SectionCommands {
+                item: 2,
+                commands: 1..2
+            }
+        );
+        assert_eq!(
+            edit(&mut doc, "    .data", "    . = ALIGN(4);\n    .data"),
+            Reparsed::SectionCommands {
+                item: 2,
+                commands: 1..3
+            }
+        );
+        assert_eq!(
+            edit(&mut doc, "/* end */", "/* end of sections */"),
+            Reparsed::SectionCommands {
+                item: 2,
+                commands: 2..3
+            }
+        );
+        // a comment on the line of the previous node becomes trailing
+        assert_eq!(
+            edit(&mut doc, "\nB = 2;", " /* one */\nB = 2;"),
+            Reparsed::Items(2..4)
+        );
+        assert_eq!(edit(&mut doc, "B = 2;", "B = 3;"), Reparsed::Items(3..4));
+        assert_eq!(
+            edit(&mut doc, "SECTIONS", "SECTIONS "),
+            Reparsed::Items(2..3)
+        );
+        assert_eq!(edit(&mut doc, "A = 1;\n", ""), Reparsed::Items(0..1));
+        // the comment hides the following commands of the block
+        assert_eq!(
+            edit(&mut doc, ". = ALIGN", "/* . = ALIGN"),
+            Reparsed::Items(1..2)
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut doc = Document::parse("A = 1;\nSECTIONS { .a : { *(.a) } }\n").unwrap();
+        let open = doc.text().find("{ *").unwrap();
+        assert!(doc.edit(open..open + 1, "").is_err());
+        assert!(doc.error().is_some());
+        assert_eq!(doc.items().len(), 2);
+        assert_eq!(doc.edit(open..open, "{"), Ok(Reparsed::Full));
+        assert!(doc.error().is_none());
+        check(&doc);
+        assert!(doc.edit(100..101, "").is_err());
+        assert!(Document::parse("A = 1; }").is_err());
+    }
+
+    #[test]
+    fn test_large_file() {
+        let mut contents = String::new();
+        File::open("tests/msp430g2332.ld")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        let mut doc = Document::parse(contents).unwrap();
+        assert_eq!(
+            edit(
+                &mut doc,
+                "KEEP (*(.resetvec))",
+                "KEEP (*(.resetvec .reset))"
+            ),
+            Reparsed::SectionCommands {
+                item: 3,
+                commands: 15..16
+            }
+        );
+        // typing a character at a time, through invalid intermediate states
+        let at = doc.text().find("PROVIDE (__bssstart").unwrap();
+        for (i, c) in "_x = 1; ".char_indices() {
+            let _ = doc.edit(at + i..at + i, &c.to_string());
+        }
+        check(&doc);
+    }
+}
@@ -0,0 +1,203 @@
+//! Interning of symbol, section and region names.
+//!
+//! An [`Interner`] maps each distinct name to a [`Symbol`], a small copyable
+//! handle that is compared and hashed as an integer. The syntax tree keeps
+//! its strings; analysis code interns the names it looks at and works with
+//! the handles from then on. The [`SymbolTable`] and the [`Layout`] look up
+//! their symbols this way.
+//!
+//! [`SymbolTable`]: ../symbols/struct.SymbolTable.html
+//! [`Layout`]: ../layout/struct.Layout.html
+//!
+//! ```
+//! extern crate ldscript_parser as lds;
+//!
+//! use lds::Script;
+//!
+//! fn main() {
+//!     let script = Script::from(lds::parse("
+//!         MEMORY { RAM : ORIGIN = 0x1000, LENGTH = 4K }
+//!         SECTIONS { .data : { *(.data) } > RAM }
+//!         _end = ORIGIN(RAM) + LENGTH(RAM);
+//!     ").unwrap());
+//!     let mut names = script.interner();
+//!     let ram = names.get("RAM").unwrap();
+//!     assert_eq!(names.resolve(ram), "RAM");
+//!     assert!(names.get(".data").is_some());
+//!     assert_eq!(names.intern("_end"), names.get("_end").unwrap());
+//! }
+//! ```
+
+use alloc::boxed::Box;
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeMap as Map;
+use alloc::vec::Vec;
+use expressions::Expression;
+use memory::Region;
+use script::RootItem;
+use sections::SectionCommand;
+use statements::Statement;
+#[cfg(feature = "std")]
+use std::collections::HashMap as Map;
+use visit::{self, Visit};
+
+/// Handle of an interned name. Handles are only meaningful for the
+/// [`Interner`] that created them.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// Position of the name in the order it was interned, starting at 0
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Table of interned names.
+#[derive(Debug, PartialEq, Eq, Default, Clone)]
+pub struct Interner {
+    names: Vec<Box<str>>,
+    symbols: Map<Box<str>, Symbol>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the symbol of `name`, adding it to the table if needed
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(self.names.len() as u32);
+        self.names.push(name.into());
+        self.symbols.insert(name.into(), symbol);
+        symbol
+    }
+
+    /// Returns the symbol of `name` if it was interned
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).cloned()
+    }
+
+    /// Returns the name of a symbol
+    ///
+    /// Panics if the symbol comes from another interner with more names.
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.names[symbol.index()]
+    }
+
+    /// Number of distinct names
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Iterates over the symbols and their names in the order they were
+    /// interned
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, &str)> {
+        self.names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (Symbol(index as u32), &**name))
+    }
+
+    /// Interns the identifiers in expressions, the names assigned by
+    /// statements, region names and output section names of `items`
+    pub fn intern_items(&mut self, items: &[RootItem]) {
+        let mut names = Names(self);
+        for item in items {
+            names.visit_root_item(item);
+        }
+    }
+}
+
+struct Names<'i>(&'i mut Interner);
+
+impl<'i, 'ast> Visit<'ast> for Names<'i> {
+    fn visit_statement(&mut self, node: &'ast Statement<'ast>) {
+        if let Some(name) = node.name() {
+            self.0.intern(name);
+        }
+        visit::visit_statement(self, node);
+    }
+
+    fn visit_region(&mut self, node: &'ast Region<'ast>) {
+        self.0.intern(&node.name);
+        visit::visit_region(self, node);
+    }
+
+    fn visit_section_command(&mut self, node: &'ast SectionCommand<'ast>) {
+        if let SectionCommand::OutputSection {
+            ref name,
+            ref region,
+            ref lma_region,
+            ..
+        } = *node
+        {
+            self.0.intern(name);
+            for region in region.iter().chain(lma_region) {
+                self.0.intern(region);
+            }
+        }
+        visit::visit_section_command(self, node);
+    }
+
+    fn visit_expression(&mut self, node: &'ast Expression<'ast>) {
+        if let Expression::Ident(ref name) = *node {
+            self.0.intern(name);
+        }
+        visit::visit_expression(self, node);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intern::*;
+
+    #[test]
+    fn test_interner() {
+        let mut names = Interner::new();
+        assert!(names.is_empty());
+        let text = names.intern(".text");
+        let data = names.intern(".data");
+        assert_ne!(text, data);
+        assert_eq!(names.intern(".text"), text);
+        assert_eq!(names.get(".data"), Some(data));
+        assert_eq!(names.get(".bss"), None);
+        assert_eq!(names.resolve(text), ".text");
+        assert_eq!(names.len(), 2);
+        assert_eq!(
+            names.iter().collect::<Vec<_>>(),
+            vec![(text, ".text"), (data, ".data")]
+        );
+        let empty = names.intern("");
+        assert_eq!(names.resolve(empty), "");
+        assert_eq!(names.resolve(data), ".data");
+    }
+
+    #[test]
+    fn test_intern_items() {
+        let items = ::parse(
+            "MEMORY { ROM : ORIGIN = 0, LENGTH = 1K }
+            SECTIONS {
+                .data : { *(.data) __data_end = .; } > RAM AT> ROM
+                ASSERT(__data_end < 100, \"data\")
+            }
+            PROVIDE(_stack = _end + 0x100);",
+        )
+        .unwrap();
+        let mut names = Interner::new();
+        names.intern_items(&items);
+        let mut all: Vec<_> = names.iter().map(|(_, name)| name).collect();
+        all.sort();
+        assert_eq!(
+            all,
+            vec![".", ".data", "RAM", "ROM", "__data_end", "_end", "_stack"]
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Pull-based parsing of root items.
+
+use alloc::string::String;
+use nom::Err;
+use options::{self, ParseOptions};
+use script::{root_item, RootItem};
+use whitespace::{commented, dangling_comments, opt_space};
+
+/// Iterator that parses a script one root item at a time.
+///
+/// Only the item being returned and the one after it are kept in memory, so
+/// the iteration can stop early without parsing the rest of the input. Text
+/// that is not a root item is reported as an error, after which the
+/// iteration ends.
+///
+/// ```
+/// extern crate ldscript_parser as lds;
+///
+/// use lds::{ItemIter, RootItem};
+///
+/// fn main() {
+///     let script = "A = 1;\nMEMORY { RAM : ORIGIN = 0, LENGTH = 4K }\nB = 2;\n";
+///     let memory = ItemIter::new(script)
+///         .filter_map(Result::ok)
+///         .find(|item| matches!(item, RootItem::Memory { .. }));
+///     assert!(memory.is_some());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ItemIter<'a> {
+    rest: &'a str,
+    next: Option<RootItem<'a>>,
+    error: Option<String>,
+    options: ParseOptions,
+    done: bool,
+}
+
+impl<'a> ItemIter<'a> {
+    /// Creates an iterator over the root items of `input`
+    pub fn new(input: &'a str) -> Self {
+        Self::with_options(input, &ParseOptions::default())
+    }
+
+    /// Creates an iterator that parses with non-default settings
+    pub fn with_options(input: &'a str, options: &ParseOptions) -> Self {
+        ItemIter {
+            rest: input,
+            next: None,
+            error: None,
+            options: *options,
+            done: false,
+        }
+    }
+
+    /// The input that has not been parsed yet. One item ahead of the last
+    /// returned one is parsed to find the comments that belong to it.
+    pub fn rest(&self) -> &'a str {
+        self.rest
+    }
+
+    /// Parses the item that follows `self.rest`. Returns `Ok(None)` when no
+    /// item follows it.
+    fn parse_one(&mut self) -> Result<Option<RootItem<'a>>, String> {
+        let rest = self.rest;
+        match options::with_options(&self.options, || commented(root_item)(rest)) {
+            Ok((rest, item)) if rest.len() < self.rest.len() => {
+                self.rest = rest;
+                Ok(Some(item))
+            }
+            Ok(_) | Err(Err::Error(_)) => Ok(None),
+            Err(e) => Err(format!("Parsing failed, error: {:?}", e)),
+        }
+    }
+
+    /// Checks that nothing but whitespace and comments is left
+    fn finish(&mut self) -> Result<(), String> {
+        match opt_space(self.rest) {
+            Ok(("", _)) => Ok(()),
+            Ok((rest, _)) => Err(format!(
+                "Parsing failed, unexpected input: {:?}",
+                rest.lines().next().unwrap_or(rest)
+            )),
+            Err(e) => Err(format!("Parsing failed, error: {:?}", e)),
+        }
+    }
+}
+
+impl<'a> Iterator for ItemIter<'a> {
+    type Item = Result<RootItem<'a>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.error.take() {
+            self.done = true;
+            return Some(Err(error));
+        }
+        if self.done {
+            return None;
+        }
+        let mut current = match self.next.take() {
+            Some(item) => item,
+            None => match self.parse_one() {
+                Ok(Some(item)) => item,
+                Ok(None) => {
+                    self.done = true;
+                    return self.finish().err().map(Err);
+                }
+                Err(error) => {
+                    self.done = true;
+                    return Some(Err(error));
+                }
+            },
+        };
+        // The comments after the last item belong to it, so one item of
+        // lookahead is needed before `current` can be returned
+        match self.parse_one() {
+            Ok(Some(item)) => self.next = Some(item),
+            Ok(None) => {
+                self.rest = match dangling_comments(self.rest, &mut current) {
+                    Ok((rest, _)) => rest,
+                    Err(e) => return Some(Err(format!("Parsing failed, error: {:?}", e))),
+                };
+                match self.finish() {
+                    Ok(()) => self.done = true,
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Err(error) => self.error = Some(error),
+        }
+        Some(Ok(current))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use iter::*;
+    use std::fs::File;
+    use std::io::Read;
+    use utils::corpus_sample;
+
+    #[test]
+    fn test_iter() {
+        let items: Vec<_> = ItemIter::new("").collect();
+        assert!(items.is_empty());
+        let items: Vec<_> = ItemIter::new(" /* only a comment */ ").collect();
+        assert!(items.is_empty());
+
+        let input = "A = 1; /* a */\nB = 2;\n/* last */\n";
+        let items: Vec<_> = ItemIter::new(input).map(Result::unwrap).collect();
+        assert_eq!(items, ::parse_borrowed(input).unwrap());
+
+        let mut iter = ItemIter::new("A = 1;\nB = 2;\nC = 3;\nD = 4;\n");
+        assert!(iter.next().unwrap().is_ok());
+        assert_eq!(iter.rest(), "\nC = 3;\nD = 4;\n");
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut iter = ItemIter::new("A = 1;\nB = 2;\n}\nC = 3;");
+        assert!(iter.next().unwrap().is_ok());
+        assert!(iter.next().unwrap().is_ok());
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+
+        let deep = format!("A = 1;\nB = {}1{};", "(".repeat(10), ")".repeat(10));
+        let options = ParseOptions { max_depth: 4 };
+        let mut iter = ItemIter::with_options(&deep, &options);
+        assert!(iter.next().unwrap().is_ok());
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+        assert_eq!(ItemIter::new(&deep).filter(Result::is_ok).count(), 2);
+    }
+
+    #[test]
+    fn test_corpus() {
+        for path in corpus_sample() {
+            let mut input = String::new();
+            File::open(&path)
+                .unwrap()
+                .read_to_string(&mut input)
+                .unwrap();
+            let items: Result<Vec<_>, _> = ItemIter::new(&input).collect();
+            assert_eq!(
+                items.unwrap(),
+                ::parse_borrowed(&input).unwrap(),
+                "{:?}",
+                path
+            );
+        }
+    }
+}
@@ -0,0 +1,1033 @@
+//! Placement of output sections without running the linker.
+//!
+//! [`Layout::compute`] walks the SECTIONS blocks of a script the way ld
+//! sizes them: input sections from a [`Manifest`] are matched against the
+//! input section commands, the location counter and the MEMORY regions are
+//! advanced, and every output section gets its VMA, LMA and size. Symbol
+//! assignments are evaluated along the way, a `PROVIDE` unless the script
+//! assigns the symbol or the manifest says an input file defines it or
+//! nothing refers to it. Like ld, the walk is repeated
+//! until the addresses settle, so expressions may refer to sections and
+//! symbols that come later in the script. `ASSERT`s are checked in the last
+//! walk, with the location counter and symbols as they are at their place
+//! in the script, and the ones that fail are collected in
+//! [`Layout::failed_assertions`], with their lines once
+//! [`Layout::locate_assertions`] is given the text of the script.
+//!
+//! ```
+//! extern crate ldscript_parser as lds;
+//!
+//! use lds::layout::Layout;
+//! use lds::manifest::Manifest;
+//!
+//! fn main() {
+//!     let items = lds::parse("
+//!         MEMORY {
+//!             RAM : ORIGIN = 0x1C00, LENGTH = 0x800
+//!             ROM : ORIGIN = 0x4400, LENGTH = 0xBB80
+//!         }
+//!         SECTIONS {
+//!             .text : { *(.text) _etext = .; } > ROM
+//!             .data : { *(.data) } > RAM AT> ROM
+//!             .bss (NOLOAD) : { *(.bss) } > RAM
+//!         }
+//!     ").unwrap();
+//!     let manifest = Manifest::parse("
+//!         main.o .text 0x122 2
+//!         main.o .data 0x10 2
+//!         main.o .bss 0x40 2
+//!     ").unwrap();
+//!     let layout = Layout::compute(&items, &manifest).unwrap();
+//!     let data = layout.section(".data").unwrap();
+//!     assert_eq!((data.vma, data.lma, data.size), (0x1C00, 0x4522, 0x10));
+//!     assert_eq!(layout.section(".bss").unwrap().vma, 0x1C10);
+//!     assert_eq!(layout.symbol("_etext"), Some(0x4522));
+//! }
+//! ```
+
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use commands::Command;
+use core::fmt;
+use eval::{Env, EvalContext, EvalError};
+use expressions::Expression;
+use intern::Interner;
+use manifest::Manifest;
+use matching::Matcher;
+use memory::Region;
+use script::RootItem;
+use sections::{OutputSectionCommand, OutputSectionType, SectionCommand};
+use statements::{AssignOperator, Statement};
+#[cfg(feature = "std")]
+use std::error::Error;
+use symbols::{Site, SymbolTable};
+
+/// Passes over the script before the addresses are expected to settle
+const MAX_PASSES: usize = 16;
+
+/// Failure of [`Layout::compute`].
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum LayoutError {
+    /// An expression cannot be evaluated
+    Eval { site: Site, error: EvalError },
+    /// An output section is placed into a region that is not defined
+    UndefinedRegion { section: String, region: String },
+    /// An assignment inside an output section moves `.` backwards
+    Backwards { site: Site, from: u64, to: u64 },
+    /// The addresses keep changing from one pass to the next
+    NotConverging,
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LayoutError::Eval { ref error, .. } => fmt::Display::fmt(error, f),
+            LayoutError::UndefinedRegion {
+                ref section,
+                ref region,
+            } => write!(
+                f,
+                "output section `{}` refers to undefined region `{}`",
+                section, region
+            ),
+            LayoutError::Backwards { from, to, .. } => write!(
+                f,
+                "cannot move location counter backwards (from {:#x} to {:#x})",
+                from, to
+            ),
+            LayoutError::NotConverging => f.write_str("section addresses do not settle"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for LayoutError {}
+
+/// An `ASSERT` whose expression is 0 in the final layout.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct FailedAssertion {
+    /// Index path of the statement in the script
+    pub site: Site,
+    /// Line of the statement, once [`Layout::locate_assertions`] found it
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for FailedAssertion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+/// An input section placed into an output section.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Placement {
+    /// Index of the input section in the manifest
+    pub input: usize,
+    pub vma: u64,
+}
+
+/// Where an output section landed.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct SectionLayout {
+    pub name: String,
+    pub site: Site,
+    pub vma: u64,
+    pub lma: u64,
+    pub size: u64,
+    /// Alignment of the section, the largest one of `ALIGN()` and its
+    /// input sections
+    pub align: u64,
+    /// The section takes up addresses in memory, false for the `DSECT`,
+    /// `COPY`, `INFO` and `OVERLAY` types
+    pub allocated: bool,
+    /// The section has no contents in the load image, as with `NOLOAD`
+    pub noload: bool,
+    pub region: Option<String>,
+    pub lma_region: Option<String>,
+    /// Input sections in address order
+    pub inputs: Vec<Placement>,
+}
+
+/// Addresses of the output sections and values of the symbols of a script.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Layout {
+    /// Output sections in script order, without `/DISCARD/`
+    pub sections: Vec<SectionLayout>,
+    /// Regions of the MEMORY blocks
+    pub regions: Vec<Region<'static>>,
+    /// Indexes in the manifest of the input sections that went to `/DISCARD/`
+    pub discarded: Vec<usize>,
+    /// Indexes in the manifest of the input sections no pattern matched
+    pub unmatched: Vec<usize>,
+    /// Assertions that do not hold, in script order
+    pub failed_assertions: Vec<FailedAssertion>,
+    /// Names of the symbols, a symbol's handle is its index in `values`
+    names: Interner,
+    values: Vec<u64>,
+}
+
+impl Layout {
+    /// Computes the layout for the address width named by `OUTPUT_ARCH`,
+    /// or for a 64-bit target if the script has none. The width is widened
+    /// to hold every MEMORY region.
+    pub fn compute(items: &[RootItem], manifest: &Manifest) -> Result<Layout, LayoutError> {
+        let ctx = items
+            .iter()
+            .filter_map(|item| match *item {
+                RootItem::Command(Command::Call {
+                    ref name,
+                    ref arguments,
+                    ..
+                }) if name == "OUTPUT_ARCH" => match arguments.first() {
+                    Some(Expression::Ident(arch)) => EvalContext::from_arch(arch),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .next()
+            .unwrap_or_default();
+        // MSP430X parts name their architecture `msp430` as well, a region
+        // above 64K means the wider address space
+        let mut ctx = ctx;
+        for item in items {
+            if let RootItem::Memory { ref regions, .. } = *item {
+                for region in regions {
+                    let last = region
+                        .origin
+                        .saturating_add(region.length.saturating_sub(1));
+                    let bits = 64 - last.leading_zeros();
+                    if bits > ctx.width() {
+                        let width = [20, 32, 64].iter().cloned().find(|&w| w >= bits).unwrap();
+                        ctx = EvalContext::new(width).signed(ctx.is_signed());
+                    }
+                }
+            }
+        }
+        Layout::compute_in(items, manifest, &ctx)
+    }
+
+    /// Computes the layout with the address width and signedness of `ctx`
+    pub fn compute_in(
+        items: &[RootItem],
+        manifest: &Manifest,
+        ctx: &EvalContext,
+    ) -> Result<Layout, LayoutError> {
+        let table = SymbolTable::new(items);
+        let matcher = Matcher::new(items);
+        let taken: Vec<(Site, Vec<usize>)> = matcher
+            .rules
+            .iter()
+            .map(|rule| rule.site)
+            .zip(matcher.select(&manifest.sections))
+            .collect();
+        let mut previous: Option<Layout> = None;
+        for _ in 0..MAX_PASSES {
+            let layout = Pass::new(
+                items,
+                manifest,
+                ctx,
+                &table,
+                &taken,
+                previous.as_ref(),
+                false,
+            )
+            .run()?;
+            if previous.as_ref() == Some(&layout) {
+                // a last pass that reports the errors the others ignored
+                return Pass::new(items, manifest, ctx, &table, &taken, Some(&layout), true).run();
+            }
+            previous = Some(layout);
+        }
+        Err(LayoutError::NotConverging)
+    }
+
+    /// Returns the output section named `name`
+    pub fn section(&self, name: &str) -> Option<&SectionLayout> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    /// Final value of a symbol
+    pub fn symbol(&self, name: &str) -> Option<u64> {
+        self.names
+            .get(name)
+            .map(|symbol| self.values[symbol.index()])
+    }
+
+    /// Iterates over the symbols and their final values in the order they
+    /// were first assigned
+    pub fn symbols(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.names
+            .iter()
+            .map(move |(symbol, name)| (name, self.values[symbol.index()]))
+    }
+
+    /// Finds the lines of the failed assertions in the text the script was
+    /// parsed from
+    pub fn locate_assertions(&mut self, text: &str) {
+        for assertion in &mut self.failed_assertions {
+            assertion.line = assertion.site.line(text);
+        }
+    }
+
+    fn set_symbol(&mut self, name: &str, value: u64) {
+        let index = self.names.intern(name).index();
+        if index == self.values.len() {
+            self.values.push(value);
+        } else {
+            self.values[index] = value;
+        }
+    }
+}
+
+/// Rounds `value` up to a multiple of `align`
+fn align_up(value: u64, align: u64) -> u64 {
+    if align <= 1 {
+        return value;
+    }
+    value.wrapping_add(align - 1) / align * align
+}
+
+/// Whether the expression is a plain number. Assigning one to `.` inside an
+/// output section sets the offset from the start of the section.
+fn is_constant(expr: &Expression) -> bool {
+    match *expr {
+        Expression::Number(_) => true,
+        Expression::Ident(_) | Expression::Call { .. } => false,
+        Expression::UnaryOp { ref right, .. } => is_constant(right),
+        Expression::BinaryOp {
+            ref left,
+            ref right,
+            ..
+        } => is_constant(left) && is_constant(right),
+        Expression::TernaryOp {
+            ref condition,
+            ref left,
+            ref right,
+        } => is_constant(condition) && is_constant(left) && is_constant(right),
+    }
+}
+
+/// One walk over the script. Passes before the last one are lenient: a
+/// value that is not known yet counts as 0 and errors are ignored.
+struct Pass<'s, 'a: 's> {
+    items: &'s [RootItem<'a>],
+    manifest: &'s Manifest,
+    ctx: EvalContext,
+    table: &'s SymbolTable<'a>,
+    previous: Option<&'s Layout>,
+    strict: bool,
+    /// The location counter, `None` outside of SECTIONS
+    dot: Option<u64>,
+    /// Next free address of each region
+    current: Vec<u64>,
+    /// Input section commands with the input sections they take
+    taken: &'s [(Site, Vec<usize>)],
+    layout: Layout,
+}
+
+impl<'s, 'a> Env for Pass<'s, 'a> {
+    fn symbol(&self, name: &str) -> Option<u64> {
+        let value = if name == "." {
+            self.dot
+        } else {
+            self.layout
+                .symbol(name)
+                .or_else(|| self.previous.and_then(|layout| layout.symbol(name)))
+        };
+        value.or(if self.strict { None } else { Some(0) })
+    }
+
+    fn builtin(&self, function: &str, name: &str) -> Option<u64> {
+        let region = || {
+            self.layout
+                .regions
+                .iter()
+                .find(|region| region.name == name)
+        };
+        let section = || {
+            self.layout
+                .section(name)
+                .or_else(|| self.previous.and_then(|layout| layout.section(name)))
+        };
+        let value = match function {
+            "ORIGIN" => region().map(|region| region.origin),
+            "LENGTH" => region().map(|region| region.length),
+            "ADDR" => section().map(|section| section.vma),
+            "LOADADDR" => section().map(|section| section.lma),
+            "SIZEOF" => section().map(|section| section.size),
+            "ALIGNOF" => section().map(|section| section.align),
+            // ld's defaults vary by target, 4K is the most common page size
+            "CONSTANT" if name == "MAXPAGESIZE" || name == "COMMONPAGESIZE" => Some(0x1000),
+            _ => None,
+        };
+        value.or(if self.strict { None } else { Some(0) })
+    }
+}
+
+impl<'s, 'a> Pass<'s, 'a> {
+    fn new(
+        items: &'s [RootItem<'a>],
+        manifest: &'s Manifest,
+        ctx: &EvalContext,
+        table: &'s SymbolTable<'a>,
+        taken: &'s [(Site, Vec<usize>)],
+        previous: Option<&'s Layout>,
+        strict: bool,
+    ) -> Self {
+        let mut regions = Vec::new();
+        for item in items {
+            if let RootItem::Memory {
+                regions: ref list, ..
+            } = *item
+            {
+                regions.extend(list.iter().map(|region| region.clone().into_owned()));
+            }
+        }
+        Pass {
+            items,
+            manifest,
+            ctx: *ctx,
+            table,
+            previous,
+            strict,
+            dot: None,
+            current: regions.iter().map(|region| region.origin).collect(),
+            taken,
+            layout: Layout {
+                sections: Vec::new(),
+                regions,
+                discarded: Vec::new(),
+                unmatched: Vec::new(),
+                failed_assertions: Vec::new(),
+                names: Interner::new(),
+                values: Vec::new(),
+            },
+        }
+    }
+
+    fn run(mut self) -> Result<Layout, LayoutError> {
+        let items = self.items;
+        for (item, root) in items.iter().enumerate() {
+            match *root {
+                RootItem::Statement(ref stmt) => {
+                    self.statement(stmt, Site::new(item, None, None), None)?
+                }
+                RootItem::Sections { ref list, .. } => {
+                    self.dot = Some(self.dot.unwrap_or(0));
+                    for (command, cmd) in list.iter().enumerate() {
+                        let site = Site::new(item, Some(command), None);
+                        match *cmd {
+                            SectionCommand::Statement(ref stmt) => {
+                                self.statement(stmt, site, None)?
+                            }
+                            SectionCommand::Command(_) => {}
+                            SectionCommand::OutputSection { .. } => {
+                                self.output_section(cmd, site)?
+                            }
+                        }
+                    }
+                }
+                RootItem::Command(_) | RootItem::Memory { .. } => {}
+            }
+        }
+        self.dot = None;
+        let mut matched = vec![false; self.manifest.sections.len()];
+        for (_, inputs) in self.taken {
+            for &input in inputs {
+                matched[input] = true;
+            }
+        }
+        self.layout.unmatched = (0..matched.len())
+            .filter(|&index| !matched[index])
+            .collect();
+        Ok(self.layout)
+    }
+
+    fn eval(&self, expr: &Expression, site: Site) -> Result<u64, LayoutError> {
+        match expr.eval_in(self, &self.ctx) {
+            Ok(value) => Ok(value),
+            Err(_) if !self.strict => Ok(0),
+            Err(error) => Err(LayoutError::Eval { site, error }),
+        }
+    }
+
+    fn region(&self, section: &str, name: &str) -> Result<usize, LayoutError> {
+        self.layout
+            .regions
+            .iter()
+            .position(|region| region.name == name)
+            .ok_or_else(|| LayoutError::UndefinedRegion {
+                section: section.to_string(),
+                region: name.to_string(),
+            })
+    }
+
+    /// Evaluates a statement, `start` is the address of the output section
+    /// it is in
+    fn statement(
+        &mut self,
+        stmt: &Statement,
+        site: Site,
+        start: Option<u64>,
+    ) -> Result<(), LayoutError> {
+        let (name, operator, expression) = match *stmt {
+            Statement::Assign {
+                ref name,
+                operator,
+                ref expression,
+                ..
+            } => (name, operator, expression),
+            Statement::Hidden {
+                ref name,
+                ref expression,
+                ..
+            } => (name, AssignOperator::Equals, expression),
+            Statement::Provide {
+                ref name,
+                ref expression,
+                ..
+            }
+            | Statement::ProvideHidden {
+                ref name,
+                ref expression,
+                ..
+            } => {
+                // ld defines the symbol if nothing else does and something
+                // refers to it
+                let symbol = self.table.get(name);
+                let used = !symbol.is_some_and(|sym| sym.is_defined())
+                    && !self.manifest.is_defined(name)
+                    && (symbol.is_some_and(|sym| sym.is_referenced())
+                        || self.manifest.may_reference(name));
+                if !used {
+                    return Ok(());
+                }
+                (name, AssignOperator::Equals, expression)
+            }
+            Statement::Assert {
+                ref expr, ref text, ..
+            } => {
+                // values are only final in the last pass
+                if self.strict && self.eval(expr, site)? == 0 {
+                    self.layout.failed_assertions.push(FailedAssertion {
+                        site,
+                        line: None,
+                        message: text.to_string(),
+                    });
+                }
+                return Ok(());
+            }
+        };
+        let mut value = match operator.binary_operator() {
+            None => self.eval(expression, site)?,
+            Some(operator) => self.eval(
+                &Expression::BinaryOp {
+                    left: Box::new(Expression::Ident(Cow::Borrowed(name))),
+                    operator,
+                    right: expression.clone(),
+                },
+                site,
+            )?,
+        };
+        if name != "." {
+            self.layout.set_symbol(name, value);
+            return Ok(());
+        }
+        if let Some(start) = start {
+            if operator.binary_operator().is_none() && is_constant(expression) {
+                value = self.ctx.truncate(start.wrapping_add(value));
+            }
+            let dot = self.dot.unwrap_or(start);
+            if value < dot && self.strict {
+                return Err(LayoutError::Backwards {
+                    site,
+                    from: dot,
+                    to: value,
+                });
+            }
+        }
+        self.dot = Some(value);
+        Ok(())
+    }
+
+    /// Indexes of the input sections the input section command at `site`
+    /// takes, in the order they are placed
+    fn select(&self, site: Site) -> Vec<usize> {
+        self.taken
+            .iter()
+            .find(|&&(command, _)| command == site)
+            .map_or_else(Vec::new, |(_, inputs)| inputs.clone())
+    }
+
+    fn output_section(&mut self, cmd: &SectionCommand, site: Site) -> Result<(), LayoutError> {
+        let (
+            name,
+            vma_address,
+            s_type,
+            lma_address,
+            section_align,
+            subsection_align,
+            content,
+            region,
+            lma_region,
+        ) = match *cmd {
+            SectionCommand::OutputSection {
+                ref name,
+                ref vma_address,
+                ref s_type,
+                ref lma_address,
+                ref section_align,
+                ref subsection_align,
+                ref content,
+                ref region,
+                ref lma_region,
+                ..
+            } => (
+                name,
+                vma_address,
+                s_type,
+                lma_address,
+                section_align,
+                subsection_align,
+                content,
+                region,
+                lma_region,
+            ),
+            _ => return Ok(()),
+        };
+
+        // input sections are assigned before the addresses, the alignment
+        // of the output section depends on them
+        let selected: Vec<Vec<usize>> = (0..content.len())
+            .map(|index| self.select(Site::new(site.item, site.command, Some(index))))
+            .collect();
+        if name == "/DISCARD/" {
+            self.layout.discarded.extend(selected.into_iter().flatten());
+            return Ok(());
+        }
+
+        let allocated = match *s_type {
+            Some(OutputSectionType::DSect)
+            | Some(OutputSectionType::Copy)
+            | Some(OutputSectionType::Info)
+            | Some(OutputSectionType::Overlay) => false,
+            Some(OutputSectionType::NoLoad) | None => true,
+        };
+        let noload = *s_type == Some(OutputSectionType::NoLoad);
+        let region = match *region {
+            Some(ref region) => Some(self.region(name, region)?),
+            None => None,
+        };
+        let lma_region = match *lma_region {
+            Some(ref region) => Some(self.region(name, region)?),
+            None => None,
+        };
+        let subalign = match *subsection_align {
+            Some(ref expr) => Some(self.eval(expr, site)?),
+            None => None,
+        };
+        let manifest = self.manifest;
+        let input_align = |input: &usize| subalign.unwrap_or(manifest.sections[*input].align);
+        let mut align = match *section_align {
+            Some(ref expr) => self.eval(expr, site)?,
+            None => 1,
+        };
+        align = selected
+            .iter()
+            .flatten()
+            .map(input_align)
+            .fold(align, u64::max);
+
+        let outer = self.dot.unwrap_or(0);
+        let vma = match *vma_address {
+            Some(ref expr) => self.eval(expr, site)?,
+            None if !allocated => 0,
+            None => align_up(region.map_or(outer, |index| self.current[index]), align),
+        };
+        let vma = self.ctx.truncate(vma);
+        let at = match *lma_address {
+            Some(ref expr) => Some(self.eval(expr, site)?),
+            None => None,
+        };
+
+        self.dot = Some(vma);
+        let mut inputs = Vec::new();
+        for (index, (cmd, selected)) in content.iter().zip(selected).enumerate() {
+            let site = Site::new(site.item, site.command, Some(index));
+            match *cmd {
+                OutputSectionCommand::Statement(ref stmt) => {
+                    self.statement(stmt, site, Some(vma))?
+                }
+                OutputSectionCommand::Data { ref d_type, .. } => {
+                    self.dot = self.dot.map(|dot| dot.wrapping_add(d_type.size()))
+                }
+                OutputSectionCommand::Fill { .. } => {}
+                OutputSectionCommand::InputSection { .. }
+                | OutputSectionCommand::KeepInputSection { .. } => {
+                    for input in selected {
+                        let address = align_up(self.dot.unwrap_or(vma), input_align(&input));
+                        inputs.push(Placement {
+                            input,
+                            vma: address,
+                        });
+                        self.dot = Some(address.wrapping_add(self.manifest.sections[input].size));
+                    }
+                }
+            }
+        }
+        let end = self.ctx.truncate(self.dot.unwrap_or(vma));
+        let size = end.wrapping_sub(vma);
+
+        let mut lma_region = lma_region;
+        let lma = if let Some(at) = at {
+            at
+        } else if let Some(index) = lma_region {
+            let lma = align_up(self.current[index], align);
+            if allocated && !noload {
+                self.current[index] = lma.wrapping_add(size);
+            }
+            lma
+        } else if vma_address.is_some() || noload || !allocated {
+            vma
+        } else {
+            // keep the distance between VMA and LMA of the previous section
+            // in the same region, or of the previous one if there is no
+            // MEMORY
+            let previous = self.layout.sections.iter().rev().find(|section| {
+                section.allocated
+                    && !section.noload
+                    && match region {
+                        Some(index) => {
+                            section.region.as_deref() == Some(&*self.layout.regions[index].name)
+                        }
+                        None => self.layout.regions.is_empty(),
+                    }
+            });
+            match previous {
+                Some(section) => {
+                    let lma = vma.wrapping_add(section.lma.wrapping_sub(section.vma));
+                    // the load image follows the previous one in its region
+                    lma_region = section.lma_region.as_ref().and_then(|name| {
+                        self.layout
+                            .regions
+                            .iter()
+                            .position(|region| region.name == *name)
+                    });
+                    if let Some(index) = lma_region {
+                        self.current[index] = lma.wrapping_add(size);
+                    }
+                    lma
+                }
+                None => vma,
+            }
+        };
+
+        if allocated {
+            if let Some(index) = region {
+                self.current[index] = end;
+            }
+            self.dot = Some(end);
+        } else {
+            self.dot = Some(outer);
+        }
+        self.layout.sections.push(SectionLayout {
+            name: name.to_string(),
+            site,
+            vma,
+            lma: self.ctx.truncate(lma),
+            size,
+            align,
+            allocated,
+            noload,
+            region: region.map(|index| self.layout.regions[index].name.to_string()),
+            lma_region: lma_region.map(|index| self.layout.regions[index].name.to_string()),
+            inputs,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use layout::*;
+
+    fn compute(script: &str, manifest: &str) -> Result<Layout, LayoutError> {
+        let items = ::parse(script).unwrap();
+        Layout::compute(&items, &Manifest::parse(manifest).unwrap())
+    }
+
+    #[test]
+    fn test_regions() {
+        let layout = compute(
+            "
+            OUTPUT_ARCH(msp430)
+            MEMORY {
+                RAM : ORIGIN = 0x1C00, LENGTH = 0x800
+                ROM : ORIGIN = 0x4400, LENGTH = 0xBB80
+            }
+            SECTIONS {
+                .text : { *(.text) *(.text.*) _etext = .; } > ROM
+                .rodata : { *(.rodata) } > ROM
+                .data : ALIGN(4) { _data = .; *(.data) LONG(0) _edata = .; } > RAM AT> ROM
+                .bss (NOLOAD) : { *(.bss) } > RAM
+                .upper : { *(.upper) } > RAM
+                __stack = ORIGIN(RAM) + LENGTH(RAM);
+                .info 0 (INFO) : { *(.info) }
+            }
+            ",
+            "
+            crt0.o .text 0x41 1
+            main.o .text 0x20 2
+            main.o .text.main 0x10 2
+            main.o .rodata 0x3 1
+            main.o .data 0x6 2
+            main.o .bss 0x40 2
+            main.o .upper 0x2 2
+            main.o .info 0x10 1
+            ",
+        )
+        .unwrap();
+        let sections: Vec<(&str, u64, u64, u64)> = layout
+            .sections
+            .iter()
+            .map(|s| (&*s.name, s.vma, s.lma, s.size))
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                (".text", 0x4400, 0x4400, 0x72),
+                (".rodata", 0x4472, 0x4472, 0x3),
+                (".data", 0x1C00, 0x4478, 0xA),
+                (".bss", 0x1C0A, 0x1C0A, 0x40),
+                (".upper", 0x1C4A, 0x1C4A + 0x4478 - 0x1C00, 0x2),
+                (".info", 0, 0, 0x10),
+            ]
+        );
+        assert_eq!(layout.symbol("_etext"), Some(0x4472));
+        assert_eq!(layout.symbol("_data"), Some(0x1C00));
+        assert_eq!(layout.symbol("_edata"), Some(0x1C0A));
+        assert_eq!(layout.symbol("__stack"), Some(0x2400));
+        let text = layout.section(".text").unwrap();
+        assert_eq!(
+            text.inputs,
+            [
+                Placement {
+                    input: 0,
+                    vma: 0x4400
+                },
+                Placement {
+                    input: 1,
+                    vma: 0x4442
+                },
+                Placement {
+                    input: 2,
+                    vma: 0x4462
+                },
+            ]
+        );
+        assert_eq!(layout.section(".data").unwrap().align, 4);
+        assert!(layout.section(".bss").unwrap().noload);
+        assert!(!layout.section(".info").unwrap().allocated);
+    }
+
+    #[test]
+    fn test_inherited_lma() {
+        let layout = compute(
+            "
+            MEMORY {
+                RAM : ORIGIN = 0x1000, LENGTH = 0x1000
+                ROM : ORIGIN = 0x4000, LENGTH = 0x1000
+            }
+            SECTIONS {
+                .data : { *(.data) } > RAM AT> ROM
+                .data2 : { *(.data2) } > RAM
+                .rodata : { *(.rodata) } > ROM
+            }
+            ",
+            "
+            main.o .data 0x10 1
+            main.o .data2 0x20 1
+            main.o .rodata 0x8 1
+            ",
+        )
+        .unwrap();
+        let data2 = layout.section(".data2").unwrap();
+        assert_eq!((data2.vma, data2.lma), (0x1010, 0x4010));
+        assert_eq!(data2.lma_region.as_deref(), Some("ROM"));
+        assert_eq!(layout.section(".rodata").unwrap().vma, 0x4030);
+    }
+
+    #[test]
+    fn test_location_counter() {
+        let layout = compute(
+            "
+            SECTIONS {
+                . = 0x8000;
+                .vectors 0xFF80 : { KEEP(*(.vectors)) }
+                .text : SUBALIGN(8) { *(.text) . = ALIGN(0x10); _etext = .; }
+                .fill : { BYTE(1) SHORT(2) . = 0x10; QUAD(3) }
+                . += 0x100;
+                .stack : { . += _stack_size; }
+                PROVIDE(_stack_size = 0x80);
+                PROVIDE(_unused = 1);
+                PROVIDE(_start = 0x8000);
+                PROVIDE(_heap = .);
+                _end = ADDR(.later) + SIZEOF(.later);
+                .later : { *(.later) }
+                /DISCARD/ : { *(.comment) }
+            }
+            ",
+            "
+            a.o .vectors 0x80
+            a.o .text 0x3
+            b.o .text 0x5
+            a.o .later 0x7
+            a.o .comment 0x20
+            a.o .debug 0x20
+            defines _start
+            references _heap
+            ",
+        )
+        .unwrap();
+        let sections: Vec<(&str, u64, u64)> = layout
+            .sections
+            .iter()
+            .map(|s| (&*s.name, s.vma, s.size))
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                (".vectors", 0xFF80, 0x80),
+                (".text", 0x10000, 0x10),
+                (".fill", 0x10010, 0x18),
+                (".stack", 0x10128, 0x80),
+                (".later", 0x101A8, 0x7),
+            ]
+        );
+        assert_eq!(layout.section(".text").unwrap().inputs[1].vma, 0x10008);
+        assert_eq!(layout.symbol("_etext"), Some(0x10010));
+        assert_eq!(layout.symbol("_stack_size"), Some(0x80));
+        assert_eq!(layout.symbol("_unused"), None);
+        assert_eq!(layout.symbol("_start"), None);
+        assert_eq!(layout.symbol("_heap"), Some(0x101A8));
+        assert_eq!(layout.symbol("_end"), Some(0x101AF));
+        assert_eq!(layout.discarded, [4]);
+        assert_eq!(layout.unmatched, [5]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_provide() {
+        use std::fs::File;
+        use std::io::Read;
+
+        let mut contents = String::new();
+        File::open("tests/msp430f5529.ld")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        let items = ::parse(&contents).unwrap();
+        let manifest = Manifest::parse(
+            "
+            crt0.o .text 0x40 2
+            main.o .data 0x10 2
+            main.o .bss 0x40 2
+            defines _start
+            ",
+        )
+        .unwrap();
+        let layout = Layout::compute(&items, &manifest).unwrap();
+        assert_eq!(layout.symbol("__stack"), Some(0x4400));
+        assert_eq!(layout.symbol("__bsssize"), Some(0x40));
+        assert_eq!(layout.symbol("__romdatacopysize"), Some(0x10));
+        assert_eq!(layout.symbol("_start"), None);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            compute("SECTIONS { .text : { . = 4; . = 2; } }", ""),
+            Err(LayoutError::Backwards {
+                site: Site::new(0, Some(0), Some(1)),
+                from: 4,
+                to: 2,
+            })
+        );
+        assert_eq!(
+            compute("SECTIONS { .text : { *(.text) } > ROM }", ""),
+            Err(LayoutError::UndefinedRegion {
+                section: ".text".into(),
+                region: "ROM".into(),
+            })
+        );
+        assert_eq!(
+            compute("SECTIONS { .text : { _x = _undefined; } }", ""),
+            Err(LayoutError::Eval {
+                site: Site::new(0, Some(0), Some(0)),
+                error: EvalError::UndefinedSymbol("_undefined".into()),
+            })
+        );
+        assert_eq!(
+            compute("SECTIONS { .a : { . += _b; } _b = SIZEOF(.a) + 1; }", ""),
+            Err(LayoutError::NotConverging)
+        );
+    }
+
+    #[test]
+    fn test_assertions() {
+        let script = "
+            ASSERT(1, \"outside\");
+            SECTIONS {
+                .text 0x100 : {
+                    *(.text)
+                    ASSERT(. == 0x100, \"before the input\");
+                    ASSERT(. == 0x110, \"after the input\");
+                }
+                .upper : { *(.upper) }
+                ASSERT(SIZEOF(.upper) == 0, \".upper is not empty\");
+                ASSERT(_end < 0x200, \"too large\");
+                _end = .;
+            }
+            ";
+        let mut layout = compute(script, "a.o .text 0x10\na.o .upper 0x2").unwrap();
+        assert_eq!(
+            layout.failed_assertions,
+            [
+                FailedAssertion {
+                    site: Site::new(1, Some(0), Some(1)),
+                    line: None,
+                    message: "before the input".into(),
+                },
+                FailedAssertion {
+                    site: Site::new(1, Some(2), None),
+                    line: None,
+                    message: ".upper is not empty".into(),
+                },
+            ]
+        );
+        layout.locate_assertions(script);
+        let lines: Vec<Option<usize>> = layout
+            .failed_assertions
+            .iter()
+            .map(|assertion| assertion.line)
+            .collect();
+        assert_eq!(lines, [Some(6), Some(10)]);
+        assert_eq!(
+            layout.failed_assertions[1].to_string(),
+            "line 10: .upper is not empty"
+        );
+        assert_eq!(
+            compute("ASSERT(. > 0, \"x\");", ""),
+            Err(LayoutError::Eval {
+                site: Site::new(0, None, None),
+                error: EvalError::UndefinedSymbol(".".into()),
+            })
+        );
+    }
+}
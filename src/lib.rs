@@ -17,32 +17,119 @@
 //! }
 //! ```
 //!
+//! # Serialization
+//!
+//! With the `serde` feature enabled, all AST types implement `Serialize` and
+//! `Deserialize`. The JSON representation is stable and follows these rules:
+//!
+//! - structs are objects with one key per field, e.g.
+//!   `{"name": "RAM", "origin": 9216, "length": 8192, "comments": []}`;
+//! - enums that carry data are objects with a `type` key naming the variant
+//!   and a `value` key holding its content, e.g.
+//!   `{"type": "Number", "value": 4096}` or
+//!   `{"type": "Ident", "value": "_stack"}`;
+//! - enums without data, such as operators, are strings naming the variant,
+//!   e.g. `"Plus"` or `"NoLoad"`;
+//! - numbers are JSON integers, optional values are `null` when absent.
+//!
+//! ```
+//! # #[cfg(feature = "serde")]
+//! # extern crate serde_json;
+//! # extern crate ldscript_parser as lds;
+//! # fn main() {
+//! # #[cfg(feature = "serde")]
+//! # {
+//! let items = lds::parse("_stack = 0x4400;").unwrap();
+//! let json = serde_json::to_string(&items).unwrap();
+//! assert_eq!(
+//!     json,
+//!     r#"[{"type":"Statement","value":{"type":"Assign","value":{"name":"_stack","operator":"Equals","expression":{"type":"Number","value":17408},"comments":[]}}}]"#
+//! );
+//! # }
+//! # }
+//! ```
+//!
+//! # `no_std`
+//!
+//! The parser only needs `alloc`. Without the default `std` feature the
+//! crate is `#![no_std]`, the `ldfmt` binary is not built and
+//! [`ParseOptions`] are shared by all threads instead of being set per
+//! thread. The parser is then meant to run on one thread:
+//! [`parse_with_options`] and an [`ItemIter`] with options panic if they run
+//! while another one does, and a [`parse`] in the meantime uses the other's
+//! limits.
+//!
+//! # Fuzzing
+//!
+//! The `arbitrary` feature implements `Arbitrary` for the AST types. The
+//! generated trees only use names, patterns and numbers the parser accepts,
+//! so they can be printed and parsed back into an equal tree.
+//!
 //! # References
 //!
 //! - [GNU binutils documentation](https://sourceware.org/binutils/docs/ld/Scripts.html#Scripts)
 //!
 
+#![no_std]
+
+#[macro_use]
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
+#[cfg(feature = "arbitrary")]
+extern crate arbitrary;
 extern crate nom;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
 
 #[macro_use]
 mod utils;
 #[macro_use]
 mod whitespace;
+pub mod builder;
 mod commands;
+pub mod eval;
 mod expressions;
+pub mod fold;
+pub mod format;
+#[cfg(feature = "arbitrary")]
+mod fuzz;
 mod idents;
+pub mod incremental;
+pub mod intern;
+mod iter;
+pub mod layout;
+pub mod loader;
+pub mod manifest;
+pub mod matching;
 mod memory;
 mod numbers;
+mod options;
+pub mod orphans;
+pub mod print;
 mod script;
 mod sections;
 mod statements;
+pub mod symbols;
+pub mod usage;
+pub mod visit;
+pub mod visit_mut;
+
+use alloc::string::String;
+use alloc::vec::Vec;
 
 pub use commands::Command;
+pub use commands::InsertOrder;
 pub use expressions::BinaryOperator;
 pub use expressions::Expression;
 pub use expressions::UnaryOperator;
+pub use iter::ItemIter;
 pub use memory::Region;
+pub use options::ParseOptions;
 pub use script::RootItem;
+pub use script::Script;
 pub use sections::DataType;
 pub use sections::OutputSectionCommand;
 pub use sections::OutputSectionConstraint;
@@ -51,9 +138,26 @@ pub use sections::SectionCommand;
 pub use sections::SectionPattern;
 pub use statements::AssignOperator;
 pub use statements::Statement;
+pub use whitespace::Comment;
+pub use whitespace::CommentPosition;
 
 /// Parses the string that contains a linker script
-pub fn parse(ldscript: &str) -> Result<Vec<RootItem>, String> {
+pub fn parse(ldscript: &str) -> Result<Vec<RootItem<'static>>, String> {
+    parse_borrowed(ldscript).map(|items| items.into_iter().map(RootItem::into_owned).collect())
+}
+
+/// Parses the string that contains a linker script with non-default
+/// settings, e.g. a lower nesting limit for untrusted input
+pub fn parse_with_options(
+    ldscript: &str,
+    options: &ParseOptions,
+) -> Result<Vec<RootItem<'static>>, String> {
+    options::with_options(options, || parse(ldscript))
+}
+
+/// Parses the string that contains a linker script without copying
+/// identifiers, patterns and strings out of it
+pub fn parse_borrowed(ldscript: &str) -> Result<Vec<RootItem<'_>>, String> {
     match script::parse(ldscript) {
         Ok((_, result)) => Ok(result),
         //TODO: add error handling
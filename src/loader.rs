@@ -0,0 +1,648 @@
+//! Resolution of `INCLUDE` commands.
+//!
+//! The parser leaves `INCLUDE file` as a command. A [`Loader`] reads the
+//! script and every file it includes through a [`FileSystem`], and splices
+//! the included items in place of the command: a file included at the top
+//! level gives root items, one included inside SECTIONS gives section
+//! commands. [`Loaded::file`] tells which file an item came from.
+//!
+//! A file is looked up as written, then in the directory of the including
+//! script, then in the `-L` directories given to the loader and last in the
+//! `SEARCH_DIR` directories of the commands read so far. The paths are
+//! normalized, and like ld the loader gives up on includes nested more than
+//! 10 deep.
+//!
+//! ```
+//! extern crate ldscript_parser as lds;
+//!
+//! use lds::loader::{Loader, MemoryFileSystem};
+//! use lds::symbols::Site;
+//!
+//! fn main() {
+//!     let fs = MemoryFileSystem::new()
+//!         .file("main.ld", "INCLUDE memory.ld\nSECTIONS { INCLUDE text.ld }")
+//!         .file("ld/memory.ld", "MEMORY { ROM : ORIGIN = 0, LENGTH = 1K }")
+//!         .file("ld/text.ld", ".text : { *(.text) } > ROM");
+//!     let loaded = Loader::new(fs).search_path("ld").load("main.ld").unwrap();
+//!     assert_eq!(loaded.items.len(), 2);
+//!     let site = Site { item: 1, command: Some(0), content: None };
+//!     assert_eq!(loaded.file(site), Some("ld/text.ld"));
+//! }
+//! ```
+
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeMap as Map;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use commands::Command;
+use core::fmt;
+use expressions::Expression;
+use script::{self, RootItem};
+use sections::SectionCommand;
+#[cfg(feature = "std")]
+use std::collections::HashMap as Map;
+#[cfg(feature = "std")]
+use std::error::Error;
+use symbols::Site;
+
+/// Access to the files of a script.
+pub trait FileSystem {
+    /// Returns the contents of the file at `path`, `None` if it cannot be
+    /// read
+    fn read(&self, path: &str) -> Option<String>;
+
+    /// Returns the same path for every way of naming the file at `path`, to
+    /// find include cycles. The default returns `path`, which the loader has
+    /// already rid of `.` and `..`.
+    fn canonicalize(&self, path: &str) -> String {
+        path.to_string()
+    }
+}
+
+/// The files of the operating system.
+#[cfg(feature = "std")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdFileSystem;
+
+#[cfg(feature = "std")]
+impl FileSystem for StdFileSystem {
+    fn read(&self, path: &str) -> Option<String> {
+        std::fs::read_to_string(path).ok()
+    }
+
+    fn canonicalize(&self, path: &str) -> String {
+        std::fs::canonicalize(path)
+            .ok()
+            .and_then(|path| path.to_str().map(String::from))
+            .unwrap_or_else(|| path.to_string())
+    }
+}
+
+/// Files kept in memory, for tests and sandboxed callers.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryFileSystem {
+    files: Map<String, String>,
+}
+
+impl MemoryFileSystem {
+    pub fn new() -> Self {
+        MemoryFileSystem::default()
+    }
+
+    /// Adds a file
+    pub fn file<P: Into<String>, T: Into<String>>(mut self, path: P, text: T) -> Self {
+        self.files.insert(path.into(), text.into());
+        self
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn read(&self, path: &str) -> Option<String> {
+        self.files.get(path).cloned()
+    }
+}
+
+/// Failure of [`Loader::load`].
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum LoadError {
+    /// A file is in none of the places it is looked up
+    NotFound {
+        file: String,
+        /// The including file, `None` for the script itself
+        included_from: Option<String>,
+        /// Paths tried, in order
+        searched: Vec<String>,
+    },
+    /// A file includes itself, directly or through other files
+    Cycle {
+        /// Paths of the files from the first one of the cycle back to it
+        chain: Vec<String>,
+    },
+    /// Includes are nested deeper than [`MAX_INCLUDE_DEPTH`]
+    TooDeep {
+        /// The file whose include would go too deep
+        file: String,
+    },
+    /// A file is not a valid script
+    Parse { file: String, error: String },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::NotFound {
+                ref file,
+                ref included_from,
+                ref searched,
+            } => {
+                write!(f, "cannot find `{}`", file)?;
+                if let Some(ref includer) = *included_from {
+                    write!(f, " included from `{}`", includer)?;
+                }
+                f.write_str(", tried ")?;
+                for (i, path) in searched.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { ", " };
+                    write!(f, "{}`{}`", separator, path)?;
+                }
+                Ok(())
+            }
+            LoadError::Cycle { ref chain } => {
+                f.write_str("include cycle: ")?;
+                for (i, path) in chain.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { " -> " };
+                    write!(f, "{}`{}`", separator, path)?;
+                }
+                Ok(())
+            }
+            LoadError::TooDeep { ref file } => {
+                write!(f, "`{}`: includes nested too deeply", file)
+            }
+            LoadError::Parse {
+                ref file,
+                ref error,
+            } => write!(f, "`{}`: {}", file, error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for LoadError {}
+
+/// A script with its includes spliced in.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Loaded {
+    pub items: Vec<RootItem<'static>>,
+    /// Paths of the files read, the script first
+    pub files: Vec<String>,
+    /// Index in `files` of the file of each root item
+    item_files: Vec<usize>,
+    /// Index in `files` of the file of each command of a SECTIONS block,
+    /// empty for the other root items
+    command_files: Vec<Vec<usize>>,
+}
+
+impl Loaded {
+    /// Returns the path of the file the item or command at `site` comes from
+    pub fn file(&self, site: Site) -> Option<&str> {
+        let index = match site.command {
+            Some(command) => *self.command_files.get(site.item)?.get(command)?,
+            None => *self.item_files.get(site.item)?,
+        };
+        Some(&self.files[index])
+    }
+}
+
+/// Deepest nesting of included files, the limit of ld.
+pub const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// Reads a script and the files it includes.
+#[derive(Debug, Clone)]
+pub struct Loader<F> {
+    fs: F,
+    search_paths: Vec<String>,
+}
+
+impl<F: FileSystem> Loader<F> {
+    pub fn new(fs: F) -> Self {
+        Loader {
+            fs,
+            search_paths: Vec::new(),
+        }
+    }
+
+    /// Adds a directory to look up files in, like `-L`
+    pub fn search_path<P: Into<String>>(mut self, dir: P) -> Self {
+        self.search_paths.push(dir.into());
+        self
+    }
+
+    /// Reads the script at `path` and resolves its includes
+    pub fn load(&self, path: &str) -> Result<Loaded, LoadError> {
+        let mut load = Load {
+            loader: self,
+            search_dirs: Vec::new(),
+            stack: Vec::new(),
+            keys: Vec::new(),
+            loaded: Loaded {
+                items: Vec::new(),
+                files: Vec::new(),
+                item_files: Vec::new(),
+                command_files: Vec::new(),
+            },
+        };
+        load.root(path)?;
+        Ok(load.loaded)
+    }
+}
+
+/// State of a [`Loader::load`] call.
+struct Load<'l, F: 'l> {
+    loader: &'l Loader<F>,
+    /// Directories of the `SEARCH_DIR` commands read so far
+    search_dirs: Vec<String>,
+    /// Files being read, the script first
+    stack: Vec<String>,
+    /// Canonical paths of the files being read
+    keys: Vec<String>,
+    loaded: Loaded,
+}
+
+impl<'l, F: FileSystem> Load<'l, F> {
+    /// Paths to try for `file`, in order
+    fn candidates(&self, file: &str) -> Vec<String> {
+        if file.starts_with('/') {
+            return vec![normalize(file)];
+        }
+        let includer_dir = self
+            .stack
+            .last()
+            .and_then(|includer| includer.rfind('/').map(|end| &includer[..end + 1]));
+        let mut paths = vec![normalize(file)];
+        let dirs = includer_dir
+            .into_iter()
+            .chain(self.loader.search_paths.iter().map(|dir| &**dir))
+            .chain(self.search_dirs.iter().map(|dir| &**dir));
+        for dir in dirs {
+            let path = normalize(&join(dir, file));
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        paths
+    }
+
+    /// Finds and reads `file`, then marks it as being read
+    fn open(&mut self, file: &str) -> Result<(usize, String), LoadError> {
+        let searched = self.candidates(file);
+        let found = searched
+            .iter()
+            .find_map(|path| self.loader.fs.read(path).map(|text| (path, text)));
+        let (path, text) = match found {
+            Some(found) => found,
+            None => {
+                return Err(LoadError::NotFound {
+                    file: file.to_string(),
+                    included_from: self.stack.last().cloned(),
+                    searched,
+                })
+            }
+        };
+        let key = self.loader.fs.canonicalize(path);
+        if let Some(start) = self.keys.iter().position(|open| *open == key) {
+            let mut chain = self.stack[start..].to_vec();
+            chain.push(path.clone());
+            return Err(LoadError::Cycle { chain });
+        }
+        if let (Some(includer), true) = (self.stack.last(), self.stack.len() > MAX_INCLUDE_DEPTH) {
+            return Err(LoadError::TooDeep {
+                file: includer.clone(),
+            });
+        }
+        self.stack.push(path.clone());
+        self.keys.push(key);
+        let files = &mut self.loaded.files;
+        let index = match files.iter().position(|read| read == path) {
+            Some(index) => index,
+            None => {
+                files.push(path.clone());
+                files.len() - 1
+            }
+        };
+        Ok((index, text))
+    }
+
+    /// Reads a file of root items
+    fn root(&mut self, file: &str) -> Result<(), LoadError> {
+        let (index, text) = self.open(file)?;
+        let items = match script::parse(&text) {
+            Ok((rest, items)) => self.check_rest(&text, rest, items)?,
+            Err(e) => return Err(self.parse_error(format!("Parsing failed, error: {:?}", e))),
+        };
+        for item in items {
+            self.root_item(item.into_owned(), index)?;
+        }
+        self.stack.pop();
+        self.keys.pop();
+        Ok(())
+    }
+
+    fn root_item(&mut self, item: RootItem<'static>, index: usize) -> Result<(), LoadError> {
+        let (item, commands) = match item {
+            RootItem::Command(Command::Include { file, .. }) => return self.root(&file),
+            RootItem::Sections { list, comments } => {
+                let mut commands = Vec::new();
+                let mut files = Vec::new();
+                for command in list {
+                    self.section_command(command, index, &mut commands, &mut files)?;
+                }
+                let item = RootItem::Sections {
+                    list: commands,
+                    comments,
+                };
+                (item, files)
+            }
+            item => {
+                if let RootItem::Command(ref command) = item {
+                    self.search_dir(command);
+                }
+                (item, Vec::new())
+            }
+        };
+        self.loaded.items.push(item);
+        self.loaded.item_files.push(index);
+        self.loaded.command_files.push(commands);
+        Ok(())
+    }
+
+    fn section_command(
+        &mut self,
+        command: SectionCommand<'static>,
+        index: usize,
+        commands: &mut Vec<SectionCommand<'static>>,
+        files: &mut Vec<usize>,
+    ) -> Result<(), LoadError> {
+        let file = match command {
+            SectionCommand::Command(Command::Include { file, .. }) => file,
+            command => {
+                commands.push(command);
+                files.push(index);
+                return Ok(());
+            }
+        };
+        let (index, text) = self.open(&file)?;
+        let included = match script::parse_section_commands(&text) {
+            Ok((rest, included)) => self.check_rest(&text, rest, included)?,
+            Err(e) => return Err(self.parse_error(format!("Parsing failed, error: {:?}", e))),
+        };
+        for command in included {
+            self.section_command(command.into_owned(), index, commands, files)?;
+        }
+        self.stack.pop();
+        self.keys.pop();
+        Ok(())
+    }
+
+    /// Adds the directory of a `SEARCH_DIR` command
+    fn search_dir(&mut self, command: &Command) {
+        if let Command::Call {
+            ref name,
+            ref arguments,
+            ..
+        } = *command
+        {
+            if let (Some(Expression::Ident(dir)), true) = (arguments.first(), name == "SEARCH_DIR")
+            {
+                // A leading `=` stands for the sysroot, which is `/` here
+                let dir = dir.strip_prefix('=').unwrap_or(dir);
+                self.search_dirs.push(dir.to_string());
+            }
+        }
+    }
+
+    /// Fails if the parser stopped before the end of `text`
+    fn check_rest<T>(&self, text: &str, rest: &str, parsed: T) -> Result<T, LoadError> {
+        if rest.is_empty() {
+            return Ok(parsed);
+        }
+        let line = text[..text.len() - rest.len()].matches('\n').count() + 1;
+        let unexpected = rest.lines().next().unwrap_or_default().trim();
+        Err(self.parse_error(format!("unexpected `{}` on line {}", unexpected, line)))
+    }
+
+    /// Error for the file being read
+    fn parse_error(&self, error: String) -> LoadError {
+        LoadError::Parse {
+            file: self.stack.last().cloned().unwrap_or_default(),
+            error,
+        }
+    }
+}
+
+/// Drops the `.` components of a path and resolves the `..` ones
+fn normalize(path: &str) -> String {
+    let absolute = path.starts_with('/');
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." if parts.last().is_some_and(|&last| last != "..") => {
+                parts.pop();
+            }
+            ".." if absolute => {}
+            part => parts.push(part),
+        }
+    }
+    let path = parts.join("/");
+    if absolute {
+        format!("/{}", path)
+    } else if path.is_empty() {
+        ".".to_string()
+    } else {
+        path
+    }
+}
+
+fn join(dir: &str, file: &str) -> String {
+    if dir.is_empty() || dir.ends_with('/') {
+        format!("{}{}", dir, file)
+    } else {
+        format!("{}/{}", dir, file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use loader::*;
+
+    fn site(item: usize, command: Option<usize>) -> Site {
+        Site {
+            item,
+            command,
+            content: None,
+        }
+    }
+
+    #[test]
+    fn test_load() {
+        let fs = MemoryFileSystem::new()
+            .file(
+                "scripts/main.ld",
+                "
+                SEARCH_DIR(\"=/usr/lib/ld\")
+                INCLUDE memory.ld
+                SECTIONS {
+                    .vectors : { KEEP(*(.vectors)) } > ROM
+                    INCLUDE text.ld
+                    .bss : { *(.bss) } > RAM
+                }
+                ",
+            )
+            .file(
+                "scripts/memory.ld",
+                "
+                MEMORY {
+                    RAM : ORIGIN = 0x1C00, LENGTH = 2K
+                    ROM : ORIGIN = 0x4400, LENGTH = 0xBB80
+                }
+                INCLUDE symbols.ld
+                ",
+            )
+            .file("common/symbols.ld", "_stack = ORIGIN(RAM) + LENGTH(RAM);")
+            .file(
+                "/usr/lib/ld/text.ld",
+                ".text : { *(.text) } > ROM\nINCLUDE rodata.ld",
+            )
+            .file("common/rodata.ld", ".rodata : { *(.rodata) } > ROM");
+        let loaded = Loader::new(fs)
+            .search_path("common")
+            .load("scripts/main.ld")
+            .unwrap();
+
+        let names: Vec<&str> = loaded
+            .items
+            .iter()
+            .flat_map(|item| match *item {
+                RootItem::Sections { ref list, .. } => list
+                    .iter()
+                    .filter_map(|command| match *command {
+                        SectionCommand::OutputSection { ref name, .. } => Some(&**name),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            })
+            .collect();
+        assert_eq!(names, [".vectors", ".text", ".rodata", ".bss"]);
+        assert_eq!(loaded.items.len(), 4);
+        assert!(matches!(loaded.items[1], RootItem::Memory { .. }));
+        assert!(matches!(loaded.items[2], RootItem::Statement(_)));
+
+        assert_eq!(
+            loaded.files,
+            [
+                "scripts/main.ld",
+                "scripts/memory.ld",
+                "common/symbols.ld",
+                "/usr/lib/ld/text.ld",
+                "common/rodata.ld",
+            ]
+        );
+        assert_eq!(loaded.file(site(0, None)), Some("scripts/main.ld"));
+        assert_eq!(loaded.file(site(1, None)), Some("scripts/memory.ld"));
+        assert_eq!(loaded.file(site(2, None)), Some("common/symbols.ld"));
+        assert_eq!(loaded.file(site(3, Some(0))), Some("scripts/main.ld"));
+        assert_eq!(loaded.file(site(3, Some(1))), Some("/usr/lib/ld/text.ld"));
+        assert_eq!(loaded.file(site(3, Some(2))), Some("common/rodata.ld"));
+        assert_eq!(loaded.file(site(3, Some(3))), Some("scripts/main.ld"));
+        assert_eq!(loaded.file(site(3, Some(4))), None);
+        assert_eq!(loaded.file(site(4, None)), None);
+    }
+
+    #[test]
+    fn test_errors() {
+        let fs = MemoryFileSystem::new()
+            .file("a.ld", "INCLUDE b.ld")
+            .file("lib/b.ld", "SECTIONS { INCLUDE c.ld }")
+            .file("lib/c.ld", "INCLUDE a.ld")
+            .file("d.ld", "SEARCH_DIR(lib)\nINCLUDE missing.ld")
+            .file("e.ld", "SECTIONS { INCLUDE empty.ld }")
+            .file("empty.ld", "  /* nothing */\n")
+            .file("f.ld", "INCLUDE lib/bad.ld")
+            .file(
+                "lib/bad.ld",
+                "_start = 0;\nSECTIONS {\n  .text : { *(.text) \n}",
+            );
+        let loader = Loader::new(fs).search_path("lib/");
+
+        let error = loader.load("a.ld").unwrap_err();
+        assert_eq!(
+            error,
+            LoadError::Cycle {
+                chain: vec![
+                    "a.ld".to_string(),
+                    "lib/b.ld".to_string(),
+                    "lib/c.ld".to_string(),
+                    "a.ld".to_string(),
+                ],
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "include cycle: `a.ld` -> `lib/b.ld` -> `lib/c.ld` -> `a.ld`"
+        );
+
+        let error = loader.load("d.ld").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "cannot find `missing.ld` included from `d.ld`, tried `missing.ld`, `lib/missing.ld`"
+        );
+        assert_eq!(
+            loader.load("none.ld").unwrap_err().to_string(),
+            "cannot find `none.ld`, tried `none.ld`, `lib/none.ld`"
+        );
+
+        let error = loader.load("f.ld").unwrap_err();
+        assert_eq!(
+            error,
+            LoadError::Parse {
+                file: "lib/bad.ld".to_string(),
+                error: "unexpected `SECTIONS {` on line 2".to_string(),
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "`lib/bad.ld`: unexpected `SECTIONS {` on line 2"
+        );
+
+        let loaded = loader.load("e.ld").unwrap();
+        assert!(matches!(loaded.items[0], RootItem::Sections { ref list, .. } if list.is_empty()));
+    }
+
+    #[test]
+    fn test_nesting() {
+        let mut fs = MemoryFileSystem::new()
+            .file("x/a.ld", "INCLUDE ../x/./a.ld")
+            .file("d11.ld", "");
+        for depth in 0..11 {
+            let text = format!("INCLUDE d{}.ld", depth + 1);
+            fs = fs.file(format!("d{}.ld", depth), text);
+        }
+        let loader = Loader::new(fs);
+
+        assert_eq!(
+            loader.load("x/a.ld").unwrap_err(),
+            LoadError::Cycle {
+                chain: vec!["x/a.ld".to_string(), "x/a.ld".to_string()],
+            }
+        );
+        assert!(loader.load("d1.ld").is_ok());
+        let error = loader.load("d0.ld").unwrap_err();
+        assert_eq!(
+            error,
+            LoadError::TooDeep {
+                file: "d10.ld".to_string(),
+            }
+        );
+        assert_eq!(error.to_string(), "`d10.ld`: includes nested too deeply");
+
+        assert_eq!(normalize("./a/../b/./c.ld"), "b/c.ld");
+        assert_eq!(normalize("../../a.ld"), "../../a.ld");
+        assert_eq!(normalize("/../usr//lib/../a.ld"), "/usr/a.ld");
+        assert_eq!(normalize("a/.."), ".");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_std_cycle() {
+        use std::fs;
+
+        let dir = std::env::temp_dir().join(format!("ldscript-loader-{}", std::process::id()));
+        fs::create_dir_all(dir.join("x")).unwrap();
+        fs::write(dir.join("x/a.ld"), "INCLUDE ../x/a.ld").unwrap();
+        let path = dir.join("x/a.ld").to_str().unwrap().to_string();
+        let error = Loader::new(StdFileSystem).load(&path).unwrap_err();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(error, LoadError::Cycle { ref chain } if chain.len() == 2));
+    }
+}
@@ -0,0 +1,342 @@
+//! Input sections that a layout is computed for.
+//!
+//! A [`Manifest`] lists the sections of the input files with their sizes
+//! and alignments, the information the linker would read from the object
+//! files. It can be built in code or parsed from a text with one section per
+//! line:
+//!
+//! ```text
+//! # file             section  size   align  flags
+//! crt0.o             .text    0x40   2
+//! libc.a(printf.o)   .text    0x1C2  2
+//! main.o             .data    8      2
+//! main.o             .bss     2K
+//! main.o             .persist 4      2      WA
+//! defines            main
+//! references         __stack
+//! ```
+//!
+//! Lines with `defines` or `references` and a name list the symbols the
+//! input files define or refer to. Archive members are written as
+//! `archive(member)`. Numbers use the syntax of the linker script, the
+//! alignment is 1 if it is left out. The flags are the letters readelf
+//! prints, see [`SectionFlags::parse`], and are guessed from the section name
+//! if they are left out. Everything after a `#` is a comment.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use numbers::number;
+
+/// ELF flags of an input section, which decide where ld puts it if no
+/// input section command takes it.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct SectionFlags {
+    /// Takes up memory when the program runs, `SHF_ALLOC`
+    pub alloc: bool,
+    /// `SHF_WRITE`
+    pub write: bool,
+    /// `SHF_EXECINSTR`
+    pub exec: bool,
+    /// Thread local storage, `SHF_TLS`
+    pub tls: bool,
+    /// Has contents in the file, false for `SHT_NOBITS` sections like `.bss`
+    pub contents: bool,
+}
+
+impl SectionFlags {
+    /// Parses the letters readelf uses for the flags, `W`, `A`, `X` and
+    /// `T`, with `N` for a section without contents. Other readelf letters
+    /// are accepted and ignored.
+    pub fn parse(letters: &str) -> Option<SectionFlags> {
+        let mut flags = SectionFlags {
+            contents: true,
+            ..SectionFlags::default()
+        };
+        for letter in letters.chars() {
+            match letter {
+                'W' => flags.write = true,
+                'A' => flags.alloc = true,
+                'X' => flags.exec = true,
+                'T' => flags.tls = true,
+                'N' => flags.contents = false,
+                'M' | 'S' | 'I' | 'L' | 'O' | 'G' | 'C' | 'E' | 'o' | 'p' | 'x' => {}
+                _ => return None,
+            }
+        }
+        Some(flags)
+    }
+
+    /// The flags compilers give a section with this name
+    pub fn from_name(name: &str) -> SectionFlags {
+        let non_alloc = [
+            ".debug",
+            ".zdebug",
+            ".stab",
+            ".line",
+            ".comment",
+            ".gnu.attributes",
+        ];
+        if non_alloc.iter().any(|prefix| name.starts_with(prefix))
+            || name.ends_with(".attributes")
+            || name == ".note.GNU-stack"
+        {
+            return SectionFlags {
+                contents: true,
+                ..SectionFlags::default()
+            };
+        }
+        let (write, exec, tls, contents) = name
+            .split('.')
+            .filter_map(|part| match part {
+                "text" | "lowtext" | "init" | "fini" | "plt" => Some((false, true, false, true)),
+                "rodata" | "const" => Some((false, false, false, true)),
+                "data" | "sdata" | "persistent" | "init_array" | "fini_array" | "preinit_array"
+                | "ctors" | "dtors" | "got" => Some((true, false, false, true)),
+                "bss" | "sbss" | "noinit" | "heap" | "stack" => Some((true, false, false, false)),
+                "tdata" => Some((true, false, true, true)),
+                "tbss" => Some((true, false, true, false)),
+                _ => None,
+            })
+            .next()
+            .unwrap_or((false, false, false, true));
+        SectionFlags {
+            alloc: true,
+            write,
+            exec,
+            tls,
+            contents,
+        }
+    }
+}
+
+/// A section of an input file.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct InputSection {
+    /// Path of the object file, or of the archive for archive members
+    pub file: String,
+    /// Name of the member if the file is an archive
+    pub member: Option<String>,
+    /// Section name, e.g. `.text.main`
+    pub name: String,
+    pub size: u64,
+    pub align: u64,
+    pub flags: SectionFlags,
+}
+
+impl InputSection {
+    /// Section of a plain object file with an alignment of 1 and the flags
+    /// its name suggests
+    pub fn new<F: Into<String>, N: Into<String>>(file: F, name: N, size: u64) -> Self {
+        let name = name.into();
+        InputSection {
+            file: file.into(),
+            member: None,
+            flags: SectionFlags::from_name(&name),
+            name,
+            size,
+            align: 1,
+        }
+    }
+
+    /// Sets the archive member the section comes from
+    pub fn member<M: Into<String>>(mut self, member: M) -> Self {
+        self.member = Some(member.into());
+        self
+    }
+
+    /// Sets the alignment
+    pub fn align(mut self, align: u64) -> Self {
+        self.align = align;
+        self
+    }
+
+    /// Sets the flags
+    pub fn flags(mut self, flags: SectionFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+}
+
+/// Input sections in command line order, and the symbols of the input
+/// files.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct Manifest {
+    pub sections: Vec<InputSection>,
+    /// Symbols an input file defines
+    pub defined: Vec<String>,
+    /// Symbols the input files refer to without defining them. If the list
+    /// is empty the references are not known.
+    pub referenced: Vec<String>,
+}
+
+fn value(line: usize, text: &str) -> Result<u64, String> {
+    match number(text) {
+        Ok(("", value)) => Ok(value),
+        _ => Err(format!("line {}: invalid number `{}`", line, text)),
+    }
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a section
+    pub fn section(mut self, section: InputSection) -> Self {
+        self.sections.push(section);
+        self
+    }
+
+    /// Adds a symbol that an input file defines
+    pub fn defines<S: Into<String>>(mut self, symbol: S) -> Self {
+        self.defined.push(symbol.into());
+        self
+    }
+
+    /// Adds a symbol that an input file refers to
+    pub fn references<S: Into<String>>(mut self, symbol: S) -> Self {
+        self.referenced.push(symbol.into());
+        self
+    }
+
+    /// Whether an input file defines the symbol
+    pub fn is_defined(&self, symbol: &str) -> bool {
+        self.defined.iter().any(|name| name == symbol)
+    }
+
+    /// Whether an input file may refer to the symbol, true for every symbol
+    /// if the references are not known
+    pub fn may_reference(&self, symbol: &str) -> bool {
+        self.referenced.is_empty() || self.referenced.iter().any(|name| name == symbol)
+    }
+
+    /// Parses the text form of a manifest
+    pub fn parse(text: &str) -> Result<Manifest, String> {
+        let mut manifest = Manifest::new();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or("");
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (file, name, size, align, flags) = match fields[..] {
+                [] => continue,
+                ["defines", symbol] => {
+                    manifest.defined.push(symbol.to_string());
+                    continue;
+                }
+                ["references", symbol] => {
+                    manifest.referenced.push(symbol.to_string());
+                    continue;
+                }
+                [file, name, size] => (file, name, size, None, None),
+                [file, name, size, align] => (file, name, size, Some(align), None),
+                [file, name, size, align, flags] => (file, name, size, Some(align), Some(flags)),
+                _ => {
+                    return Err(format!(
+                        "line {}: expected file, section, size, alignment and flags",
+                        line_number
+                    ))
+                }
+            };
+            let mut section = InputSection::new(file, name, value(line_number, size)?);
+            if let Some(align) = align {
+                section.align = value(line_number, align)?;
+            }
+            if let Some(flags) = flags {
+                section.flags = SectionFlags::parse(flags)
+                    .ok_or_else(|| format!("line {}: invalid flags `{}`", line_number, flags))?;
+            }
+            if let (Some(open), true) = (file.find('('), file.ends_with(')')) {
+                section.file = file[..open].to_string();
+                section.member = Some(file[open + 1..file.len() - 1].to_string());
+            }
+            manifest.sections.push(section);
+        }
+        Ok(manifest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use manifest::*;
+
+    #[test]
+    fn test_parse() {
+        let manifest = Manifest::parse(
+            "
+            # file             section  size   align
+            crt0.o             .text    0x40   2
+            libc.a(printf.o)   .text    0x1C2  2  # printf
+            main.o             .bss     2K
+            main.o             .persist 4      2  WA
+            defines            main
+            references         __stack
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            manifest,
+            Manifest::new()
+                .section(InputSection::new("crt0.o", ".text", 0x40).align(2))
+                .section(
+                    InputSection::new("libc.a", ".text", 0x1C2)
+                        .member("printf.o")
+                        .align(2)
+                )
+                .section(InputSection::new("main.o", ".bss", 2048))
+                .section(
+                    InputSection::new("main.o", ".persist", 4)
+                        .align(2)
+                        .flags(SectionFlags {
+                            alloc: true,
+                            write: true,
+                            contents: true,
+                            ..SectionFlags::default()
+                        })
+                )
+                .defines("main")
+                .references("__stack")
+        );
+        assert!(manifest.is_defined("main") && !manifest.is_defined("__stack"));
+        assert!(manifest.may_reference("__stack"));
+        assert!(!manifest.may_reference("__heap_start"));
+        assert!(Manifest::new().may_reference("__heap_start"));
+        assert_eq!(
+            Manifest::parse("a.o .text"),
+            Err("line 1: expected file, section, size, alignment and flags".to_string())
+        );
+        assert_eq!(
+            Manifest::parse("a.o .text 2 1 AQ"),
+            Err("line 1: invalid flags `AQ`".to_string())
+        );
+        assert_eq!(
+            Manifest::parse("\na.o .text 12z"),
+            Err("line 2: invalid number `12z`".to_string())
+        );
+    }
+
+    #[test]
+    fn test_flags() {
+        let flags = |name| {
+            let f = SectionFlags::from_name(name);
+            (f.alloc, f.write, f.exec, f.contents)
+        };
+        assert_eq!(flags(".text.main"), (true, false, true, true));
+        assert_eq!(flags(".upper.text"), (true, false, true, true));
+        assert_eq!(flags(".rodata.str1.1"), (true, false, false, true));
+        assert_eq!(flags(".data.rel.ro"), (true, true, false, true));
+        assert_eq!(flags(".bss.buffer"), (true, true, false, false));
+        assert_eq!(flags("__interrupt_vector_5"), (true, false, false, true));
+        assert_eq!(flags(".debug_info"), (false, false, false, true));
+        assert_eq!(flags(".MSP430.attributes"), (false, false, false, true));
+        assert!(SectionFlags::from_name(".tbss").tls);
+        assert_eq!(
+            SectionFlags::parse("WAN"),
+            Some(SectionFlags {
+                alloc: true,
+                write: true,
+                ..SectionFlags::default()
+            })
+        );
+    }
+}
@@ -0,0 +1,534 @@
+//! Assignment of input sections to output sections.
+//!
+//! A [`Matcher`] collects the input section commands of the SECTIONS blocks
+//! and finds the one that takes an input section, following ld's rules:
+//!
+//! - file and section patterns are globs with `*`, `?` and `[chars]`;
+//! - a file pattern `archive:member` matches members of archives, `archive:`
+//!   every member of an archive and `:member` only files outside of
+//!   archives, a pattern without `:` matches the path of the file or the
+//!   archive;
+//! - `EXCLUDE_FILE` leaves out the files its patterns match;
+//! - the first command in script order that matches takes the section,
+//!   including the commands of `/DISCARD/`.
+//!
+//! The sections a command takes are placed in input order, unless their
+//! pattern is wrapped in `SORT_BY_NAME`, `SORT_BY_ALIGNMENT` (largest
+//! first) or `SORT_BY_INIT_PRIORITY`, or the file pattern in `SORT_BY_NAME`.
+//! Like ld, a sorted section is inserted before the first section taken so
+//! far that sorts after it, [`Matcher::select`] gives the resulting order.
+//!
+//! ```
+//! extern crate ldscript_parser as lds;
+//!
+//! use lds::manifest::InputSection;
+//! use lds::matching::Matcher;
+//!
+//! fn main() {
+//!     let items = lds::parse("
+//!         SECTIONS {
+//!             .ctors : { KEEP (*crtbegin*.o(.ctors)) KEEP (*(.ctors)) }
+//!             .rodata : { *(.rodata .const .const:*) }
+//!             .text : { libc.a:*(.text) *(EXCLUDE_FILE(*crt0.o) .text) }
+//!             /DISCARD/ : { *(.comment) }
+//!         }
+//!     ").unwrap();
+//!     let matcher = Matcher::new(&items);
+//!     let section = |file, name| InputSection::new(file, name, 0);
+//!
+//!     let crtbegin = section("/lib/crtbegin.o", ".ctors");
+//!     assert_eq!(matcher.output_section(&crtbegin), Some(".ctors"));
+//!     assert!(matcher.find(&crtbegin).unwrap().keep);
+//!     assert_eq!(matcher.output_section(&section("a.o", ".const:x")), Some(".rodata"));
+//!     assert_eq!(matcher.output_section(&section("libc.a", ".text").member("printf.o")), Some(".text"));
+//!     assert_eq!(matcher.output_section(&section("crt0.o", ".text")), None);
+//!     assert_eq!(matcher.output_section(&section("a.o", ".comment")), Some("/DISCARD/"));
+//!
+//!     let items = lds::parse("
+//!         SECTIONS {
+//!             .init_array : {
+//!                 KEEP (*(SORT_BY_INIT_PRIORITY(.init_array.*) SORT_BY_INIT_PRIORITY(.ctors.*)))
+//!                 KEEP (*(.init_array))
+//!             }
+//!         }
+//!     ").unwrap();
+//!     let inputs = [
+//!         section("a.o", ".init_array"),
+//!         section("a.o", ".init_array.00200"),
+//!         section("b.o", ".ctors.65434"),
+//!         section("b.o", ".init_array.00300"),
+//!     ];
+//!     // `.ctors.65434` is priority 101, ctors count down from 65535
+//!     assert_eq!(Matcher::new(&items).output_sections(&inputs), [(".init_array", vec![2, 1, 3, 0])]);
+//! }
+//! ```
+
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use manifest::InputSection;
+use script::RootItem;
+use sections::{OutputSectionCommand, SectionCommand, SectionPattern};
+use symbols::Site;
+
+/// Whether `text` matches a glob pattern with `*`, `?` and `[chars]`
+pub fn glob(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_chars(&pattern, &text)
+}
+
+fn glob_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => (0..=text.len()).any(|skip| glob_chars(&pattern[1..], &text[skip..])),
+        Some('?') => !text.is_empty() && glob_chars(&pattern[1..], &text[1..]),
+        Some('[') => match pattern.iter().position(|&c| c == ']') {
+            Some(end) if !text.is_empty() => {
+                let class = &pattern[1..end];
+                let (negate, class) = match class.first() {
+                    Some('!') | Some('^') => (true, &class[1..]),
+                    _ => (false, class),
+                };
+                let mut found = false;
+                let mut i = 0;
+                while i < class.len() {
+                    if i + 2 < class.len() && class[i + 1] == '-' {
+                        found |= class[i] <= text[0] && text[0] <= class[i + 2];
+                        i += 3;
+                    } else {
+                        found |= class[i] == text[0];
+                        i += 1;
+                    }
+                }
+                found != negate && glob_chars(&pattern[end + 1..], &text[1..])
+            }
+            Some(_) => false,
+            None => text.first() == Some(&'[') && glob_chars(&pattern[1..], &text[1..]),
+        },
+        Some(&c) => text.first() == Some(&c) && glob_chars(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Whether a file name pattern matches the file of an input section
+pub fn file_matches(pattern: &str, input: &InputSection) -> bool {
+    match pattern.find(':') {
+        None => glob(pattern, &input.file),
+        Some(colon) => {
+            let (archive, member) = (&pattern[..colon], &pattern[colon + 1..]);
+            let member_matches = |name: &str| member.is_empty() || glob(member, name);
+            match input.member {
+                Some(ref name) => {
+                    !archive.is_empty() && glob(archive, &input.file) && member_matches(name)
+                }
+                None => archive.is_empty() && member_matches(&input.file),
+            }
+        }
+    }
+}
+
+/// The glob of a pattern without its sorting or `EXCLUDE_FILE`
+pub fn pattern_glob<'p>(pattern: &'p SectionPattern) -> &'p str {
+    match *pattern {
+        SectionPattern::Simple(ref p)
+        | SectionPattern::SortByName(ref p)
+        | SectionPattern::SortByAlignment(ref p)
+        | SectionPattern::SortByInitPriority(ref p)
+        | SectionPattern::SortNone(ref p) => p,
+        SectionPattern::ExcludeFile { ref pattern, .. } => pattern_glob(pattern),
+    }
+}
+
+/// Whether the files of an `EXCLUDE_FILE` in the pattern include the file
+/// of the input section
+fn excluded(pattern: &SectionPattern, input: &InputSection) -> bool {
+    match *pattern {
+        SectionPattern::ExcludeFile {
+            ref files,
+            ref pattern,
+        } => files.iter().any(|file| file_matches(file, input)) || excluded(pattern, input),
+        _ => false,
+    }
+}
+
+/// Priority of a constructor or destructor section from the number at the
+/// end of its name. Only `.init_array.NNN`, `.fini_array.NNN`, `.ctors.NNN`
+/// and `.dtors.NNN` have a priority. The numbers of `.ctors` and `.dtors`
+/// count down from 65535 as they run backwards, so these sections get 65535
+/// minus the number. A lower value runs earlier.
+pub fn init_priority(name: &str) -> Option<u64> {
+    let dot = name.rfind('.')?;
+    let digits = &name[dot + 1..];
+    if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let priority: u64 = digits.parse().ok()?;
+    match &name[..dot] {
+        ".ctors" | ".dtors" => 65535u64.checked_sub(priority),
+        ".init_array" | ".fini_array" => Some(priority),
+        _ => None,
+    }
+}
+
+/// Order of two input sections for a sorted section pattern, `None` if the
+/// pattern is not sorted
+fn compare_sections(
+    pattern: &SectionPattern,
+    a: &InputSection,
+    b: &InputSection,
+) -> Option<Ordering> {
+    match *pattern {
+        SectionPattern::SortByName(_) => Some(a.name.cmp(&b.name)),
+        SectionPattern::SortByAlignment(_) => Some(b.align.cmp(&a.align)),
+        SectionPattern::SortByInitPriority(_) => {
+            let priority = match (init_priority(&a.name), init_priority(&b.name)) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                _ => Ordering::Equal,
+            };
+            Some(priority.then_with(|| a.name.cmp(&b.name)))
+        }
+        SectionPattern::ExcludeFile { ref pattern, .. } => compare_sections(pattern, a, b),
+        SectionPattern::Simple(_) | SectionPattern::SortNone(_) => None,
+    }
+}
+
+/// Order of the files of two input sections, by archive or file name and
+/// then by member name
+fn compare_files(a: &InputSection, b: &InputSection) -> Ordering {
+    a.file.cmp(&b.file).then_with(|| {
+        if a.member.is_none() && b.member.is_none() {
+            return Ordering::Equal;
+        }
+        let name =
+            |input: &InputSection| input.member.clone().unwrap_or_else(|| input.file.clone());
+        name(a).cmp(&name(b))
+    })
+}
+
+/// An input section command of a SECTIONS block.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Rule<'s, 'a: 's> {
+    /// Place of the command, `content` is its index in the output section
+    pub site: Site,
+    /// Name of the output section the command is in
+    pub output: &'s str,
+    pub file: &'s SectionPattern<'a>,
+    /// Section patterns, an empty list takes every section of the file
+    pub sections: &'s [SectionPattern<'a>],
+    /// The command is wrapped in `KEEP`
+    pub keep: bool,
+}
+
+impl<'s, 'a> Rule<'s, 'a> {
+    fn file_matches(&self, input: &InputSection) -> bool {
+        !excluded(self.file, input) && file_matches(pattern_glob(self.file), input)
+    }
+
+    /// Whether the command selects the input section
+    pub fn matches(&self, input: &InputSection) -> bool {
+        self.file_matches(input) && (self.sections.is_empty() || self.pattern(input).is_some())
+    }
+
+    /// The first section pattern of the command that selects the input
+    /// section
+    pub fn pattern(&self, input: &InputSection) -> Option<&'s SectionPattern<'a>> {
+        if !self.file_matches(input) {
+            return None;
+        }
+        self.sections
+            .iter()
+            .find(|pattern| !excluded(pattern, input) && glob(pattern_glob(pattern), &input.name))
+    }
+
+    /// Whether the command discards the sections it selects
+    pub fn is_discard(&self) -> bool {
+        self.output == "/DISCARD/"
+    }
+
+    /// Adds an input section to the sections the command takes, which are
+    /// in the order they are placed
+    fn insert(&self, taken: &mut Vec<usize>, index: usize, inputs: &[InputSection]) {
+        let input = &inputs[index];
+        let files_sorted = matches!(*self.file, SectionPattern::SortByName(_));
+        let pattern = self.pattern(input);
+        let sections_sorted =
+            pattern.is_some_and(|pattern| compare_sections(pattern, input, input).is_some());
+        if !files_sorted && !sections_sorted {
+            taken.push(index);
+            return;
+        }
+        // the position ld's `wild_sort` finds
+        let position = taken.iter().position(|&other| {
+            let other = &inputs[other];
+            if files_sorted {
+                match compare_files(input, other) {
+                    Ordering::Greater => return false,
+                    Ordering::Less => return true,
+                    Ordering::Equal => {}
+                }
+            }
+            pattern
+                .and_then(|pattern| compare_sections(pattern, input, other))
+                .is_some_and(|order| order == Ordering::Less)
+        });
+        taken.insert(position.unwrap_or(taken.len()), index);
+    }
+}
+
+/// The input section commands of a script in script order.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Matcher<'s, 'a: 's> {
+    pub rules: Vec<Rule<'s, 'a>>,
+}
+
+impl<'s, 'a> Matcher<'s, 'a> {
+    pub fn new(items: &'s [RootItem<'a>]) -> Self {
+        let mut rules = Vec::new();
+        for (item, root) in items.iter().enumerate() {
+            let list = match *root {
+                RootItem::Sections { ref list, .. } => list,
+                _ => continue,
+            };
+            for (command, cmd) in list.iter().enumerate() {
+                let (output, content) = match *cmd {
+                    SectionCommand::OutputSection {
+                        ref name,
+                        ref content,
+                        ..
+                    } => (name, content),
+                    _ => continue,
+                };
+                for (index, cmd) in content.iter().enumerate() {
+                    let (file, sections, keep) = match *cmd {
+                        OutputSectionCommand::InputSection {
+                            ref file,
+                            ref sections,
+                            ..
+                        } => (file, sections, false),
+                        OutputSectionCommand::KeepInputSection {
+                            ref file,
+                            ref sections,
+                            ..
+                        } => (file, sections, true),
+                        _ => continue,
+                    };
+                    rules.push(Rule {
+                        site: Site::new(item, Some(command), Some(index)),
+                        output,
+                        file,
+                        sections,
+                        keep,
+                    });
+                }
+            }
+        }
+        Matcher { rules }
+    }
+
+    /// Index of the command that takes the input section, the first one
+    /// that selects it
+    pub fn position(&self, input: &InputSection) -> Option<usize> {
+        self.rules.iter().position(|rule| rule.matches(input))
+    }
+
+    /// The command that takes the input section
+    pub fn find(&self, input: &InputSection) -> Option<&Rule<'s, 'a>> {
+        self.position(input).map(|index| &self.rules[index])
+    }
+
+    /// Name of the output section the input section goes to, `/DISCARD/`
+    /// if it is discarded and `None` if it is an orphan
+    pub fn output_section(&self, input: &InputSection) -> Option<&'s str> {
+        self.find(input).map(|rule| rule.output)
+    }
+
+    /// Index of the command that takes each of the input sections
+    pub fn assign(&self, inputs: &[InputSection]) -> Vec<Option<usize>> {
+        inputs.iter().map(|input| self.position(input)).collect()
+    }
+
+    /// Indexes of the input sections each command takes, in the order they
+    /// are placed
+    pub fn select(&self, inputs: &[InputSection]) -> Vec<Vec<usize>> {
+        let mut taken = vec![Vec::new(); self.rules.len()];
+        for (index, rule) in self.assign(inputs).into_iter().enumerate() {
+            if let Some(rule) = rule {
+                self.rules[rule].insert(&mut taken[rule], index, inputs);
+            }
+        }
+        taken
+    }
+
+    /// Output sections in script order with the indexes of their input
+    /// sections in the order they are placed, `/DISCARD/` included
+    pub fn output_sections(&self, inputs: &[InputSection]) -> Vec<(&'s str, Vec<usize>)> {
+        let mut sections: Vec<(&'s str, Vec<usize>)> = Vec::new();
+        for (rule, taken) in self.rules.iter().zip(self.select(inputs)) {
+            match sections
+                .iter_mut()
+                .find(|&&mut (name, _)| name == rule.output)
+            {
+                Some(&mut (_, ref mut list)) => list.extend(taken),
+                None => sections.push((rule.output, taken)),
+            }
+        }
+        sections
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use manifest::InputSection;
+    use matching::*;
+
+    #[test]
+    fn test_glob() {
+        assert!(glob("*", ""));
+        assert!(glob(".text*", ".text.main"));
+        assert!(!glob(".text*", ".data"));
+        assert!(glob("*crtbegin?.o", "/lib/crtbeginS.o"));
+        assert!(glob("[0-9]x[!a]", "5xb"));
+        assert!(!glob("[0-9]x[!a]", "5xa"));
+        assert!(glob(".const:*", ".const:table"));
+    }
+
+    #[test]
+    fn test_file_matches() {
+        let object = InputSection::new("/lib/crt0.o", ".text", 0);
+        let member = InputSection::new("/lib/libc.a", ".text", 0).member("printf.o");
+        assert!(file_matches("*crt0.o", &object));
+        assert!(!file_matches("crt0.o", &object));
+        assert!(file_matches("*libc.a", &member));
+        assert!(file_matches("*libc.a:", &member));
+        assert!(file_matches("*libc.a:print*.o", &member));
+        assert!(!file_matches("*libc.a:puts.o", &member));
+        assert!(!file_matches("*libc.a:*", &object));
+        assert!(file_matches(":*crt0.o", &object));
+        assert!(!file_matches(":printf.o", &member));
+    }
+
+    #[test]
+    fn test_matcher() {
+        let items = ::parse(
+            "
+            SECTIONS {
+                .ctors : {
+                    KEEP (*crtbegin*.o(.ctors))
+                    KEEP (*(EXCLUDE_FILE (*crtend*.o) .ctors))
+                    KEEP (*(.ctors))
+                }
+                .text : { crt0.o *(.text .text.*) }
+                /DISCARD/ : { *(.text.unused) *(.comment) }
+                .data : { *libm.a:*(.data) EXCLUDE_FILE (*.a) *(.data .comment) }
+            }
+            ",
+        )
+        .unwrap();
+        let matcher = Matcher::new(&items);
+        assert_eq!(matcher.rules.len(), 9);
+        assert_eq!(matcher.rules[3].site, Site::new(0, Some(1), Some(0)));
+        let inputs = [
+            InputSection::new("crtbegin.o", ".ctors", 0),
+            InputSection::new("main.o", ".ctors", 0),
+            InputSection::new("crtend.o", ".ctors", 0),
+            InputSection::new("crt0.o", ".data", 0),
+            InputSection::new("main.o", ".text.unused", 0),
+            InputSection::new("libm.a", ".data", 0).member("sin.o"),
+            InputSection::new("libc.a", ".data", 0).member("errno.o"),
+            InputSection::new("main.o", ".data", 0),
+            InputSection::new("main.o", ".comment", 0),
+            InputSection::new("main.o", ".bss", 0),
+        ];
+        assert_eq!(
+            matcher.assign(&inputs),
+            [
+                Some(0),
+                Some(1),
+                Some(2),
+                Some(3),
+                Some(4),
+                Some(7),
+                None,
+                Some(8),
+                Some(6),
+                None,
+            ]
+        );
+        assert_eq!(
+            matcher.rules[1].pattern(&inputs[1]),
+            Some(&matcher.rules[1].sections[0])
+        );
+        assert_eq!(matcher.rules[1].pattern(&inputs[2]), None);
+        assert_eq!(matcher.rules[3].pattern(&inputs[3]), None);
+        assert!(matcher.rules[3].matches(&inputs[3]));
+        assert!(matcher.rules[6].is_discard());
+    }
+
+    #[test]
+    fn test_init_priority() {
+        assert_eq!(init_priority(".init_array.00101"), Some(101));
+        assert_eq!(init_priority(".fini_array.65535"), Some(65535));
+        assert_eq!(init_priority(".ctors.65434"), Some(101));
+        assert_eq!(init_priority(".dtors.00000"), Some(65535));
+        assert_eq!(init_priority(".init_array"), None);
+        assert_eq!(init_priority(".init_array.x1"), None);
+        assert_eq!(init_priority(".ctors.70000"), None);
+        assert_eq!(init_priority(".text.123"), None);
+        assert_eq!(init_priority(".init_array.ctors.5"), None);
+
+        // without a priority the sections are sorted by name
+        let items = ::parse("SECTIONS { .text : { *(SORT_BY_INIT_PRIORITY(.text.*)) } }").unwrap();
+        let inputs = [
+            InputSection::new("a.o", ".text.20", 0),
+            InputSection::new("a.o", ".text.123", 0),
+        ];
+        assert_eq!(Matcher::new(&items).select(&inputs), [vec![1, 0]]);
+    }
+
+    #[test]
+    fn test_sort() {
+        let items = ::parse(
+            "
+            SECTIONS {
+                .text : { *(.text.a SORT_BY_NAME(.text.*)) }
+                .data : { SORT(*)(.data) }
+                .bss : { *(SORT_BY_ALIGNMENT(.bss*)) }
+                .ctors : { *(SORT_BY_INIT_PRIORITY(.ctors.*) SORT_NONE(.ctors)) }
+            }
+            ",
+        )
+        .unwrap();
+        let inputs = [
+            InputSection::new("b.o", ".text.c", 0),
+            InputSection::new("b.o", ".text.a", 0),
+            InputSection::new("a.o", ".text.b", 0),
+            InputSection::new("b.o", ".data", 0),
+            InputSection::new("lib.a", ".data", 0).member("z.o"),
+            InputSection::new("a.o", ".data", 0),
+            InputSection::new("lib.a", ".data", 0).member("y.o"),
+            InputSection::new("a.o", ".bss", 0).align(2),
+            InputSection::new("a.o", ".bss.x", 0).align(8),
+            InputSection::new("b.o", ".bss", 0).align(2),
+            InputSection::new("b.o", ".bss.y", 0).align(4),
+            InputSection::new("a.o", ".ctors.65435", 0),
+            InputSection::new("a.o", ".ctors", 0),
+            InputSection::new("b.o", ".ctors.65500", 0),
+            InputSection::new("b.o", ".ctors.x", 0),
+            InputSection::new("c.o", ".ctors.65434", 0),
+        ];
+        let matcher = Matcher::new(&items);
+        // `.text.a` matches the unsorted pattern first and is appended
+        assert_eq!(
+            matcher.select(&inputs),
+            [
+                vec![2, 0, 1],
+                vec![5, 3, 6, 4],
+                vec![8, 10, 7, 9],
+                vec![13, 11, 12, 15, 14],
+            ]
+        );
+        assert_eq!(
+            matcher.output_sections(&inputs)[0],
+            (".text", vec![2, 0, 1])
+        );
+    }
+}
@@ -1,19 +1,44 @@
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
 use idents::symbol;
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_until},
     combinator::opt,
-    sequence::{delimited, tuple},
+    sequence::{delimited, preceded, tuple},
     IResult,
 };
 use numbers::number;
-use whitespace::opt_space;
+use whitespace::{opt_space, Comment, Commented};
 
-#[derive(Debug, PartialEq)]
-pub struct Region {
-    pub name: String,
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Region<'a> {
+    pub name: Cow<'a, str>,
+    /// Region attributes, e.g. `rx` or `!w`, without the parentheses
+    pub attributes: Option<Cow<'a, str>>,
     pub origin: u64,
     pub length: u64,
+    pub comments: Vec<Comment<'a>>,
+}
+
+impl<'a> Region<'a> {
+    /// Converts the region into one that owns all of its strings
+    pub fn into_owned(self) -> Region<'static> {
+        Region {
+            name: Cow::Owned(self.name.into_owned()),
+            attributes: self.attributes.map(|a| Cow::Owned(a.into_owned())),
+            origin: self.origin,
+            length: self.length,
+            comments: self.comments.into_iter().map(Comment::into_owned).collect(),
+        }
+    }
+}
+
+impl<'a> Commented<'a> for Region<'a> {
+    fn comments_mut(&mut self) -> Option<&mut Vec<Comment<'a>>> {
+        Some(&mut self.comments)
+    }
 }
 
 fn attributes(input: &str) -> IResult<&str, &str> {
@@ -28,15 +53,10 @@ fn length(input: &str) -> IResult<&str, &str> {
     alt((tag("LENGTH"), tag("len"), tag("l")))(input)
 }
 
-pub fn region(input: &str) -> IResult<&str, Region> {
+pub fn region(input: &str) -> IResult<&str, Region<'_>> {
     let (input, name) = symbol(input)?;
-    let (input, _) = tuple((
-        opt_space,
-        opt(attributes),
-        wsc!(tag(":")),
-        origin,
-        wsc!(tag("=")),
-    ))(input)?;
+    let (input, attrs) = preceded(opt_space, opt(attributes))(input)?;
+    let (input, _) = tuple((wsc!(tag(":")), origin, wsc!(tag("="))))(input)?;
     let (input, org) = number(input)?;
     let (input, _) = tuple((wsc!(tag(",")), length, wsc!(tag("="))))(input)?;
     let (input, len) = number(input)?;
@@ -44,8 +64,10 @@ pub fn region(input: &str) -> IResult<&str, Region> {
         input,
         Region {
             name: name.into(),
+            attributes: attrs.map(Cow::Borrowed),
             origin: org,
             length: len,
+            comments: Vec::new(),
         },
     ))
 }
@@ -60,16 +82,20 @@ mod tests {
             region("rom (rx)  : ORIGIN = 0, LENGTH = 256K"),
             Region {
                 name: "rom".into(),
+                attributes: Some("rx".into()),
                 origin: 0,
                 length: 256 * 1024,
+                comments: vec![],
             }
         );
         assert_done!(
             region("ram (!rx) : org = 0x40000000, l = 4M"),
             Region {
                 name: "ram".into(),
+                attributes: Some("!rx".into()),
                 origin: 0x40000000,
                 length: 4 * 1024 * 1024,
+                comments: vec![],
             }
         );
     }
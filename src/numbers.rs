@@ -1,5 +1,3 @@
-use std::panic;
-
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_while1},
@@ -49,26 +47,26 @@ fn prefixed_hex(input: &str) -> IResult<&str, u64> {
 }
 
 fn is_num_or_suffix(c: char) -> bool {
-    match c {
-        '0'..='9' | 'A'..='F' | 'a'..='f' | 'h' | 'H' | 'o' | 'O' | 'k' | 'K' | 'm' | 'M' => true,
-        _ => false,
-    }
+    matches!(
+        c,
+        '0'..='9' | 'A'..='F' | 'a'..='f' | 'h' | 'H' | 'o' | 'O' | 'k' | 'K' | 'm' | 'M'
+    )
 }
 
-fn parse_oct_or_dec(num: &str) -> Result<u64, ::std::num::ParseIntError> {
+fn parse_oct_or_dec(num: &str) -> Result<u64, ::core::num::ParseIntError> {
     match num.chars().next() {
         Some('0') => u64::from_str_radix(num, 8),
-        _ => u64::from_str_radix(num, 10),
+        _ => num.parse::<u64>(),
     }
 }
 
 fn suffixed_num(input: &str) -> IResult<&str, u64> {
     map_res(take_while1(is_num_or_suffix), |num: &str| {
         match num.char_indices().last() {
-            Some((0, _)) => u64::from_str_radix(num, 10),
+            Some((0, _)) => num.parse::<u64>(),
             Some((n, 'b')) | Some((n, 'B')) => u64::from_str_radix(&num[..n], 2),
             Some((n, 'o')) | Some((n, 'O')) => u64::from_str_radix(&num[..n], 8),
-            Some((n, 'd')) | Some((n, 'D')) => u64::from_str_radix(&num[..n], 10),
+            Some((n, 'd')) | Some((n, 'D')) => num[..n].parse::<u64>(),
             Some((n, 'h')) | Some((n, 'H')) => u64::from_str_radix(&num[..n], 16),
             Some((n, 'k')) | Some((n, 'K')) => parse_oct_or_dec(&num[..n]).map(|v| v * 1024),
             Some((n, 'm')) | Some((n, 'M')) => parse_oct_or_dec(&num[..n]).map(|v| v * 1024 * 1024),
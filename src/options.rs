@@ -0,0 +1,166 @@
+#[cfg(not(feature = "std"))]
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use nom::error::{ErrorKind, ParseError};
+use nom::Err;
+#[cfg(feature = "std")]
+use std::cell::Cell;
+
+/// Settings of the parser.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ParseOptions {
+    /// Deepest nesting of parentheses, function calls, unary operators,
+    /// ternary branches and `EXCLUDE_FILE` lists that is accepted. Deeper
+    /// input fails with `ErrorKind::TooLarge` instead of exhausting the
+    /// stack.
+    pub max_depth: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions { max_depth: 128 }
+    }
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static MAX_DEPTH: Cell<usize> = Cell::new(ParseOptions::default().max_depth);
+}
+
+#[cfg(feature = "std")]
+fn max_depth() -> usize {
+    MAX_DEPTH.with(Cell::get)
+}
+
+#[cfg(feature = "std")]
+fn replace_max_depth(depth: usize) -> usize {
+    MAX_DEPTH.with(|max| max.replace(depth))
+}
+
+/// Without threads of `std` the limit is shared by all parsers
+#[cfg(not(feature = "std"))]
+static MAX_DEPTH: AtomicUsize = AtomicUsize::new(128);
+
+/// Set while a parser runs with options, only one may do so at a time
+#[cfg(not(feature = "std"))]
+static BUSY: AtomicBool = AtomicBool::new(false);
+
+#[cfg(not(feature = "std"))]
+struct Busy;
+
+#[cfg(not(feature = "std"))]
+impl Busy {
+    fn acquire() -> Busy {
+        if BUSY.swap(true, Ordering::Acquire) {
+            panic!(
+                "another parse with options is running, without `std` parsing is single-threaded"
+            );
+        }
+        Busy
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Drop for Busy {
+    fn drop(&mut self) {
+        BUSY.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(not(feature = "std"))]
+fn max_depth() -> usize {
+    MAX_DEPTH.load(Ordering::Relaxed)
+}
+
+#[cfg(not(feature = "std"))]
+fn replace_max_depth(depth: usize) -> usize {
+    MAX_DEPTH.swap(depth, Ordering::Relaxed)
+}
+
+/// Restores the previous limit when the parse is done, even if it panics
+struct Restore(usize);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        replace_max_depth(self.0);
+    }
+}
+
+/// Runs a parser with the limits of `options`.
+///
+/// Without `std` the limits are global. Panics if another parser runs with
+/// options at the same time, instead of letting the two overwrite each
+/// other's limits.
+pub fn with_options<T, F>(options: &ParseOptions, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    #[cfg(not(feature = "std"))]
+    let _busy = Busy::acquire();
+    let _restore = Restore(replace_max_depth(options.max_depth));
+    f()
+}
+
+/// Fails if a recursive parser is nested deeper than the limit
+pub fn check_depth<'a, E>(input: &'a str, depth: usize) -> Result<(), Err<E>>
+where
+    E: ParseError<&'a str>,
+{
+    if depth > max_depth() {
+        Err(Err::Failure(E::from_error_kind(input, ErrorKind::TooLarge)))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+    use expressions::expression;
+    use nom::error::ErrorKind;
+    use nom::Err;
+    use options::*;
+    use sections::section_command;
+
+    fn nested(depth: usize) -> String {
+        format!("{}1{}", "(".repeat(depth), ")".repeat(depth))
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let options = ParseOptions { max_depth: 4 };
+        with_options(&options, || {
+            assert_done!(expression(&nested(4)));
+            match expression(&nested(5)) {
+                Err(Err::Failure(e)) => assert_eq!(e.code, ErrorKind::TooLarge),
+                r => panic!("should fail: {:?}", r),
+            }
+            assert_done!(expression("- - - - 1"));
+            assert_fail!(expression("- - - - - 1"));
+            assert_done!(expression("a ? b ? c ? d ? 1 : 2 : 3 : 4 : 5"));
+            assert_fail!(expression("a ? b ? c ? d ? e ? 1 : 2 : 3 : 4 : 5 : 6"));
+            let exclude = "EXCLUDE_FILE(a) ".repeat(4);
+            let section = |exclude: &str| format!(".text : {{ *({}.text) }}", exclude);
+            assert_done!(section_command(&section(&exclude)));
+            assert_fail!(section_command(&section(&exclude.repeat(2))));
+        });
+        assert_done!(expression(&nested(5)));
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[test]
+    #[should_panic(expected = "single-threaded")]
+    fn test_concurrent_options() {
+        let options = ParseOptions::default();
+        with_options(&options, || with_options(&options, || ()));
+    }
+
+    #[test]
+    fn test_parse_with_options() {
+        let script = format!("SECTIONS {{ . = {}; }}", nested(20));
+        assert!(::parse(&script).is_ok());
+        let options = ParseOptions { max_depth: 10 };
+        assert!(::parse_with_options(&script, &options).is_err());
+        let deep = format!("SECTIONS {{ . = {}; }}", "(".repeat(100_000));
+        assert!(::parse(&deep).is_err());
+    }
+}
@@ -0,0 +1,512 @@
+//! Placement of orphan sections.
+//!
+//! Input sections that no input section command takes are orphans, and ld
+//! finds a place for them with a heuristic that looks at their flags and the
+//! output sections of the script. [`place_orphans`] follows ld's ELF
+//! heuristic:
+//!
+//! - an orphan goes into an output section with the same name, or into the
+//!   one made for an earlier orphan of that name;
+//! - otherwise it gets an output section of its own, placed after `.text`
+//!   for code, `.rodata` for read-only data, `.data` for writable data,
+//!   `.bss` for sections without contents and `.comment` for sections that
+//!   are not allocated, or after the last output section with similar flags
+//!   if the script has no section of that name. Orphans of the same kind
+//!   follow each other in input order, debugging sections go last;
+//! - a new output section is put into the first MEMORY region whose
+//!   attributes fit its flags, or else it stays in the region of the output
+//!   section it follows.
+//!
+//! The `Display` form of an [`Orphan`] is the warning of
+//! `--orphan-handling=warn`.
+//!
+//! ```
+//! extern crate ldscript_parser as lds;
+//!
+//! use lds::layout::Layout;
+//! use lds::manifest::Manifest;
+//! use lds::orphans::place_orphans;
+//!
+//! fn main() {
+//!     let items = lds::parse("
+//!         MEMORY {
+//!             RAM : ORIGIN = 0x1C00, LENGTH = 2K
+//!             ROM : ORIGIN = 0x4400, LENGTH = 0xBB80
+//!         }
+//!         SECTIONS {
+//!             .text : { *(.text) } > ROM
+//!             .data : { *(.data) } > RAM AT> ROM
+//!         }
+//!     ").unwrap();
+//!     let manifest = Manifest::parse("
+//!         main.o .text 0x100
+//!         main.o .data 0x10
+//!         main.o .text.startup 0x20
+//!     ").unwrap();
+//!     let layout = Layout::compute(&items, &manifest).unwrap();
+//!     let orphans = place_orphans(&layout, &manifest);
+//!     assert_eq!(orphans[0].after.as_deref(), Some(".text"));
+//!     assert_eq!(orphans[0].region.as_deref(), Some("ROM"));
+//!     assert_eq!(
+//!         orphans[0].to_string(),
+//!         "orphan section `.text.startup' from `main.o' being placed in section `.text.startup'"
+//!     );
+//! }
+//! ```
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use layout::Layout;
+use manifest::{InputSection, Manifest};
+use memory::Region;
+
+// the `SEC_*` flags of BFD that the heuristic looks at
+const CONTENTS: u32 = 1;
+const ALLOC: u32 = 1 << 1;
+const LOAD: u32 = 1 << 2;
+const READONLY: u32 = 1 << 3;
+const CODE: u32 = 1 << 4;
+const DATA: u32 = 1 << 5;
+const THREAD_LOCAL: u32 = 1 << 6;
+const DEBUGGING: u32 = 1 << 7;
+
+/// Where an orphan input section goes.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Orphan {
+    /// Index of the input section in the manifest
+    pub input: usize,
+    /// Name of the input section
+    pub section: String,
+    /// The input file the way ld prints it, `archive(member)` for archive
+    /// members
+    pub file: String,
+    /// Output section the orphan goes into
+    pub output: String,
+    /// The output section is in the script or was made for an earlier
+    /// orphan
+    pub existing: bool,
+    /// Output section a new output section follows, `None` if it comes
+    /// before all of them
+    pub after: Option<String>,
+    pub region: Option<String>,
+}
+
+impl fmt::Display for Orphan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "orphan section `{}' from `{}' being placed in section `{}'",
+            self.section, self.file, self.output
+        )
+    }
+}
+
+/// The kinds of orphans ld keeps together, with the output section each
+/// kind goes after
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Kind {
+    Text,
+    Rodata,
+    Tdata,
+    Data,
+    Bss,
+    Rel,
+    Interp,
+    NonAlloc,
+}
+
+impl Kind {
+    fn anchor(self) -> Option<&'static str> {
+        match self {
+            Kind::Text => Some(".text"),
+            Kind::Rodata => Some(".rodata"),
+            Kind::Tdata => Some(".tdata"),
+            Kind::Data => Some(".data"),
+            Kind::Bss => Some(".bss"),
+            Kind::Rel => None,
+            Kind::Interp => Some(".interp"),
+            Kind::NonAlloc => Some(".comment"),
+        }
+    }
+
+    /// The kind of an orphan with these flags, `None` for debugging
+    /// sections
+    fn of(name: &str, flags: u32) -> Option<Kind> {
+        Some(if flags & (ALLOC | DEBUGGING) == 0 {
+            Kind::NonAlloc
+        } else if flags & ALLOC == 0 {
+            return None;
+        } else if flags & LOAD != 0 && name.starts_with(".note") {
+            Kind::Interp
+        } else if flags & (LOAD | CONTENTS | THREAD_LOCAL) == 0 {
+            Kind::Bss
+        } else if flags & THREAD_LOCAL != 0 {
+            Kind::Tdata
+        } else if flags & READONLY == 0 {
+            Kind::Data
+        } else if name.starts_with(".rel") && flags & LOAD != 0 {
+            Kind::Rel
+        } else if flags & CODE == 0 {
+            Kind::Rodata
+        } else {
+            Kind::Text
+        })
+    }
+}
+
+const KINDS: usize = 8;
+
+fn section_flags(input: &InputSection) -> u32 {
+    let flags = input.flags;
+    let mut bits = 0;
+    if flags.contents {
+        bits |= CONTENTS;
+    }
+    if flags.alloc {
+        bits |= ALLOC;
+        if flags.contents {
+            bits |= LOAD;
+            if !flags.exec {
+                bits |= DATA;
+            }
+        }
+    }
+    if !flags.write {
+        bits |= READONLY;
+    }
+    if flags.exec {
+        bits |= CODE;
+    }
+    if flags.tls {
+        bits |= THREAD_LOCAL;
+    }
+    let debug = [".debug", ".zdebug", ".stab", ".line"];
+    if debug.iter().any(|prefix| input.name.starts_with(prefix)) {
+        bits |= DEBUGGING;
+    }
+    bits
+}
+
+/// An output section of the script or one made for an orphan
+struct Output {
+    name: String,
+    flags: u32,
+    region: Option<String>,
+}
+
+/// Last output section whose flags are like `flags`, the port of ld's
+/// `lang_output_section_find_by_flags`. The flag is set if the flags are
+/// the same.
+fn find_by_flags(outputs: &[Output], flags: u32) -> Option<(usize, bool)> {
+    let last = |mask: u32| {
+        outputs
+            .iter()
+            .rposition(|output| (output.flags ^ flags) & mask == 0)
+    };
+    let exact = last(CONTENTS | ALLOC | LOAD | READONLY | CODE | THREAD_LOCAL);
+    if let Some(index) = exact {
+        return Some((index, true));
+    }
+    let found = if flags & ALLOC == 0 {
+        // non-alloc go last
+        last(DEBUGGING)
+    } else if flags & CODE != 0 {
+        last(CONTENTS | ALLOC | LOAD | CODE | THREAD_LOCAL)
+    } else if flags & READONLY != 0 {
+        // .rodata can go after .text
+        last(CONTENTS | ALLOC | LOAD | READONLY)
+    } else if flags & THREAD_LOCAL != 0 {
+        // .tdata goes after .data, .tbss right after .tdata
+        let mut found = None;
+        let mut seen_thread_local = false;
+        for (index, output) in outputs.iter().enumerate() {
+            let diff = output.flags ^ (flags | LOAD | CONTENTS);
+            if diff & (ALLOC | THREAD_LOCAL) == 0 {
+                if output.flags & LOAD == 0 && flags & LOAD != 0 {
+                    break;
+                }
+                seen_thread_local = true;
+            } else if seen_thread_local {
+                break;
+            } else if diff & (CONTENTS | ALLOC | LOAD) == 0 {
+                found = Some(index);
+            }
+        }
+        found
+    } else if flags & CONTENTS != 0 {
+        // .data goes after .rodata
+        last(CONTENTS | ALLOC | LOAD | THREAD_LOCAL)
+    } else {
+        // .bss goes after any other alloc section
+        last(ALLOC)
+    };
+    found.map(|index| (index, false))
+}
+
+/// Flags of a MEMORY region that a section must have and must not have
+fn region_flags(region: &Region) -> (u32, u32) {
+    let (mut flags, mut not_flags) = (0, 0);
+    let mut invert = false;
+    for letter in region.attributes.as_deref().unwrap_or("").chars() {
+        let flag = match letter.to_ascii_lowercase() {
+            '!' => {
+                invert = !invert;
+                continue;
+            }
+            'a' => ALLOC,
+            'r' => READONLY,
+            'w' => DATA,
+            'x' => CODE,
+            'i' | 'l' => LOAD,
+            _ => continue,
+        };
+        if invert {
+            not_flags |= flag;
+        } else {
+            flags |= flag;
+        }
+    }
+    (flags, not_flags)
+}
+
+/// The first region whose attributes fit a section, ld's
+/// `lang_memory_default`
+fn default_region(regions: &[Region], flags: u32) -> Option<String> {
+    let mut flags = flags;
+    // a writable section counts as data
+    if flags & (ALLOC | READONLY | CODE) == ALLOC {
+        flags |= DATA;
+    }
+    regions
+        .iter()
+        .find(|region| {
+            let (with, without) = region_flags(region);
+            with & flags != 0 && without & flags == 0
+        })
+        .map(|region| region.name.to_string())
+}
+
+/// Predicts where ld puts the input sections of the manifest that the
+/// layout left unmatched, in input order
+pub fn place_orphans(layout: &Layout, manifest: &Manifest) -> Vec<Orphan> {
+    let mut outputs: Vec<Output> = layout
+        .sections
+        .iter()
+        .map(|section| {
+            let mut flags = 0;
+            let mut readonly = !section.inputs.is_empty();
+            for placement in &section.inputs {
+                let input = section_flags(&manifest.sections[placement.input]);
+                flags |= input & !READONLY;
+                readonly &= input & READONLY != 0;
+            }
+            if readonly {
+                flags |= READONLY;
+            }
+            if section.noload {
+                flags &= !(CONTENTS | LOAD);
+            }
+            if !section.allocated {
+                flags &= !(ALLOC | LOAD);
+            }
+            Output {
+                name: section.name.clone(),
+                flags,
+                region: section.region.clone(),
+            }
+        })
+        .collect();
+    // the output section each kind goes after and the last orphan of the
+    // kind, by their names as the list grows
+    let mut anchors: [Option<String>; KINDS] = Default::default();
+    let mut tails: [Option<String>; KINDS] = Default::default();
+
+    let mut orphans = Vec::new();
+    for &index in &layout.unmatched {
+        let input = &manifest.sections[index];
+        let flags = section_flags(input);
+        let file = match input.member {
+            Some(ref member) => format!("{}({})", input.file, member),
+            None => input.file.clone(),
+        };
+        let position =
+            |outputs: &[Output], name: &str| outputs.iter().position(|output| output.name == name);
+
+        if let Some(existing) = position(&outputs, &input.name) {
+            orphans.push(Orphan {
+                input: index,
+                section: input.name.clone(),
+                file,
+                output: input.name.clone(),
+                existing: true,
+                after: None,
+                region: outputs[existing].region.clone(),
+            });
+            continue;
+        }
+
+        // index of the output section the new one follows, `None` for the
+        // start
+        let after = match Kind::of(&input.name, flags) {
+            None => outputs.len().checked_sub(1),
+            Some(kind) => {
+                let slot = kind as usize;
+                let after = if let Some(ref tail) = tails[slot] {
+                    position(&outputs, tail)
+                } else {
+                    if anchors[slot].is_none() {
+                        anchors[slot] = kind
+                            .anchor()
+                            .filter(|name| position(&outputs, name).is_some())
+                            .map(str::to_string);
+                    }
+                    if let Some(ref anchor) = anchors[slot] {
+                        position(&outputs, anchor)
+                    } else {
+                        let found = find_by_flags(&outputs, flags);
+                        if let Some((found, true)) = found {
+                            anchors[slot] = Some(outputs[found].name.clone());
+                        }
+                        found.map(|(found, _)| found)
+                    }
+                };
+                tails[slot] = Some(input.name.clone());
+                after
+            }
+        };
+
+        let region = if flags & ALLOC == 0 {
+            None
+        } else {
+            default_region(&layout.regions, flags)
+                .or_else(|| after.and_then(|after| outputs[after].region.clone()))
+        };
+        orphans.push(Orphan {
+            input: index,
+            section: input.name.clone(),
+            file,
+            output: input.name.clone(),
+            existing: false,
+            after: after.map(|after| outputs[after].name.clone()),
+            region: region.clone(),
+        });
+        outputs.insert(
+            after.map_or(0, |after| after + 1),
+            Output {
+                name: input.name.clone(),
+                flags,
+                region,
+            },
+        );
+    }
+    orphans
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use layout::Layout;
+    use manifest::Manifest;
+    use orphans::*;
+
+    fn orphans(script: &str, manifest: &str) -> Vec<Orphan> {
+        let items = ::parse(script).unwrap();
+        let manifest = Manifest::parse(manifest).unwrap();
+        place_orphans(&Layout::compute(&items, &manifest).unwrap(), &manifest)
+    }
+
+    fn placements(orphans: &[Orphan]) -> Vec<(&str, bool, Option<&str>, Option<&str>)> {
+        orphans
+            .iter()
+            .map(|orphan| {
+                (
+                    &*orphan.output,
+                    orphan.existing,
+                    orphan.after.as_deref(),
+                    orphan.region.as_deref(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_place_orphans() {
+        let list = orphans(
+            "
+            MEMORY {
+                RAM : ORIGIN = 0x1C00, LENGTH = 0x800
+                ROM : ORIGIN = 0x4400, LENGTH = 0xBB80
+            }
+            SECTIONS {
+                .text : { *(EXCLUDE_FILE(crt0.o) .text) } > ROM
+                .rodata : { *(.rodata) } > ROM
+                .data : { *(.data) } > RAM AT> ROM
+                .bss : { *(.bss) } > RAM
+            }
+            ",
+            "
+            a.o .text 0x10
+            a.o .rodata 4
+            a.o .data 2
+            a.o .bss 2
+            crt0.o .text 4
+            a.o .text.extra 8
+            a.o .rodata.str 4
+            a.o .persistent 2
+            a.o .noinit 2
+            b.o .text.extra 8
+            a.o .lowtext 2
+            a.o .debug_info 0x100
+            lib.a(x.o) .MSP430.attributes 0x10
+            ",
+        );
+        assert_eq!(
+            placements(&list),
+            [
+                (".text", true, None, Some("ROM")),
+                (".text.extra", false, Some(".text"), Some("ROM")),
+                (".rodata.str", false, Some(".rodata"), Some("ROM")),
+                (".persistent", false, Some(".data"), Some("RAM")),
+                (".noinit", false, Some(".bss"), Some("RAM")),
+                (".text.extra", true, None, Some("ROM")),
+                (".lowtext", false, Some(".text.extra"), Some("ROM")),
+                (".debug_info", false, Some(".noinit"), None),
+                (".MSP430.attributes", false, Some(".debug_info"), None),
+            ]
+        );
+        assert_eq!(list[1].input, 5);
+        assert_eq!(
+            list[8].to_string(),
+            "orphan section `.MSP430.attributes' from `lib.a(x.o)' \
+             being placed in section `.MSP430.attributes'"
+        );
+
+        // regions with attributes take the orphans that fit them
+        let list = orphans(
+            "
+            MEMORY {
+                ROM (rx) : ORIGIN = 0x8000, LENGTH = 0x8000
+                RAM (w!x) : ORIGIN = 0x200, LENGTH = 0x400
+            }
+            SECTIONS {
+                .text : { *(.text) } > ROM
+            }
+            ",
+            "
+            a.o .text 0x10
+            a.o .data 2
+            a.o .bss 2
+            a.o .vectors 0x20
+            ",
+        );
+        assert_eq!(
+            placements(&list),
+            [
+                (".data", false, Some(".text"), Some("RAM")),
+                (".bss", false, Some(".data"), Some("RAM")),
+                (".vectors", false, Some(".text"), Some("ROM")),
+            ]
+        );
+    }
+}
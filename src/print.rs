@@ -0,0 +1,997 @@
+//! Conversion of the AST back into linker script text.
+//!
+//! Every AST type implements `Display` and emits valid GNU ld syntax. The
+//! output uses a fixed layout with two spaces of indentation; comments are
+//! kept next to the nodes they are attached to. The
+//! [`format`](../format/index.html) module offers a configurable layout.
+//! Parsing the printed text yields the same AST:
+//!
+//! ```
+//! extern crate ldscript_parser as lds;
+//!
+//! fn main() {
+//!     let items = lds::parse("SECTIONS{.text:{*(.text)}>ROM}").unwrap();
+//!     let text = lds::print::print(&items);
+//!     assert_eq!(text, "SECTIONS\n{\n  .text :\n  {\n    *(.text)\n  } > ROM\n}\n");
+//!     assert_eq!(lds::parse(&text).unwrap(), items);
+//! }
+//! ```
+
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use commands::{Command, InsertOrder};
+use core::fmt::{self, Display, Formatter, Write};
+use expressions::{BinaryOperator, Expression, UnaryOperator};
+use format::{BraceStyle, FormatOptions, Source};
+use memory::Region;
+use numbers::number;
+use script::{RootItem, Script};
+use sections::{
+    DataType, OutputSectionCommand, OutputSectionConstraint, OutputSectionType, SectionCommand,
+    SectionPattern,
+};
+use statements::{AssignOperator, Statement};
+use whitespace::{Comment, CommentPosition};
+
+/// Layout of the `Display` implementations and of [`print`].
+const PLAIN: FormatOptions = FormatOptions {
+    indent_width: 2,
+    hard_tabs: false,
+    brace_style: BraceStyle::NextLine,
+    max_width: 0,
+    align_memory: false,
+    blank_lines: false,
+};
+
+/// Words that would be parsed as keywords if a pattern was printed without
+/// quotes at the start of an output section command.
+const PATTERN_KEYWORDS: &[&str] = &[
+    "KEEP",
+    "BYTE",
+    "SHORT",
+    "LONG",
+    "QUAD",
+    "FILL",
+    "ASSERT",
+    "HIDDEN",
+    "PROVIDE",
+    "PROVIDE_HIDDEN",
+];
+
+/// Prints a list of root items as a linker script, one item after another.
+pub fn print(items: &[RootItem]) -> String {
+    let mut out = String::new();
+    Printer::new(&mut out, &PLAIN).items(items).unwrap();
+    out
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '.'
+}
+
+fn is_simple_symbol(name: &str, allow_minus: bool) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if is_ident_start(c) => {}
+        _ => return false,
+    }
+    if !chars.all(|c| c.is_alphanumeric() || c == '_' || c == '.' || (allow_minus && c == '-')) {
+        return false;
+    }
+    // names like `bh` or `fahx` start with something that would be read
+    // back as a number
+    number(name).is_err()
+}
+
+fn write_quoted(f: &mut dyn Write, text: &str) -> fmt::Result {
+    write!(f, "\"{}\"", text)
+}
+
+/// Writes the name of a region, output section or similar, where a `-` is
+/// part of the name.
+fn write_name(f: &mut dyn Write, name: &str) -> fmt::Result {
+    if is_simple_symbol(name, true) {
+        f.write_str(name)
+    } else {
+        write_quoted(f, name)
+    }
+}
+
+/// Writes a symbol used in an expression, where `A-B` would be read as a
+/// subtraction by GNU ld.
+fn write_ident(f: &mut dyn Write, name: &str) -> fmt::Result {
+    if is_simple_symbol(name, false) {
+        f.write_str(name)
+    } else {
+        write_quoted(f, name)
+    }
+}
+
+fn is_simple_pattern(pattern: &str) -> bool {
+    !pattern.is_empty()
+        && pattern
+            .chars()
+            .all(|c| c.is_alphanumeric() || "_.$/\\~=+[]*?-!<>^:".contains(c))
+        && !pattern.starts_with("SORT")
+        && !pattern.starts_with("EXCLUDE_FILE")
+        && !PATTERN_KEYWORDS.contains(&pattern)
+}
+
+fn write_pattern(f: &mut dyn Write, pattern: &str) -> fmt::Result {
+    if is_simple_pattern(pattern) {
+        f.write_str(pattern)
+    } else {
+        write_quoted(f, pattern)
+    }
+}
+
+/// Writes a number the way it is spelled in the source, or in decimal below
+/// 0x100 and in hexadecimal above
+fn write_number(f: &mut dyn Write, value: u64, source: Option<&Source>) -> fmt::Result {
+    if let Some(text) = source.and_then(|source| source.literal(value)) {
+        f.write_str(&text)
+    } else if value < 0x100 {
+        write!(f, "{}", value)
+    } else {
+        write!(f, "0x{:X}", value)
+    }
+}
+
+fn is_block(item: &RootItem) -> bool {
+    match *item {
+        RootItem::Memory { .. } | RootItem::Sections { .. } => true,
+        RootItem::Statement(_) | RootItem::Command(_) => false,
+    }
+}
+
+fn is_discard(cmd: &SectionCommand) -> bool {
+    match *cmd {
+        SectionCommand::OutputSection { ref name, .. } => name == "/DISCARD/",
+        SectionCommand::Statement(_) | SectionCommand::Command(_) => false,
+    }
+}
+
+fn has_body(cmd: &SectionCommand) -> bool {
+    match *cmd {
+        SectionCommand::OutputSection { ref content, .. } => !content.is_empty(),
+        SectionCommand::Statement(_) | SectionCommand::Command(_) => false,
+    }
+}
+
+/// Writes the AST nodes with the layout described by a [`FormatOptions`].
+///
+/// Every method expects the indentation of the first line of the node to be
+/// written already, and does not end the last line.
+pub(crate) struct Printer<'w, 'o> {
+    out: &'w mut dyn Write,
+    options: &'o FormatOptions,
+    /// What the tree does not keep of the text it was parsed from
+    source: Option<&'o Source>,
+    /// Number of leading comments and nodes with comments written so far
+    written: usize,
+}
+
+impl<'w, 'o> Printer<'w, 'o> {
+    pub(crate) fn new(out: &'w mut dyn Write, options: &'o FormatOptions) -> Self {
+        Printer {
+            out,
+            options,
+            source: None,
+            written: 0,
+        }
+    }
+
+    /// Keeps the spelling of the numbers and the blank lines of the source
+    pub(crate) fn source(mut self, source: &'o Source) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Whether the source has a blank line before the next leading comment
+    /// or node
+    fn blank_line(&self) -> bool {
+        self.source
+            .is_some_and(|source| source.blank_line(self.written))
+    }
+
+    fn expression(&mut self, expr: &Expression) -> fmt::Result {
+        write_expression(self.out, expr, self.source)
+    }
+
+    /// Width of the indentation at the given level, tabs count as
+    /// `indent_width` columns
+    fn width(&self, level: usize) -> usize {
+        level * self.options.indent_width
+    }
+
+    fn indent(&mut self, level: usize) -> fmt::Result {
+        for _ in 0..level {
+            if self.options.hard_tabs {
+                self.out.write_char('\t')?;
+            } else {
+                for _ in 0..self.options.indent_width {
+                    self.out.write_char(' ')?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the comments before a node, every node with comments starts
+    /// with this call. The blank line before the first comment is up to the
+    /// caller, as it goes before the indentation.
+    fn leading(&mut self, comments: &[Comment], level: usize) -> fmt::Result {
+        self.written += 1;
+        for comment in comments {
+            if comment.position == CommentPosition::Leading {
+                write!(self.out, "{}", comment)?;
+                self.out.write_char('\n')?;
+                if self.blank_line() {
+                    self.out.write_char('\n')?;
+                }
+                self.written += 1;
+                self.indent(level)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn trailing(&mut self, comments: &[Comment]) -> fmt::Result {
+        for comment in comments {
+            if comment.position == CommentPosition::Trailing {
+                write!(self.out, " {}", comment)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn dangling(&mut self, comments: &[Comment], level: usize) -> fmt::Result {
+        for comment in comments {
+            if comment.position == CommentPosition::Dangling {
+                self.out.write_char('\n')?;
+                self.indent(level)?;
+                write!(self.out, "{}", comment)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the opening brace of a block and ends the line
+    fn open_brace(&mut self, level: usize) -> fmt::Result {
+        match self.options.brace_style {
+            BraceStyle::NextLine => {
+                self.out.write_char('\n')?;
+                self.indent(level)?;
+                self.out.write_str("{\n")
+            }
+            BraceStyle::SameLine => self.out.write_str(" {\n"),
+        }
+    }
+
+    /// Writes the root items of a script, each of them ends with a newline
+    pub(crate) fn items(&mut self, items: &[RootItem]) -> fmt::Result {
+        for (i, item) in items.iter().enumerate() {
+            let block = i > 0 && (is_block(&items[i - 1]) || is_block(item));
+            if i > 0 && ((self.options.blank_lines && block) || self.blank_line()) {
+                self.out.write_char('\n')?;
+            }
+            self.root_item(item)?;
+            self.out.write_char('\n')?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &Statement, level: usize) -> fmt::Result {
+        self.leading(stmt.comments(), level)?;
+        match *stmt {
+            Statement::Assign {
+                ref name,
+                ref operator,
+                ref expression,
+                ..
+            } => {
+                write_ident(self.out, name)?;
+                write!(self.out, " {} ", operator)?;
+                self.expression(expression)?;
+                self.out.write_char(';')?;
+            }
+            Statement::Hidden {
+                ref name,
+                ref expression,
+                ..
+            }
+            | Statement::Provide {
+                ref name,
+                ref expression,
+                ..
+            }
+            | Statement::ProvideHidden {
+                ref name,
+                ref expression,
+                ..
+            } => {
+                self.out.write_str(match *stmt {
+                    Statement::Hidden { .. } => "HIDDEN(",
+                    Statement::Provide { .. } => "PROVIDE(",
+                    _ => "PROVIDE_HIDDEN(",
+                })?;
+                write_ident(self.out, name)?;
+                self.out.write_str(" = ")?;
+                self.expression(expression)?;
+                self.out.write_str(");")?;
+            }
+            Statement::Assert {
+                ref expr, ref text, ..
+            } => {
+                self.out.write_str("ASSERT(")?;
+                self.expression(expr)?;
+                self.out.write_str(", ")?;
+                write_quoted(self.out, text)?;
+                self.out.write_str(");")?;
+            }
+        }
+        self.trailing(stmt.comments())?;
+        self.dangling(stmt.comments(), level)
+    }
+
+    fn command(&mut self, cmd: &Command, level: usize, terminate: bool) -> fmt::Result {
+        self.leading(cmd.comments(), level)?;
+        match *cmd {
+            Command::Call {
+                ref name,
+                ref arguments,
+                ..
+            } => {
+                write_ident(self.out, name)?;
+                self.out.write_char('(')?;
+                for (i, arg) in arguments.iter().enumerate() {
+                    if i > 0 {
+                        self.out.write_str(", ")?;
+                    }
+                    self.expression(arg)?;
+                }
+                self.out.write_char(')')?;
+            }
+            Command::Include { ref file, .. } => {
+                self.out.write_str("INCLUDE ")?;
+                write_pattern(self.out, file)?;
+            }
+            Command::Insert {
+                ref order,
+                ref section,
+                ..
+            } => {
+                write!(self.out, "INSERT {} ", order)?;
+                write_name(self.out, section)?;
+            }
+        }
+        if terminate {
+            self.out.write_char(';')?;
+        }
+        self.trailing(cmd.comments())?;
+        self.dangling(cmd.comments(), level)
+    }
+
+    /// Texts of the name, origin and length columns of a region
+    fn region_columns(&self, region: &Region) -> [String; 3] {
+        let number = |value: u64| match self.source.and_then(|source| source.literal(value)) {
+            Some(text) => text,
+            None => format!("0x{:X}", value),
+        };
+        [
+            region_name(region),
+            format!("ORIGIN = {},", number(region.origin)),
+            format!("LENGTH = {}", number(region.length)),
+        ]
+    }
+
+    /// Writes a region from its columns, padding the name and the origin to
+    /// the given widths
+    fn region(
+        &mut self,
+        region: &Region,
+        columns: &[String; 3],
+        level: usize,
+        widths: (usize, usize),
+    ) -> fmt::Result {
+        self.leading(&region.comments, level)?;
+        write!(
+            self.out,
+            "{:<name$} : {:<origin$} {}",
+            columns[0],
+            columns[1],
+            columns[2],
+            name = widths.0,
+            origin = widths.1,
+        )?;
+        self.trailing(&region.comments)?;
+        self.dangling(&region.comments, level)
+    }
+
+    fn input_section(
+        &mut self,
+        keep: bool,
+        file: &SectionPattern,
+        sections: &[SectionPattern],
+        level: usize,
+    ) -> fmt::Result {
+        let mut head = String::new();
+        if keep {
+            head.push_str("KEEP(");
+        }
+        write!(head, "{}", file)?;
+        let close = if keep { ")" } else { "" };
+        if sections.is_empty() {
+            self.out.write_str(&head)?;
+            return self.out.write_str(close);
+        }
+        head.push('(');
+        let close = if keep { "))" } else { ")" };
+
+        let parts: Vec<String> = sections.iter().map(|s| s.to_string()).collect();
+        let length = |text: &str| text.chars().count();
+        let single =
+            length(&head) + parts.iter().map(|p| length(p) + 1).sum::<usize>() + close.len() - 1;
+        let max_width = self.options.max_width;
+        let wrap = max_width > 0 && self.width(level) + single > max_width;
+
+        self.out.write_str(&head)?;
+        let mut column = self.width(level) + length(&head);
+        for (i, part) in parts.iter().enumerate() {
+            if i > 0 {
+                let end = if i + 1 == parts.len() { close.len() } else { 0 };
+                if wrap && column + 1 + length(part) + end > max_width {
+                    // continuation lines are indented one level deeper
+                    self.out.write_char('\n')?;
+                    self.indent(level + 1)?;
+                    column = self.width(level + 1);
+                } else {
+                    self.out.write_char(' ')?;
+                    column += 1;
+                }
+            }
+            self.out.write_str(part)?;
+            column += length(part);
+        }
+        self.out.write_str(close)
+    }
+
+    fn output_section_command(&mut self, cmd: &OutputSectionCommand, level: usize) -> fmt::Result {
+        if let OutputSectionCommand::Statement(ref stmt) = *cmd {
+            return self.statement(stmt, level);
+        }
+        self.leading(cmd.comments(), level)?;
+        match *cmd {
+            OutputSectionCommand::Statement(_) => unreachable!(),
+            OutputSectionCommand::Fill { ref expr, .. } => {
+                self.out.write_str("FILL(")?;
+                self.expression(expr)?;
+                self.out.write_char(')')?;
+            }
+            OutputSectionCommand::Data {
+                ref d_type,
+                ref value,
+                ..
+            } => {
+                write!(self.out, "{}(", d_type)?;
+                self.expression(value)?;
+                self.out.write_char(')')?;
+            }
+            OutputSectionCommand::InputSection {
+                ref file,
+                ref sections,
+                ..
+            } => self.input_section(false, file, sections, level)?,
+            OutputSectionCommand::KeepInputSection {
+                ref file,
+                ref sections,
+                ..
+            } => self.input_section(true, file, sections, level)?,
+        }
+        self.trailing(cmd.comments())?;
+        self.dangling(cmd.comments(), level)
+    }
+
+    /// `separate` ends a fill expression with a comma, which is needed when
+    /// the next command is `/DISCARD/` as it would be read back as a division
+    fn section_command(
+        &mut self,
+        cmd: &SectionCommand,
+        level: usize,
+        separate: bool,
+    ) -> fmt::Result {
+        match *cmd {
+            SectionCommand::Statement(ref stmt) => self.statement(stmt, level),
+            SectionCommand::Command(ref cmd) => {
+                // an `INCLUDE` without the semicolon would make the next
+                // section look like a continuation of the file name
+                self.command(cmd, level, true)
+            }
+            SectionCommand::OutputSection {
+                ref name,
+                ref vma_address,
+                ref s_type,
+                ref lma_address,
+                ref section_align,
+                align_with_input,
+                ref subsection_align,
+                ref constraint,
+                ref content,
+                ref region,
+                ref lma_region,
+                ref fillexp,
+                ref comments,
+            } => {
+                self.leading(comments, level)?;
+                if name == "/DISCARD/" {
+                    self.out.write_str(name)?;
+                } else {
+                    write_name(self.out, name)?;
+                }
+                if let Some(ref vma) = *vma_address {
+                    // the address is followed by a type or a colon, so anything
+                    // other than a plain number or a call goes into parentheses
+                    match **vma {
+                        Expression::Number(_) | Expression::Call { .. } => {
+                            self.out.write_char(' ')?;
+                            self.expression(vma)?;
+                        }
+                        _ => {
+                            self.out.write_str(" (")?;
+                            self.expression(vma)?;
+                            self.out.write_char(')')?;
+                        }
+                    }
+                }
+                if let Some(ref s_type) = *s_type {
+                    write!(self.out, " {}", s_type)?;
+                }
+                self.out.write_str(" :")?;
+                if let Some(ref lma) = *lma_address {
+                    self.out.write_str(" AT(")?;
+                    self.expression(lma)?;
+                    self.out.write_char(')')?;
+                }
+                if let Some(ref align) = *section_align {
+                    self.out.write_str(" ALIGN(")?;
+                    self.expression(align)?;
+                    self.out.write_char(')')?;
+                }
+                if align_with_input {
+                    self.out.write_str(" ALIGN_WITH_INPUT")?;
+                }
+                if let Some(ref align) = *subsection_align {
+                    self.out.write_str(" SUBALIGN(")?;
+                    self.expression(align)?;
+                    self.out.write_char(')')?;
+                }
+                if let Some(ref constraint) = *constraint {
+                    write!(self.out, " {}", constraint)?;
+                }
+                if content.is_empty() {
+                    self.out.write_str(" {}")?;
+                } else {
+                    self.open_brace(level)?;
+                    for (i, command) in content.iter().enumerate() {
+                        if i > 0 && self.blank_line() {
+                            self.out.write_char('\n')?;
+                        }
+                        self.indent(level + 1)?;
+                        self.output_section_command(command, level + 1)?;
+                        self.out.write_char('\n')?;
+                    }
+                    self.indent(level)?;
+                    self.out.write_char('}')?;
+                }
+                if let Some(ref region) = *region {
+                    self.out.write_str(" > ")?;
+                    write_name(self.out, region)?;
+                }
+                if let Some(ref region) = *lma_region {
+                    self.out.write_str(" AT> ")?;
+                    write_name(self.out, region)?;
+                }
+                if let Some(ref fill) = *fillexp {
+                    self.out.write_str(" = ")?;
+                    self.expression(fill)?;
+                    if separate {
+                        self.out.write_char(',')?;
+                    }
+                }
+                self.trailing(comments)?;
+                self.dangling(comments, level)
+            }
+        }
+    }
+
+    fn root_item(&mut self, item: &RootItem) -> fmt::Result {
+        match *item {
+            RootItem::Statement(ref stmt) => self.statement(stmt, 0),
+            RootItem::Command(ref cmd) => self.command(cmd, 0, false),
+            RootItem::Memory {
+                ref regions,
+                ref comments,
+            } => {
+                self.leading(comments, 0)?;
+                self.out.write_str("MEMORY")?;
+                self.open_brace(0)?;
+                let columns: Vec<[String; 3]> = regions
+                    .iter()
+                    .map(|region| self.region_columns(region))
+                    .collect();
+                let mut widths = (0, 0);
+                if self.options.align_memory {
+                    for column in &columns {
+                        widths.0 = widths.0.max(column[0].chars().count());
+                        widths.1 = widths.1.max(column[1].chars().count());
+                    }
+                }
+                for (i, (region, columns)) in regions.iter().zip(&columns).enumerate() {
+                    if i > 0 && self.blank_line() {
+                        self.out.write_char('\n')?;
+                    }
+                    self.indent(1)?;
+                    self.region(region, columns, 1, widths)?;
+                    self.out.write_char('\n')?;
+                }
+                self.out.write_char('}')?;
+                self.trailing(comments)?;
+                self.dangling(comments, 0)
+            }
+            RootItem::Sections {
+                ref list,
+                ref comments,
+            } => {
+                self.leading(comments, 0)?;
+                self.out.write_str("SECTIONS")?;
+                self.open_brace(0)?;
+                for (i, command) in list.iter().enumerate() {
+                    let body = i > 0 && (has_body(&list[i - 1]) || has_body(command));
+                    if i > 0 && ((self.options.blank_lines && body) || self.blank_line()) {
+                        self.out.write_char('\n')?;
+                    }
+                    self.indent(1)?;
+                    let separate = list.get(i + 1).is_some_and(is_discard);
+                    self.section_command(command, 1, separate)?;
+                    self.out.write_char('\n')?;
+                }
+                self.out.write_char('}')?;
+                self.trailing(comments)?;
+                self.dangling(comments, 0)
+            }
+        }
+    }
+}
+
+/// Name and attributes of a region, the part before the colon
+fn region_name(region: &Region) -> String {
+    let mut text = String::new();
+    write_name(&mut text, &region.name).unwrap();
+    if let Some(ref attributes) = region.attributes {
+        write!(text, " ({})", attributes).unwrap();
+    }
+    text
+}
+
+impl<'a> Display for Comment<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "/*{}*/", self.text)
+    }
+}
+
+impl Display for UnaryOperator {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            UnaryOperator::LogicNot => "!",
+            UnaryOperator::Minus => "-",
+            UnaryOperator::BitwiseNot => "~",
+        })
+    }
+}
+
+impl Display for BinaryOperator {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            BinaryOperator::LogicOr => "||",
+            BinaryOperator::LogicAnd => "&&",
+            BinaryOperator::BitwiseOr => "|",
+            BinaryOperator::BitwiseAnd => "&",
+            BinaryOperator::Equals => "==",
+            BinaryOperator::NotEquals => "!=",
+            BinaryOperator::Lesser => "<",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::LesserOrEquals => "<=",
+            BinaryOperator::GreaterOrEquals => ">=",
+            BinaryOperator::ShiftRight => ">>",
+            BinaryOperator::ShiftLeft => "<<",
+            BinaryOperator::Plus => "+",
+            BinaryOperator::Minus => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Remainder => "%",
+        })
+    }
+}
+
+/// Precedence of an expression node, used to decide where the printer needs
+/// parentheses.
+fn expr_precedence(expr: &Expression) -> u8 {
+    match *expr {
+        Expression::TernaryOp { .. } => 0,
+        Expression::BinaryOp { ref operator, .. } => operator.precedence(),
+        Expression::UnaryOp { .. } => 9,
+        Expression::Ident(_) | Expression::Number(_) | Expression::Call { .. } => 10,
+    }
+}
+
+fn write_operand(
+    f: &mut dyn Write,
+    expr: &Expression,
+    min_precedence: u8,
+    source: Option<&Source>,
+) -> fmt::Result {
+    if expr_precedence(expr) < min_precedence {
+        f.write_char('(')?;
+        write_expression(f, expr, source)?;
+        f.write_char(')')
+    } else {
+        write_expression(f, expr, source)
+    }
+}
+
+/// Writes an expression, with the spelling of the numbers in the source if
+/// it is known
+fn write_expression(f: &mut dyn Write, expr: &Expression, source: Option<&Source>) -> fmt::Result {
+    match *expr {
+        Expression::Ident(ref name) => write_ident(f, name),
+        Expression::Number(value) => write_number(f, value, source),
+        Expression::Call {
+            ref function,
+            ref arguments,
+        } => {
+            write_ident(f, function)?;
+            f.write_char('(')?;
+            for (i, arg) in arguments.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write_expression(f, arg, source)?;
+            }
+            f.write_char(')')
+        }
+        Expression::UnaryOp {
+            ref operator,
+            ref right,
+        } => {
+            write!(f, "{}", operator)?;
+            write_operand(f, right, 9, source)
+        }
+        Expression::BinaryOp {
+            ref left,
+            ref operator,
+            ref right,
+        } => {
+            // all binary operators are left associative
+            let precedence = operator.precedence();
+            write_operand(f, left, precedence, source)?;
+            write!(f, " {} ", operator)?;
+            write_operand(f, right, precedence + 1, source)
+        }
+        Expression::TernaryOp {
+            ref condition,
+            ref left,
+            ref right,
+        } => {
+            write_operand(f, condition, 1, source)?;
+            f.write_str(" ? ")?;
+            write_expression(f, left, source)?;
+            f.write_str(" : ")?;
+            write_expression(f, right, source)
+        }
+    }
+}
+
+impl<'a> Display for Expression<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write_expression(f, self, None)
+    }
+}
+
+impl Display for AssignOperator {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            AssignOperator::Equals => "=",
+            AssignOperator::Plus => "+=",
+            AssignOperator::Minus => "-=",
+            AssignOperator::Multiply => "*=",
+            AssignOperator::Divide => "/=",
+            AssignOperator::ShiftLeft => "<<=",
+            AssignOperator::ShiftRight => ">>=",
+            AssignOperator::And => "&=",
+            AssignOperator::Or => "|=",
+        })
+    }
+}
+
+impl<'a> Display for Statement<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Printer::new(f, &PLAIN).statement(self, 0)
+    }
+}
+
+impl Display for InsertOrder {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            InsertOrder::Before => "BEFORE",
+            InsertOrder::After => "AFTER",
+        })
+    }
+}
+
+impl<'a> Display for Command<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Printer::new(f, &PLAIN).command(self, 0, false)
+    }
+}
+
+impl<'a> Display for Region<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut printer = Printer::new(f, &PLAIN);
+        let columns = printer.region_columns(self);
+        printer.region(self, &columns, 0, (0, 0))
+    }
+}
+
+impl<'a> Display for SectionPattern<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let (keyword, inner) = match *self {
+            SectionPattern::Simple(ref pattern) => return write_pattern(f, pattern),
+            SectionPattern::SortByName(ref pattern) => ("SORT_BY_NAME", pattern),
+            SectionPattern::SortByAlignment(ref pattern) => ("SORT_BY_ALIGNMENT", pattern),
+            SectionPattern::SortByInitPriority(ref pattern) => ("SORT_BY_INIT_PRIORITY", pattern),
+            SectionPattern::SortNone(ref pattern) => ("SORT_NONE", pattern),
+            SectionPattern::ExcludeFile {
+                ref files,
+                ref pattern,
+            } => {
+                f.write_str("EXCLUDE_FILE(")?;
+                for (i, file) in files.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(' ')?;
+                    }
+                    write_pattern(f, file)?;
+                }
+                return write!(f, ") {}", pattern);
+            }
+        };
+        write!(f, "{}(", keyword)?;
+        write_pattern(f, inner)?;
+        f.write_char(')')
+    }
+}
+
+impl Display for DataType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            DataType::Byte => "BYTE",
+            DataType::Short => "SHORT",
+            DataType::Long => "LONG",
+            DataType::Quad => "QUAD",
+        })
+    }
+}
+
+impl<'a> Display for OutputSectionCommand<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Printer::new(f, &PLAIN).output_section_command(self, 0)
+    }
+}
+
+impl Display for OutputSectionType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            OutputSectionType::NoLoad => "(NOLOAD)",
+            OutputSectionType::DSect => "(DSECT)",
+            OutputSectionType::Copy => "(COPY)",
+            OutputSectionType::Info => "(INFO)",
+            OutputSectionType::Overlay => "(OVERLAY)",
+        })
+    }
+}
+
+impl Display for OutputSectionConstraint {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            OutputSectionConstraint::OnlyIfRo => "ONLY_IF_RO",
+            OutputSectionConstraint::OnlyIfRw => "ONLY_IF_RW",
+        })
+    }
+}
+
+impl<'a> Display for SectionCommand<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Printer::new(f, &PLAIN).section_command(self, 0, false)
+    }
+}
+
+impl<'a> Display for RootItem<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Printer::new(f, &PLAIN).root_item(self)
+    }
+}
+
+impl<'a> Display for Script<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Printer::new(f, &PLAIN).items(&self.items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use print::*;
+    use script::parse;
+    use std::fs::File;
+    use std::io::Read;
+    use utils::corpus_sample;
+
+    fn roundtrip(input: &str) -> String {
+        let (_, items) = parse(input).unwrap();
+        let text = print(&items);
+        let (rest, again) = parse(&text).unwrap();
+        assert_eq!(rest, "", "unparsed output of {:?}", input);
+        assert_eq!(items, again, "printed as {:?}", text);
+        text
+    }
+
+    #[test]
+    fn test_expression() {
+        assert_eq!(roundtrip("A = (1 + 2) * 3;"), "A = (1 + 2) * 3;\n");
+        assert_eq!(roundtrip("A = 1 - (2 - 3);"), "A = 1 - (2 - 3);\n");
+        assert_eq!(roundtrip("A = 1 - 2 - 3;"), "A = 1 - 2 - 3;\n");
+        assert_eq!(roundtrip("A = -(B + 1);"), "A = -(B + 1);\n");
+        assert_eq!(roundtrip("A = (B ? 1 : 2) + 3;"), "A = (B ? 1 : 2) + 3;\n");
+        assert_eq!(
+            roundtrip("A = B ? C ? 1 : 2 : 3;"),
+            "A = B ? C ? 1 : 2 : 3;\n"
+        );
+        assert_eq!(
+            roundtrip("A = (B ? 1 : 2) ? 3 : 4;"),
+            "A = (B ? 1 : 2) ? 3 : 4;\n"
+        );
+        assert_eq!(roundtrip("A = 1 & 2 == 3;"), "A = 1 & 2 == 3;\n");
+        assert_eq!(roundtrip("A = (1 & 2) == 3;"), "A = (1 & 2) == 3;\n");
+        assert_eq!(roundtrip("A = \"B-C\" - D;"), "A = \"B-C\" - D;\n");
+        assert_eq!(roundtrip("A = \"ah\";"), "A = \"ah\";\n");
+        assert_eq!(roundtrip("A = \"aeHx\";"), "A = \"aeHx\";\n");
+        assert_eq!(roundtrip("A = 0x4400;"), "A = 0x4400;\n");
+    }
+
+    #[test]
+    fn test_sections() {
+        roundtrip(
+            "SECTIONS { .a 0x100 (NOLOAD) : AT(0x200) ALIGN(4) { *(.a) } > RAM AT> ROM = 0xFF }",
+        );
+        roundtrip("SECTIONS { .a foo (NOLOAD) : { KEEP(*(SORT(.ctors.*))) } }");
+        roundtrip("SECTIONS { .a A ? 1 : 2 : { LONG(0) FILL(1) } }");
+        roundtrip("SECTIONS { /DISCARD/ : { *(.note.GNU-stack) } }");
+        roundtrip("SECTIONS { .a : { *(EXCLUDE_FILE(*crtend.o *b.o) .ctors) \"LONG\"(.x) } }");
+        roundtrip("MEMORY { ROM (rx) : ORIGIN = 0, LENGTH = 4K /* rom */ }");
+        roundtrip("SECTIONS { INCLUDE a.ld*; .b : {} }");
+        roundtrip("SECTIONS { .a : {} = 0xFF, /DISCARD/ : {} }");
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for path in corpus_sample() {
+            let mut file = File::open(&path).unwrap();
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).unwrap();
+            let (_, items) = parse(&contents).unwrap();
+            let text = print(&items);
+            let (rest, again) = parse(&text).unwrap();
+            assert_eq!(rest, "", "{:?} printed unparsable output", path);
+            assert!(items == again, "{:?} does not round trip", path);
+        }
+    }
+}
@@ -1,60 +1,365 @@
+use alloc::vec;
+use alloc::vec::Vec;
 use commands::{command, Command};
+use core::slice;
+use expressions::Expression;
+use intern::Interner;
+use layout::{Layout, LayoutError};
+use manifest::Manifest;
+use matching::Matcher;
 use memory::region;
 use memory::Region;
 use nom::branch::alt;
 use nom::bytes::complete::tag;
 use nom::combinator::map;
-use nom::multi::many1;
-use nom::sequence::tuple;
+use nom::sequence::{preceded, terminated, tuple};
 use nom::IResult;
 use sections::section_command;
-use sections::SectionCommand;
+use sections::{OutputSectionCommand, SectionCommand};
 use statements::{statement, Statement};
-use whitespace::opt_space;
+use symbols::SymbolTable;
+use whitespace::{commented_many1, opt_space, Comment, Commented};
 
-#[derive(Debug, PartialEq)]
-pub enum RootItem {
-    Statement(Statement),
-    Command(Command),
-    Memory { regions: Vec<Region> },
-    Sections { list: Vec<SectionCommand> },
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
+pub enum RootItem<'a> {
+    Statement(Statement<'a>),
+    Command(Command<'a>),
+    Memory {
+        regions: Vec<Region<'a>>,
+        comments: Vec<Comment<'a>>,
+    },
+    Sections {
+        list: Vec<SectionCommand<'a>>,
+        comments: Vec<Comment<'a>>,
+    },
 }
 
-fn statement_item(input: &str) -> IResult<&str, RootItem> {
-    map(statement, |stmt| RootItem::Statement(stmt))(input)
+impl<'a> RootItem<'a> {
+    /// Converts the item into one that owns all of its strings
+    pub fn into_owned(self) -> RootItem<'static> {
+        match self {
+            RootItem::Statement(stmt) => RootItem::Statement(stmt.into_owned()),
+            RootItem::Command(cmd) => RootItem::Command(cmd.into_owned()),
+            RootItem::Memory { regions, comments } => RootItem::Memory {
+                regions: regions.into_iter().map(Region::into_owned).collect(),
+                comments: comments.into_iter().map(Comment::into_owned).collect(),
+            },
+            RootItem::Sections { list, comments } => RootItem::Sections {
+                list: list.into_iter().map(SectionCommand::into_owned).collect(),
+                comments: comments.into_iter().map(Comment::into_owned).collect(),
+            },
+        }
+    }
 }
 
-fn command_item(input: &str) -> IResult<&str, RootItem> {
-    map(command, |cmd| RootItem::Command(cmd))(input)
+impl<'a> Commented<'a> for RootItem<'a> {
+    fn comments_mut(&mut self) -> Option<&mut Vec<Comment<'a>>> {
+        match *self {
+            RootItem::Statement(ref mut stmt) => stmt.comments_mut(),
+            RootItem::Command(ref mut cmd) => cmd.comments_mut(),
+            RootItem::Memory {
+                ref mut comments, ..
+            }
+            | RootItem::Sections {
+                ref mut comments, ..
+            } => Some(comments),
+        }
+    }
 }
 
-fn memory_item(input: &str) -> IResult<&str, RootItem> {
-    let (input, _) = tuple((tag("MEMORY"), wsc!(tag("{"))))(input)?;
-    let (input, regions) = many1(wsc!(region))(input)?;
-    let (input, _) = tag("}")(input)?;
-    Ok((input, RootItem::Memory { regions: regions }))
+/// A parsed linker script with queries over its items.
+///
+/// The queries look into every MEMORY and SECTIONS block and return the
+/// results in source order.
+///
+/// ```
+/// extern crate ldscript_parser as lds;
+///
+/// fn main() {
+///     let script = lds::Script::from(lds::parse("
+///         ENTRY(Reset_Handler)
+///         MEMORY { FLASH : ORIGIN = 0, LENGTH = 64K }
+///         MEMORY { RAM : ORIGIN = 0x20000000, LENGTH = 20K }
+///         SECTIONS { .text : { *(.text) } > FLASH }
+///     ").unwrap());
+///     assert_eq!(script.entry(), Some("Reset_Handler"));
+///     assert_eq!(script.regions().count(), 2);
+///     assert_eq!(script.region("RAM").unwrap().length, 20 * 1024);
+///     assert!(script.output_section(".text").is_some());
+/// }
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Script<'a> {
+    pub items: Vec<RootItem<'a>>,
 }
 
-fn sections_item(input: &str) -> IResult<&str, RootItem> {
-    let (input, _) = tuple((tag("SECTIONS"), wsc!(tag("{"))))(input)?;
-    let (input, sections) = many1(wsc!(section_command))(input)?;
-    let (input, _) = tag("}")(input)?;
-    Ok((input, RootItem::Sections { list: sections }))
+impl<'a> From<Vec<RootItem<'a>>> for Script<'a> {
+    fn from(items: Vec<RootItem<'a>>) -> Self {
+        Script { items }
+    }
 }
 
-fn root_item(input: &str) -> IResult<&str, RootItem> {
+impl<'a> Script<'a> {
+    /// Converts the script into one that owns all of its strings
+    pub fn into_owned(self) -> Script<'static> {
+        Script {
+            items: self.items.into_iter().map(RootItem::into_owned).collect(),
+        }
+    }
+
+    /// Iterates over the root items in source order
+    pub fn iter(&self) -> slice::Iter<'_, RootItem<'a>> {
+        self.items.iter()
+    }
+
+    /// Iterates over the commands of all SECTIONS blocks
+    pub fn section_commands(&self) -> impl Iterator<Item = &SectionCommand<'a>> {
+        self.items.iter().flat_map(|item| match *item {
+            RootItem::Sections { ref list, .. } => list.iter(),
+            _ => [].iter(),
+        })
+    }
+
+    /// Iterates over the regions of all MEMORY blocks
+    pub fn regions(&self) -> impl Iterator<Item = &Region<'a>> {
+        self.items.iter().flat_map(|item| match *item {
+            RootItem::Memory { ref regions, .. } => regions.iter(),
+            _ => [].iter(),
+        })
+    }
+
+    /// Returns the region with the given name
+    pub fn region(&self, name: &str) -> Option<&Region<'a>> {
+        self.regions().find(|region| region.name == name)
+    }
+
+    /// Iterates over the output sections, which are always
+    /// `SectionCommand::OutputSection`
+    pub fn output_sections(&self) -> impl Iterator<Item = &SectionCommand<'a>> {
+        self.section_commands().filter(|cmd| match **cmd {
+            SectionCommand::OutputSection { .. } => true,
+            SectionCommand::Statement(_) | SectionCommand::Command(_) => false,
+        })
+    }
+
+    /// Returns the first output section with the given name
+    pub fn output_section(&self, name: &str) -> Option<&SectionCommand<'a>> {
+        self.output_sections().find(|cmd| match **cmd {
+            SectionCommand::OutputSection { name: ref n, .. } => n == name,
+            _ => false,
+        })
+    }
+
+    /// Iterates over the statements at the top level, in SECTIONS blocks
+    /// and in output section bodies
+    pub fn statements(&self) -> impl Iterator<Item = &Statement<'a>> {
+        self.items.iter().flat_map(|item| {
+            let mut found = Vec::new();
+            match *item {
+                RootItem::Statement(ref stmt) => found.push(stmt),
+                RootItem::Sections { ref list, .. } => {
+                    for cmd in list {
+                        match *cmd {
+                            SectionCommand::Statement(ref stmt) => found.push(stmt),
+                            SectionCommand::OutputSection { ref content, .. } => {
+                                for osc in content {
+                                    if let OutputSectionCommand::Statement(ref stmt) = *osc {
+                                        found.push(stmt);
+                                    }
+                                }
+                            }
+                            SectionCommand::Command(_) => {}
+                        }
+                    }
+                }
+                RootItem::Command(_) | RootItem::Memory { .. } => {}
+            }
+            found
+        })
+    }
+
+    /// Names of the symbols assigned or provided by the script, in the
+    /// order of their first definition. The location counter is not
+    /// included.
+    pub fn symbols_defined(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for name in self.statements().filter_map(Statement::name) {
+            if name != "." && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// Places the output sections and input sections of `manifest` the way
+    /// the linker would
+    pub fn layout(&self, manifest: &Manifest) -> Result<Layout, LayoutError> {
+        Layout::compute(&self.items, manifest)
+    }
+
+    /// Returns the input section commands, which assign input sections to
+    /// output sections
+    pub fn matcher(&self) -> Matcher<'_, 'a> {
+        Matcher::new(&self.items)
+    }
+
+    /// Returns the symbols defined and referenced by the script
+    pub fn symbol_table(&self) -> SymbolTable<'a> {
+        SymbolTable::new(&self.items)
+    }
+
+    /// Builds an interner that holds the symbol, region and output section
+    /// names used in the script. Each call walks the script again, keep the
+    /// interner to reuse its handles.
+    pub fn interner(&self) -> Interner {
+        let mut interner = Interner::new();
+        interner.intern_items(&self.items);
+        interner
+    }
+
+    /// Iterates over the commands at the top level and in SECTIONS blocks
+    pub fn commands(&self) -> impl Iterator<Item = &Command<'a>> {
+        self.items.iter().flat_map(|item| {
+            let mut found = Vec::new();
+            match *item {
+                RootItem::Command(ref cmd) => found.push(cmd),
+                RootItem::Sections { ref list, .. } => {
+                    for cmd in list {
+                        if let SectionCommand::Command(ref cmd) = *cmd {
+                            found.push(cmd);
+                        }
+                    }
+                }
+                RootItem::Statement(_) | RootItem::Memory { .. } => {}
+            }
+            found
+        })
+    }
+
+    /// Returns the symbol argument of the last call with this name, e.g.
+    /// `ENTRY(_start)`
+    fn call_argument(&self, function: &str) -> Option<&str> {
+        let mut found = None;
+        for cmd in self.commands() {
+            if let Command::Call {
+                ref name,
+                ref arguments,
+                ..
+            } = *cmd
+            {
+                if name == function {
+                    if let Some(Expression::Ident(arg)) = arguments.first() {
+                        found = Some(&**arg);
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    /// The entry point set by the last `ENTRY` command
+    pub fn entry(&self) -> Option<&str> {
+        self.call_argument("ENTRY")
+    }
+
+    /// The architecture set by the last `OUTPUT_ARCH` command
+    pub fn output_arch(&self) -> Option<&str> {
+        self.call_argument("OUTPUT_ARCH")
+    }
+
+    /// Iterates over the files named by `INCLUDE` commands
+    pub fn includes(&self) -> impl Iterator<Item = &str> {
+        self.commands().filter_map(|cmd| match *cmd {
+            Command::Include { ref file, .. } => Some(&**file),
+            _ => None,
+        })
+    }
+}
+
+impl<'s, 'a> IntoIterator for &'s Script<'a> {
+    type Item = &'s RootItem<'a>;
+    type IntoIter = slice::Iter<'s, RootItem<'a>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+impl<'a> IntoIterator for Script<'a> {
+    type Item = RootItem<'a>;
+    type IntoIter = vec::IntoIter<RootItem<'a>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+fn statement_item(input: &str) -> IResult<&str, RootItem<'_>> {
+    map(statement, RootItem::Statement)(input)
+}
+
+fn command_item(input: &str) -> IResult<&str, RootItem<'_>> {
+    map(command, RootItem::Command)(input)
+}
+
+fn memory_item(input: &str) -> IResult<&str, RootItem<'_>> {
+    let (input, _) = tuple((tag("MEMORY"), opt_space, tag("{")))(input)?;
+    let (input, regions) = commented_many1(region)(input)?;
+    let (input, _) = preceded(opt_space, tag("}"))(input)?;
+    Ok((
+        input,
+        RootItem::Memory {
+            regions,
+            comments: Vec::new(),
+        },
+    ))
+}
+
+fn sections_item(input: &str) -> IResult<&str, RootItem<'_>> {
+    let (input, _) = tuple((tag("SECTIONS"), opt_space, tag("{")))(input)?;
+    let (input, sections) = commented_many1(section_command)(input)?;
+    let (input, _) = preceded(opt_space, tag("}"))(input)?;
+    Ok((
+        input,
+        RootItem::Sections {
+            list: sections,
+            comments: Vec::new(),
+        },
+    ))
+}
+
+pub fn root_item(input: &str) -> IResult<&str, RootItem<'_>> {
     alt((statement_item, memory_item, sections_item, command_item))(input)
 }
 
-pub fn parse(input: &str) -> IResult<&str, Vec<RootItem>> {
-    alt((many1(wsc!(root_item)), map(opt_space, |_| vec![])))(input)
+pub fn parse(input: &str) -> IResult<&str, Vec<RootItem<'_>>> {
+    alt((
+        terminated(commented_many1(root_item), opt_space),
+        map(opt_space, |_| vec![]),
+    ))(input)
+}
+
+/// Parses the section commands of a file included inside a SECTIONS block
+pub fn parse_section_commands(input: &str) -> IResult<&str, Vec<SectionCommand<'_>>> {
+    alt((
+        terminated(commented_many1(section_command), opt_space),
+        map(opt_space, |_| vec![]),
+    ))(input)
 }
 
 #[cfg(test)]
 mod tests {
+    use alloc::string::String;
     use script::*;
+    use sections::OutputSectionCommand;
+    use std::borrow::Cow;
     use std::fs::{self, File};
     use std::io::Read;
+    use std::println;
+    use whitespace::CommentPosition;
 
     #[test]
     fn test_empty() {
@@ -63,6 +368,149 @@ mod tests {
         assert_done_vec!(parse("      /* hello */              "), 0);
     }
 
+    #[test]
+    fn test_borrowed() {
+        let input = String::from("PROVIDE ( __stack = ORIGIN(RAM) + LENGTH(RAM) );");
+        let (_, items) = parse(&input).unwrap();
+        match items[0] {
+            RootItem::Statement(Statement::Provide { ref name, .. }) => match *name {
+                Cow::Borrowed(name) => assert_eq!(name, "__stack"),
+                Cow::Owned(_) => panic!("name should borrow from the input"),
+            },
+            _ => panic!("expected PROVIDE"),
+        }
+
+        let owned: Vec<RootItem<'static>> = items.into_iter().map(RootItem::into_owned).collect();
+        drop(input);
+        match owned[0] {
+            RootItem::Statement(Statement::Provide { ref name, .. }) => {
+                assert_eq!(name, "__stack")
+            }
+            _ => panic!("expected PROVIDE"),
+        }
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "
+            /* header */
+            MEMORY {
+                /* ram */
+                RAM : ORIGIN = 0x2400, LENGTH = 0x2000 /* END=0x43FF */
+                ROM : ORIGIN = 0x4400, LENGTH = 0xBB80
+            }
+            SECTIONS {
+                .infoA : {} > INFOA /* INFO */
+                .text : {
+                    /* start */
+                    . = ALIGN(2); /* align */
+                    *(.text)
+                }
+            }
+        ";
+        let (_, items) = parse(input).unwrap();
+        assert_eq!(items.len(), 2);
+
+        let leading = |text: &'static str| Comment {
+            text: text.into(),
+            position: CommentPosition::Leading,
+        };
+        let trailing = |text: &'static str| Comment {
+            text: text.into(),
+            position: CommentPosition::Trailing,
+        };
+
+        match items[0] {
+            RootItem::Memory { ref regions, .. } => {
+                assert_eq!(
+                    regions[0].comments,
+                    vec![leading(" ram "), trailing(" END=0x43FF ")]
+                );
+                assert_eq!(regions[1].comments, vec![]);
+            }
+            _ => panic!("expected MEMORY"),
+        }
+        match items[1] {
+            RootItem::Sections { ref list, .. } => {
+                assert_eq!(list[0].comments(), &[trailing(" INFO ")][..]);
+                assert_eq!(list[1].comments(), &[][..]);
+                match list[1] {
+                    SectionCommand::OutputSection { ref content, .. } => match content[0] {
+                        OutputSectionCommand::Statement(ref stmt) => assert_eq!(
+                            stmt.comments(),
+                            &[leading(" start "), trailing(" align ")][..]
+                        ),
+                        _ => panic!("expected statement"),
+                    },
+                    _ => panic!("expected output section"),
+                }
+            }
+            _ => panic!("expected SECTIONS"),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        extern crate serde_json;
+
+        for name in &["tests/armelf.x", "tests/msp430f5529.ld"] {
+            let mut contents = String::new();
+            File::open(name)
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            let (_, items) = parse(&contents).unwrap();
+            let json = serde_json::to_string(&items).unwrap();
+            let back: Vec<RootItem> = serde_json::from_str(&json).unwrap();
+            assert_eq!(items, back);
+        }
+    }
+
+    #[test]
+    fn test_script() {
+        let input = "
+            OUTPUT_ARCH(msp430)
+            ENTRY(_start)
+            INCLUDE common.ld
+            MEMORY { ROM : ORIGIN = 0xF000, LENGTH = 0x1000 }
+            SECTIONS {
+                .text : { *(.text) _etext = .; } > ROM
+                INCLUDE extra.ld
+            }
+            MEMORY { RAM : ORIGIN = 0x200, LENGTH = 0x100 }
+            SECTIONS {
+                . = 0x200;
+                .data : { PROVIDE(_data = .); } > RAM AT> ROM
+                _etext = .;
+            }
+            __stack = ORIGIN(RAM) + LENGTH(RAM);
+        ";
+        let (_, items) = parse(input).unwrap();
+        let script = Script::from(items);
+
+        assert_eq!(script.output_arch(), Some("msp430"));
+        assert_eq!(script.entry(), Some("_start"));
+        assert_eq!(
+            script.includes().collect::<Vec<_>>(),
+            vec!["common.ld", "extra.ld"]
+        );
+        let regions: Vec<_> = script.regions().map(|r| &*r.name).collect();
+        assert_eq!(regions, vec!["ROM", "RAM"]);
+        assert_eq!(script.region("RAM").unwrap().origin, 0x200);
+        assert!(script.region("FLASH").is_none());
+        assert_eq!(script.output_sections().count(), 2);
+        match *script.output_section(".data").unwrap() {
+            SectionCommand::OutputSection { ref lma_region, .. } => {
+                assert_eq!(lma_region.as_ref().unwrap(), "ROM")
+            }
+            _ => panic!(),
+        }
+        assert_eq!(script.symbols_defined(), vec!["_etext", "_data", "__stack"]);
+        assert_eq!(script.iter().count(), 8);
+        assert_eq!((&script).into_iter().count(), 8);
+    }
+
     #[test]
     fn test_parse() {
         for entry in fs::read_dir("tests").unwrap() {
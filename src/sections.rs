@@ -1,3 +1,6 @@
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 use commands::{command, Command};
 use expressions::expression;
 use expressions::Expression;
@@ -8,56 +11,212 @@ use nom::bytes::complete::tag;
 use nom::combinator::cut;
 use nom::combinator::map;
 use nom::combinator::opt;
-use nom::multi::many0;
 use nom::multi::many1;
 use nom::sequence::delimited;
 use nom::sequence::preceded;
 use nom::sequence::tuple;
 use nom::IResult;
+use options::check_depth;
 use statements::{statement, Statement};
-use whitespace::opt_space;
+use whitespace::{commented_many0, opt_space, Comment, Commented};
 
-#[derive(Debug, PartialEq)]
-pub enum SectionCommand {
-    Statement(Statement),
-    Command(Command),
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
+pub enum SectionCommand<'a> {
+    Statement(Statement<'a>),
+    Command(Command<'a>),
     OutputSection {
-        name: String,
-        vma_address: Option<Box<Expression>>,
+        name: Cow<'a, str>,
+        vma_address: Option<Box<Expression<'a>>>,
         s_type: Option<OutputSectionType>,
-        lma_address: Option<Box<Expression>>,
-        section_align: Option<Box<Expression>>,
+        lma_address: Option<Box<Expression<'a>>>,
+        section_align: Option<Box<Expression<'a>>>,
         align_with_input: bool,
-        subsection_align: Option<Box<Expression>>,
+        subsection_align: Option<Box<Expression<'a>>>,
         constraint: Option<OutputSectionConstraint>,
-        content: Vec<OutputSectionCommand>,
-        region: Option<String>,
-        lma_region: Option<String>,
-        fillexp: Option<Box<Expression>>,
+        content: Vec<OutputSectionCommand<'a>>,
+        region: Option<Cow<'a, str>>,
+        lma_region: Option<Cow<'a, str>>,
+        fillexp: Option<Box<Expression<'a>>>,
+        comments: Vec<Comment<'a>>,
     },
 }
 
-#[derive(Debug, PartialEq)]
-pub enum OutputSectionCommand {
-    Statement(Statement),
+fn owned_str(s: Cow<str>) -> Cow<'static, str> {
+    Cow::Owned(s.into_owned())
+}
+
+impl<'a> SectionCommand<'a> {
+    /// Comments attached to this command
+    pub fn comments(&self) -> &[Comment<'a>] {
+        match *self {
+            SectionCommand::Statement(ref stmt) => stmt.comments(),
+            SectionCommand::Command(ref cmd) => cmd.comments(),
+            SectionCommand::OutputSection { ref comments, .. } => comments,
+        }
+    }
+
+    /// Converts the command into one that owns all of its strings
+    pub fn into_owned(self) -> SectionCommand<'static> {
+        match self {
+            SectionCommand::Statement(stmt) => SectionCommand::Statement(stmt.into_owned()),
+            SectionCommand::Command(cmd) => SectionCommand::Command(cmd.into_owned()),
+            SectionCommand::OutputSection {
+                name,
+                vma_address,
+                s_type,
+                lma_address,
+                section_align,
+                align_with_input,
+                subsection_align,
+                constraint,
+                content,
+                region,
+                lma_region,
+                fillexp,
+                comments,
+            } => SectionCommand::OutputSection {
+                name: owned_str(name),
+                vma_address: vma_address.map(|e| Box::new(e.into_owned())),
+                s_type,
+                lma_address: lma_address.map(|e| Box::new(e.into_owned())),
+                section_align: section_align.map(|e| Box::new(e.into_owned())),
+                align_with_input,
+                subsection_align: subsection_align.map(|e| Box::new(e.into_owned())),
+                constraint,
+                content: content
+                    .into_iter()
+                    .map(OutputSectionCommand::into_owned)
+                    .collect(),
+                region: region.map(owned_str),
+                lma_region: lma_region.map(owned_str),
+                fillexp: fillexp.map(|e| Box::new(e.into_owned())),
+                comments: comments.into_iter().map(Comment::into_owned).collect(),
+            },
+        }
+    }
+}
+
+impl<'a> Commented<'a> for SectionCommand<'a> {
+    fn comments_mut(&mut self) -> Option<&mut Vec<Comment<'a>>> {
+        match *self {
+            SectionCommand::Statement(ref mut stmt) => stmt.comments_mut(),
+            SectionCommand::Command(ref mut cmd) => cmd.comments_mut(),
+            SectionCommand::OutputSection {
+                ref mut comments, ..
+            } => Some(comments),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
+pub enum OutputSectionCommand<'a> {
+    Statement(Statement<'a>),
     Fill {
-        expr: Box<Expression>,
+        expr: Box<Expression<'a>>,
+        comments: Vec<Comment<'a>>,
     },
     Data {
         d_type: DataType,
-        value: Box<Expression>,
+        value: Box<Expression<'a>>,
+        comments: Vec<Comment<'a>>,
     },
     InputSection {
-        file: SectionPattern,
-        sections: Vec<SectionPattern>,
+        file: SectionPattern<'a>,
+        sections: Vec<SectionPattern<'a>>,
+        comments: Vec<Comment<'a>>,
     },
     KeepInputSection {
-        file: SectionPattern,
-        sections: Vec<SectionPattern>,
+        file: SectionPattern<'a>,
+        sections: Vec<SectionPattern<'a>>,
+        comments: Vec<Comment<'a>>,
     },
 }
 
-#[derive(Debug, PartialEq)]
+impl<'a> OutputSectionCommand<'a> {
+    /// Comments attached to this command
+    pub fn comments(&self) -> &[Comment<'a>] {
+        match *self {
+            OutputSectionCommand::Statement(ref stmt) => stmt.comments(),
+            OutputSectionCommand::Fill { ref comments, .. }
+            | OutputSectionCommand::Data { ref comments, .. }
+            | OutputSectionCommand::InputSection { ref comments, .. }
+            | OutputSectionCommand::KeepInputSection { ref comments, .. } => comments,
+        }
+    }
+
+    /// Converts the command into one that owns all of its strings
+    pub fn into_owned(self) -> OutputSectionCommand<'static> {
+        match self {
+            OutputSectionCommand::Statement(stmt) => {
+                OutputSectionCommand::Statement(stmt.into_owned())
+            }
+            OutputSectionCommand::Fill { expr, comments } => OutputSectionCommand::Fill {
+                expr: Box::new(expr.into_owned()),
+                comments: comments.into_iter().map(Comment::into_owned).collect(),
+            },
+            OutputSectionCommand::Data {
+                d_type,
+                value,
+                comments,
+            } => OutputSectionCommand::Data {
+                d_type,
+                value: Box::new(value.into_owned()),
+                comments: comments.into_iter().map(Comment::into_owned).collect(),
+            },
+            OutputSectionCommand::InputSection {
+                file,
+                sections,
+                comments,
+            } => OutputSectionCommand::InputSection {
+                file: file.into_owned(),
+                sections: sections
+                    .into_iter()
+                    .map(SectionPattern::into_owned)
+                    .collect(),
+                comments: comments.into_iter().map(Comment::into_owned).collect(),
+            },
+            OutputSectionCommand::KeepInputSection {
+                file,
+                sections,
+                comments,
+            } => OutputSectionCommand::KeepInputSection {
+                file: file.into_owned(),
+                sections: sections
+                    .into_iter()
+                    .map(SectionPattern::into_owned)
+                    .collect(),
+                comments: comments.into_iter().map(Comment::into_owned).collect(),
+            },
+        }
+    }
+}
+
+impl<'a> Commented<'a> for OutputSectionCommand<'a> {
+    fn comments_mut(&mut self) -> Option<&mut Vec<Comment<'a>>> {
+        match *self {
+            OutputSectionCommand::Statement(ref mut stmt) => stmt.comments_mut(),
+            OutputSectionCommand::Fill {
+                ref mut comments, ..
+            }
+            | OutputSectionCommand::Data {
+                ref mut comments, ..
+            }
+            | OutputSectionCommand::InputSection {
+                ref mut comments, ..
+            }
+            | OutputSectionCommand::KeepInputSection {
+                ref mut comments, ..
+            } => Some(comments),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum DataType {
     Byte,
     Short,
@@ -65,20 +224,60 @@ pub enum DataType {
     Quad,
 }
 
-#[derive(Debug, PartialEq)]
-pub enum SectionPattern {
-    Simple(String),
-    SortByName(String),
-    SortByAlignment(String),
-    SortByInitPriority(String),
-    SortNone(String),
+impl DataType {
+    /// Number of bytes the data takes up
+    pub fn size(&self) -> u64 {
+        match *self {
+            DataType::Byte => 1,
+            DataType::Short => 2,
+            DataType::Long => 4,
+            DataType::Quad => 8,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
+pub enum SectionPattern<'a> {
+    Simple(Cow<'a, str>),
+    SortByName(Cow<'a, str>),
+    SortByAlignment(Cow<'a, str>),
+    SortByInitPriority(Cow<'a, str>),
+    SortNone(Cow<'a, str>),
     ExcludeFile {
-        files: Vec<String>,
-        pattern: Box<SectionPattern>,
+        files: Vec<Cow<'a, str>>,
+        pattern: Box<SectionPattern<'a>>,
     },
 }
 
-#[derive(Debug, PartialEq)]
+impl<'a> SectionPattern<'a> {
+    /// Converts the pattern into one that owns all of its strings
+    pub fn into_owned(self) -> SectionPattern<'static> {
+        match self {
+            SectionPattern::Simple(s) => SectionPattern::Simple(owned_str(s)),
+            SectionPattern::SortByName(s) => SectionPattern::SortByName(owned_str(s)),
+            SectionPattern::SortByAlignment(s) => SectionPattern::SortByAlignment(owned_str(s)),
+            SectionPattern::SortByInitPriority(s) => {
+                SectionPattern::SortByInitPriority(owned_str(s))
+            }
+            SectionPattern::SortNone(s) => SectionPattern::SortNone(owned_str(s)),
+            SectionPattern::ExcludeFile { files, pattern } => SectionPattern::ExcludeFile {
+                files: files.into_iter().map(owned_str).collect(),
+                pattern: Box::new(pattern.into_owned()),
+            },
+        }
+    }
+}
+
+impl<'a> From<&'a str> for SectionPattern<'a> {
+    fn from(pattern: &'a str) -> Self {
+        SectionPattern::Simple(pattern.into())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum OutputSectionType {
     NoLoad,
     DSect,
@@ -87,7 +286,8 @@ pub enum OutputSectionType {
     Overlay,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum OutputSectionConstraint {
     OnlyIfRo,
     OnlyIfRw,
@@ -110,7 +310,7 @@ fn output_section_constraint(input: &str) -> IResult<&str, OutputSectionConstrai
     ))(input)
 }
 
-fn sorted_sp(input: &str) -> IResult<&str, SectionPattern> {
+fn sorted_sp(input: &str) -> IResult<&str, SectionPattern<'_>> {
     let (input, keyword) = alt((
         tag("SORT_BY_NAME"),
         tag("SORT_BY_ALIGNMENT"),
@@ -134,33 +334,39 @@ fn sorted_sp(input: &str) -> IResult<&str, SectionPattern> {
     ))
 }
 
-fn exclude_file_sp(input: &str) -> IResult<&str, SectionPattern> {
+fn exclude_file_sp(input: &str, depth: usize) -> IResult<&str, SectionPattern<'_>> {
     let (input, _) = tuple((tag("EXCLUDE_FILE"), opt_space, tag("(")))(input)?;
-    let (input, files) = cut(many1(wsc!(map(pattern, String::from))))(input)?;
+    let (input, files) = cut(many1(wsc!(map(pattern, Cow::Borrowed))))(input)?;
     let (input, _) = cut(tuple((tag(")"), opt_space)))(input)?;
-    let (input, inner) = cut(section_pattern)(input)?;
+    check_depth(input, depth + 1)?;
+    let (input, inner) = cut(|i| nested_section_pattern(i, depth + 1))(input)?;
     Ok((
         input,
         SectionPattern::ExcludeFile {
-            files: files,
+            files,
             pattern: Box::new(inner),
         },
     ))
 }
 
-fn simple_sp(input: &str) -> IResult<&str, SectionPattern> {
-    map(pattern, |x: &str| SectionPattern::Simple(x.into()))(input)
+fn simple_sp(input: &str) -> IResult<&str, SectionPattern<'_>> {
+    map(pattern, |x| SectionPattern::Simple(x.into()))(input)
 }
 
-fn section_pattern(input: &str) -> IResult<&str, SectionPattern> {
-    alt((exclude_file_sp, sorted_sp, simple_sp))(input)
+fn nested_section_pattern(input: &str, depth: usize) -> IResult<&str, SectionPattern<'_>> {
+    alt((|i| exclude_file_sp(i, depth), sorted_sp, simple_sp))(input)
 }
 
-fn data_osc(input: &str) -> IResult<&str, OutputSectionCommand> {
+fn section_pattern(input: &str) -> IResult<&str, SectionPattern<'_>> {
+    nested_section_pattern(input, 0)
+}
+
+fn data_osc(input: &str) -> IResult<&str, OutputSectionCommand<'_>> {
     let (input, d_type) = alt((tag("BYTE"), tag("SHORT"), tag("LONG"), tag("QUAD")))(input)?;
     let (input, _) = wsc!(tag("("))(input)?;
     let (input, value) = expression(input)?;
-    let (input, _) = tuple((wsc!(tag(")")), opt(tag(";"))))(input)?;
+    let (input, _) = preceded(opt_space, tag(")"))(input)?;
+    let (input, _) = opt(preceded(opt_space, tag(";")))(input)?;
     Ok((
         input,
         OutputSectionCommand::Data {
@@ -172,77 +378,80 @@ fn data_osc(input: &str) -> IResult<&str, OutputSectionCommand> {
                 _ => panic!("invalid data type"),
             },
             value: Box::new(value),
+            comments: Vec::new(),
         },
     ))
 }
 
-fn fill_osc(input: &str) -> IResult<&str, OutputSectionCommand> {
+fn fill_osc(input: &str) -> IResult<&str, OutputSectionCommand<'_>> {
     let (input, _) = tuple((tag("FILL"), wsc!(tag("("))))(input)?;
     let (input, expr) = expression(input)?;
-    let (input, _) = tuple((wsc!(tag(")")), opt(tag(";"))))(input)?;
+    let (input, _) = preceded(opt_space, tag(")"))(input)?;
+    let (input, _) = opt(preceded(opt_space, tag(";")))(input)?;
     Ok((
         input,
         OutputSectionCommand::Fill {
             expr: Box::new(expr),
+            comments: Vec::new(),
         },
     ))
 }
 
-fn statement_osc(input: &str) -> IResult<&str, OutputSectionCommand> {
-    map(statement, |stmt| OutputSectionCommand::Statement(stmt))(input)
+fn statement_osc(input: &str) -> IResult<&str, OutputSectionCommand<'_>> {
+    map(statement, OutputSectionCommand::Statement)(input)
 }
 
-fn input_osc(input: &str) -> IResult<&str, OutputSectionCommand> {
+fn input_osc(input: &str) -> IResult<&str, OutputSectionCommand<'_>> {
     let (input, file) = section_pattern(input)?;
-    let (input, _) = opt_space(input)?;
     let (input, sections) = opt(delimited(
         wsc!(tag("(")),
         many1(wsc!(section_pattern)),
-        wsc!(tag(")")),
+        tag(")"),
     ))(input)?;
     Ok((
         input,
         OutputSectionCommand::InputSection {
-            file: file,
-            sections: match sections {
-                Some(s) => s,
-                None => Vec::new(),
-            },
+            file,
+            sections: sections.unwrap_or_default(),
+            comments: Vec::new(),
         },
     ))
 }
 
-fn keep_osc(input: &str) -> IResult<&str, OutputSectionCommand> {
+fn keep_osc(input: &str) -> IResult<&str, OutputSectionCommand<'_>> {
     let (input, _) = tuple((tag("KEEP"), wsc!(tag("("))))(input)?;
     let (input, inner) = input_osc(input)?;
-    let (input, _) = wsc!(tag(")"))(input)?;
+    let (input, _) = preceded(opt_space, tag(")"))(input)?;
     Ok((
         input,
         match inner {
-            OutputSectionCommand::InputSection { file, sections } => {
-                OutputSectionCommand::KeepInputSection {
-                    file: file,
-                    sections: sections,
-                }
-            }
+            OutputSectionCommand::InputSection {
+                file,
+                sections,
+                comments,
+            } => OutputSectionCommand::KeepInputSection {
+                file,
+                sections,
+                comments,
+            },
             _ => panic!("wrong output section command"),
         },
     ))
 }
 
-fn output_section_command(input: &str) -> IResult<&str, OutputSectionCommand> {
+pub fn output_section_command(input: &str) -> IResult<&str, OutputSectionCommand<'_>> {
     alt((statement_osc, keep_osc, data_osc, fill_osc, input_osc))(input)
 }
 
-fn statement_sc(input: &str) -> IResult<&str, SectionCommand> {
-    map(statement, |stmt| SectionCommand::Statement(stmt))(input)
+fn statement_sc(input: &str) -> IResult<&str, SectionCommand<'_>> {
+    map(statement, SectionCommand::Statement)(input)
 }
 
-fn command_sc(input: &str) -> IResult<&str, SectionCommand> {
-    map(command, |cmd| SectionCommand::Command(cmd))(input)
+fn command_sc(input: &str) -> IResult<&str, SectionCommand<'_>> {
+    map(command, SectionCommand::Command)(input)
 }
 
-fn output_sc(input: &str) -> IResult<&str, SectionCommand> {
+fn output_sc(input: &str) -> IResult<&str, SectionCommand<'_>> {
     let (input, name) = alt((tag("/DISCARD/"), symbol))(input)?;
     let (input, _) = opt_space(input)?;
     let (input, s_type1) = opt(output_section_type)(input)?;
@@ -256,13 +465,13 @@ fn output_sc(input: &str) -> IResult<&str, SectionCommand> {
     let (input, subsection_align) =
         opt(delimited(tag("SUBALIGN("), wsc!(expression), tag(")")))(input)?;
     let (input, constraint) = wsc!(opt(output_section_constraint))(input)?;
-    let (input, _) = wsc!(tag("{"))(input)?;
-    let (input, content) = many0(wsc!(output_section_command))(input)?;
-    let (input, _) = wsc!(tag("}"))(input)?;
-    let (input, region) = opt(preceded(tag(">"), wsc!(symbol)))(input)?;
-    let (input, lma_region) = opt(preceded(tag("AT>"), wsc!(symbol)))(input)?;
-    let (input, fillexp) = opt(preceded(tag("="), wsc!(expression)))(input)?;
-    let (input, _) = opt(tag(","))(input)?;
+    let (input, _) = preceded(opt_space, tag("{"))(input)?;
+    let (input, content) = commented_many0(output_section_command)(input)?;
+    let (input, _) = preceded(opt_space, tag("}"))(input)?;
+    let (input, region) = opt(preceded(wsc!(tag(">")), symbol))(input)?;
+    let (input, lma_region) = opt(preceded(wsc!(tag("AT>")), symbol))(input)?;
+    let (input, fillexp) = opt(preceded(wsc!(tag("=")), expression))(input)?;
+    let (input, _) = opt(preceded(opt_space, tag(",")))(input)?;
     Ok((
         input,
         SectionCommand::OutputSection {
@@ -273,16 +482,17 @@ fn output_sc(input: &str) -> IResult<&str, SectionCommand> {
             section_align: section_align.map(Box::new),
             align_with_input: align_with_input.is_some(),
             subsection_align: subsection_align.map(Box::new),
-            constraint: constraint,
-            content: content,
-            region: region.map(String::from),
-            lma_region: lma_region.map(String::from),
+            constraint,
+            content,
+            region: region.map(Cow::Borrowed),
+            lma_region: lma_region.map(Cow::Borrowed),
             fillexp: fillexp.map(Box::new),
+            comments: Vec::new(),
         },
     ))
 }
 
-pub fn section_command(input: &str) -> IResult<&str, SectionCommand> {
+pub fn section_command(input: &str) -> IResult<&str, SectionCommand<'_>> {
     alt((statement_sc, output_sc, command_sc))(input)
 }
 
@@ -1,14 +1,19 @@
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 use expressions::expression;
-use expressions::Expression;
+use expressions::{BinaryOperator, Expression};
 use idents::{string, symbol};
 use nom::branch::alt;
 use nom::bytes::complete::tag;
 use nom::combinator::map;
 use nom::combinator::opt;
+use nom::sequence::preceded;
 use nom::IResult;
-use whitespace::opt_space;
+use whitespace::{opt_space, Comment, Commented};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum AssignOperator {
     Equals,
     Plus,
@@ -21,31 +26,154 @@ pub enum AssignOperator {
     Or,
 }
 
-#[derive(Debug, PartialEq)]
-pub enum Statement {
+impl AssignOperator {
+    /// Operator that a compound assignment like `+=` applies, `None` for `=`
+    pub fn binary_operator(&self) -> Option<BinaryOperator> {
+        match *self {
+            AssignOperator::Equals => None,
+            AssignOperator::Plus => Some(BinaryOperator::Plus),
+            AssignOperator::Minus => Some(BinaryOperator::Minus),
+            AssignOperator::Multiply => Some(BinaryOperator::Multiply),
+            AssignOperator::Divide => Some(BinaryOperator::Divide),
+            AssignOperator::ShiftLeft => Some(BinaryOperator::ShiftLeft),
+            AssignOperator::ShiftRight => Some(BinaryOperator::ShiftRight),
+            AssignOperator::And => Some(BinaryOperator::BitwiseAnd),
+            AssignOperator::Or => Some(BinaryOperator::BitwiseOr),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
+pub enum Statement<'a> {
     Assign {
-        name: String,
+        name: Cow<'a, str>,
         operator: AssignOperator,
-        expression: Box<Expression>,
+        expression: Box<Expression<'a>>,
+        comments: Vec<Comment<'a>>,
     },
     Hidden {
-        name: String,
-        expression: Box<Expression>,
+        name: Cow<'a, str>,
+        expression: Box<Expression<'a>>,
+        comments: Vec<Comment<'a>>,
     },
     Provide {
-        name: String,
-        expression: Box<Expression>,
+        name: Cow<'a, str>,
+        expression: Box<Expression<'a>>,
+        comments: Vec<Comment<'a>>,
     },
     ProvideHidden {
-        name: String,
-        expression: Box<Expression>,
+        name: Cow<'a, str>,
+        expression: Box<Expression<'a>>,
+        comments: Vec<Comment<'a>>,
     },
     Assert {
-        expr: Box<Expression>,
-        text: String,
+        expr: Box<Expression<'a>>,
+        text: Cow<'a, str>,
+        comments: Vec<Comment<'a>>,
     },
 }
 
+impl<'a> Statement<'a> {
+    /// Comments attached to this statement
+    pub fn comments(&self) -> &[Comment<'a>] {
+        match *self {
+            Statement::Assign { ref comments, .. }
+            | Statement::Hidden { ref comments, .. }
+            | Statement::Provide { ref comments, .. }
+            | Statement::ProvideHidden { ref comments, .. }
+            | Statement::Assert { ref comments, .. } => comments,
+        }
+    }
+
+    /// Name of the symbol the statement assigns to, `None` for ASSERT
+    pub fn name(&self) -> Option<&str> {
+        match *self {
+            Statement::Assign { ref name, .. }
+            | Statement::Hidden { ref name, .. }
+            | Statement::Provide { ref name, .. }
+            | Statement::ProvideHidden { ref name, .. } => Some(name),
+            Statement::Assert { .. } => None,
+        }
+    }
+
+    /// Converts the statement into one that owns all of its strings
+    pub fn into_owned(self) -> Statement<'static> {
+        match self {
+            Statement::Assign {
+                name,
+                operator,
+                expression,
+                comments,
+            } => Statement::Assign {
+                name: Cow::Owned(name.into_owned()),
+                operator,
+                expression: Box::new(expression.into_owned()),
+                comments: comments.into_iter().map(Comment::into_owned).collect(),
+            },
+            Statement::Hidden {
+                name,
+                expression,
+                comments,
+            } => Statement::Hidden {
+                name: Cow::Owned(name.into_owned()),
+                expression: Box::new(expression.into_owned()),
+                comments: comments.into_iter().map(Comment::into_owned).collect(),
+            },
+            Statement::Provide {
+                name,
+                expression,
+                comments,
+            } => Statement::Provide {
+                name: Cow::Owned(name.into_owned()),
+                expression: Box::new(expression.into_owned()),
+                comments: comments.into_iter().map(Comment::into_owned).collect(),
+            },
+            Statement::ProvideHidden {
+                name,
+                expression,
+                comments,
+            } => Statement::ProvideHidden {
+                name: Cow::Owned(name.into_owned()),
+                expression: Box::new(expression.into_owned()),
+                comments: comments.into_iter().map(Comment::into_owned).collect(),
+            },
+            Statement::Assert {
+                expr,
+                text,
+                comments,
+            } => Statement::Assert {
+                expr: Box::new(expr.into_owned()),
+                text: Cow::Owned(text.into_owned()),
+                comments: comments.into_iter().map(Comment::into_owned).collect(),
+            },
+        }
+    }
+}
+
+impl<'a> Commented<'a> for Statement<'a> {
+    fn comments_mut(&mut self) -> Option<&mut Vec<Comment<'a>>> {
+        match *self {
+            Statement::Assign {
+                ref mut comments, ..
+            }
+            | Statement::Hidden {
+                ref mut comments, ..
+            }
+            | Statement::Provide {
+                ref mut comments, ..
+            }
+            | Statement::ProvideHidden {
+                ref mut comments, ..
+            }
+            | Statement::Assert {
+                ref mut comments, ..
+            } => Some(comments),
+        }
+    }
+}
+
 fn assign_operator(input: &str) -> IResult<&str, AssignOperator> {
     map(
         alt((
@@ -74,7 +202,7 @@ fn assign_operator(input: &str) -> IResult<&str, AssignOperator> {
     )(input)
 }
 
-fn special_assign(input: &str) -> IResult<&str, Statement> {
+fn special_assign(input: &str) -> IResult<&str, Statement<'_>> {
     let (input, keyword) = alt((tag("PROVIDE_HIDDEN"), tag("PROVIDE"), tag("HIDDEN")))(input)?;
     let (input, _) = wsc!(tag("("))(input)?;
     let (input, name) = symbol(input)?;
@@ -88,21 +216,24 @@ fn special_assign(input: &str) -> IResult<&str, Statement> {
             "HIDDEN" => Statement::Hidden {
                 name: name.into(),
                 expression: Box::new(expr),
+                comments: Vec::new(),
             },
             "PROVIDE" => Statement::Provide {
                 name: name.into(),
                 expression: Box::new(expr),
+                comments: Vec::new(),
             },
             "PROVIDE_HIDDEN" => Statement::ProvideHidden {
                 name: name.into(),
                 expression: Box::new(expr),
+                comments: Vec::new(),
             },
             _ => panic!("invalid assign keyword"),
         },
     ))
 }
 
-fn assign(input: &str) -> IResult<&str, Statement> {
+fn assign(input: &str) -> IResult<&str, Statement<'_>> {
     let (input, name) = symbol(input)?;
     let (input, op) = wsc!(assign_operator)(input)?;
     let (input, expr) = expression(input)?;
@@ -114,28 +245,30 @@ fn assign(input: &str) -> IResult<&str, Statement> {
             name: name.into(),
             operator: op,
             expression: Box::new(expr),
+            comments: Vec::new(),
         },
     ))
 }
 
-fn assert_stmt(input: &str) -> IResult<&str, Statement> {
+fn assert_stmt(input: &str) -> IResult<&str, Statement<'_>> {
     let (input, _) = tag("ASSERT")(input)?;
     let (input, _) = wsc!(tag("("))(input)?;
     let (input, expr) = expression(input)?;
     let (input, _) = wsc!(tag(","))(input)?;
     let (input, text) = string(input)?;
-    let (input, _) = wsc!(tag(")"))(input)?;
-    let (input, _) = opt(tag(";"))(input)?;
+    let (input, _) = preceded(opt_space, tag(")"))(input)?;
+    let (input, _) = opt(preceded(opt_space, tag(";")))(input)?;
     Ok((
         input,
         Statement::Assert {
             expr: Box::new(expr),
             text: text.into(),
+            comments: Vec::new(),
         },
     ))
 }
 
-pub fn statement(input: &str) -> IResult<&str, Statement> {
+pub fn statement(input: &str) -> IResult<&str, Statement<'_>> {
     alt((special_assign, assign, assert_stmt))(input)
 }
 
@@ -152,6 +285,7 @@ mod tests {
                 name: "A".into(),
                 operator: AssignOperator::Equals,
                 expression: Box::new(Expression::Number(11)),
+                comments: vec![],
             }
         );
         assert_done!(
@@ -159,6 +293,7 @@ mod tests {
             Statement::Provide {
                 name: "x".into(),
                 expression: Box::new(Expression::Ident("x".into())),
+                comments: vec![],
             }
         );
         assert_done!(statement("PROBLEM += HELLO ( WORLD , 0 ) + 1 ;"));
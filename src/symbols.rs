@@ -0,0 +1,477 @@
+//! Symbol table of a script.
+//!
+//! A [`SymbolTable`] lists every symbol that statements assign to, at the
+//! top level and inside output sections, with the place and the expression
+//! of each definition, and every place that refers to the symbol. The
+//! location counter `.` is not included.
+//!
+//! ```
+//! extern crate ldscript_parser as lds;
+//!
+//! use lds::symbols::{SymbolTable, Visibility};
+//!
+//! fn main() {
+//!     let items = lds::parse("
+//!         SECTIONS {
+//!             .heap : { _end = .; PROVIDE(end = .); _end = . + 0x100; }
+//!         }
+//!         PROVIDE_HIDDEN(__stack = ORIGIN(RAM) + LENGTH(RAM));
+//!         ENTRY(_start)
+//!     ").unwrap();
+//!     let table = SymbolTable::new(&items);
+//!     assert!(table.get("_end").unwrap().is_redefined());
+//!     assert!(!table.get("end").unwrap().takes_effect());
+//!     let stack = table.get("__stack").unwrap();
+//!     assert_eq!(stack.definitions[0].visibility, Visibility::Hidden);
+//!     assert!(table.get("_start").unwrap().definitions.is_empty());
+//! }
+//! ```
+
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+use commands::Command;
+use eval::takes_name;
+use expressions::Expression;
+use format::block_body;
+use incremental::parse_list;
+use intern::{Interner, Symbol};
+use script::{root_item, RootItem};
+use sections::{output_section_command, section_command, OutputSectionCommand, SectionCommand};
+use statements::{AssignOperator, Statement};
+use whitespace::leading_comments;
+
+/// Position of a statement or expression in a list of root items, as the
+/// index path of the nodes that lead to it.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Site {
+    /// Index of the root item
+    pub item: usize,
+    /// Index of the command in the SECTIONS block
+    pub command: Option<usize>,
+    /// Index of the command in the output section
+    pub content: Option<usize>,
+}
+
+impl Site {
+    pub fn new(item: usize, command: Option<usize>, content: Option<usize>) -> Self {
+        Site {
+            item,
+            command,
+            content,
+        }
+    }
+
+    /// Byte offset of the node at this site in the text the items were
+    /// parsed from, after its leading comments
+    pub fn offset(&self, text: &str) -> Option<usize> {
+        let (_, (_, ranges)) = parse_list(text, 0, root_item, true).ok()?;
+        let mut start = ranges.get(self.item)?.start;
+        if let Some(command) = self.command {
+            let body = block_body(text, start)?;
+            let (_, (_, ranges)) = parse_list(&text[body..], body, section_command, true).ok()?;
+            start = ranges.get(command)?.start;
+        }
+        if let Some(content) = self.content {
+            let body = block_body(text, start)?;
+            let (_, (_, ranges)) =
+                parse_list(&text[body..], body, output_section_command, true).ok()?;
+            start = ranges.get(content)?.start;
+        }
+        let (rest, _) = leading_comments(&text[start..]).ok()?;
+        Some(text.len() - rest.len())
+    }
+
+    /// Line of the node at this site in the text the items were parsed
+    /// from, starting at 1
+    pub fn line(&self, text: &str) -> Option<usize> {
+        let offset = self.offset(text)?;
+        Some(text[..offset].matches('\n').count() + 1)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Visibility {
+    /// Exported from the output file
+    Default,
+    /// Defined with `HIDDEN` or `PROVIDE_HIDDEN`, local to the output file
+    Hidden,
+}
+
+/// A statement that assigns to a symbol.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Definition<'a> {
+    pub site: Site,
+    /// `Equals` unless the statement is a compound assignment like `+=`
+    pub operator: AssignOperator,
+    pub expression: Expression<'a>,
+    pub visibility: Visibility,
+    /// Defined with `PROVIDE` or `PROVIDE_HIDDEN`, which only takes effect
+    /// if the symbol is referenced and not defined anywhere else
+    pub provided: bool,
+}
+
+/// Definitions of and references to one symbol.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct SymbolInfo<'a> {
+    pub name: Cow<'a, str>,
+    /// Definitions in the order they appear in the script
+    pub definitions: Vec<Definition<'a>>,
+    /// Places of the expressions that use the value of the symbol, in the
+    /// order they appear in the script. `ENTRY` and `EXTERN` count as
+    /// references, `DEFINED(sym)` does not.
+    pub references: Vec<Site>,
+}
+
+impl<'a> SymbolInfo<'a> {
+    /// Whether the symbol has a definition that is not a `PROVIDE`
+    pub fn is_defined(&self) -> bool {
+        self.definitions.iter().any(|def| !def.provided)
+    }
+
+    pub fn is_referenced(&self) -> bool {
+        !self.references.is_empty()
+    }
+
+    /// Whether the symbol is assigned more than once outside of `PROVIDE`
+    pub fn is_redefined(&self) -> bool {
+        self.definitions.iter().filter(|def| !def.provided).count() > 1
+    }
+
+    /// Whether the `PROVIDE` definitions of the symbol are used. The script
+    /// alone cannot tell whether an input file defines the symbol, so this
+    /// only checks the script.
+    pub fn takes_effect(&self) -> bool {
+        self.definitions.iter().any(|def| def.provided)
+            && !self.is_defined()
+            && self.is_referenced()
+    }
+
+    /// The definitions that give the symbol its value: the assignments, or
+    /// the `PROVIDE` definitions if they take effect
+    pub fn effective_definitions(&self) -> impl Iterator<Item = &Definition<'a>> {
+        let provided = self.takes_effect();
+        self.definitions
+            .iter()
+            .filter(move |def| def.provided == provided)
+    }
+}
+
+/// Symbols defined or referenced by a list of root items.
+#[derive(Debug, Default, Clone)]
+pub struct SymbolTable<'a> {
+    /// Names of the symbols, a symbol's handle is its index in `symbols`
+    names: Interner,
+    symbols: Vec<SymbolInfo<'a>>,
+}
+
+impl<'a> SymbolTable<'a> {
+    pub fn new(items: &[RootItem<'a>]) -> Self {
+        let mut table = SymbolTable::default();
+        for (item, root) in items.iter().enumerate() {
+            let site = Site::new(item, None, None);
+            match *root {
+                RootItem::Statement(ref stmt) => table.statement(stmt, site),
+                RootItem::Command(ref cmd) => table.command(cmd, site),
+                RootItem::Memory { .. } => {}
+                RootItem::Sections { ref list, .. } => {
+                    for (command, cmd) in list.iter().enumerate() {
+                        table.section_command(cmd, Site::new(item, Some(command), None));
+                    }
+                }
+            }
+        }
+        table
+    }
+
+    /// Returns the symbol named `name`
+    pub fn get(&self, name: &str) -> Option<&SymbolInfo<'a>> {
+        self.names
+            .get(name)
+            .map(|symbol| &self.symbols[symbol.index()])
+    }
+
+    /// Returns the handle of the symbol named `name`
+    pub fn symbol(&self, name: &str) -> Option<Symbol> {
+        self.names.get(name)
+    }
+
+    /// Returns the symbol with the handle `symbol`
+    ///
+    /// Panics if the handle does not come from this table.
+    pub fn info(&self, symbol: Symbol) -> &SymbolInfo<'a> {
+        &self.symbols[symbol.index()]
+    }
+
+    /// Names of the symbols
+    pub fn names(&self) -> &Interner {
+        &self.names
+    }
+
+    /// Number of distinct symbols
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Iterates over the symbols in the order they first appear
+    pub fn iter(&self) -> impl Iterator<Item = &SymbolInfo<'a>> {
+        self.symbols.iter()
+    }
+
+    /// The symbol named `name`, `None` for the location counter
+    fn entry(&mut self, name: &Cow<'a, str>) -> Option<&mut SymbolInfo<'a>> {
+        if name == "." {
+            return None;
+        }
+        let index = self.names.intern(name).index();
+        if index == self.symbols.len() {
+            self.symbols.push(SymbolInfo {
+                name: name.clone(),
+                definitions: Vec::new(),
+                references: Vec::new(),
+            });
+        }
+        Some(&mut self.symbols[index])
+    }
+
+    fn expression(&mut self, expr: &Expression<'a>, site: Site) {
+        match *expr {
+            Expression::Ident(ref name) => {
+                if let Some(symbol) = self.entry(name) {
+                    symbol.references.push(site);
+                }
+            }
+            Expression::Number(_) => {}
+            Expression::Call {
+                ref function,
+                ref arguments,
+            } => {
+                let skip = if takes_name(function) { 1 } else { 0 };
+                for argument in arguments.iter().skip(skip) {
+                    self.expression(argument, site);
+                }
+            }
+            Expression::UnaryOp { ref right, .. } => self.expression(right, site),
+            Expression::BinaryOp {
+                ref left,
+                ref right,
+                ..
+            } => {
+                self.expression(left, site);
+                self.expression(right, site);
+            }
+            Expression::TernaryOp {
+                ref condition,
+                ref left,
+                ref right,
+            } => {
+                self.expression(condition, site);
+                self.expression(left, site);
+                self.expression(right, site);
+            }
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement<'a>, site: Site) {
+        let (name, operator, expression, visibility, provided) = match *stmt {
+            Statement::Assign {
+                ref name,
+                operator,
+                ref expression,
+                ..
+            } => (name, operator, expression, Visibility::Default, false),
+            Statement::Hidden {
+                ref name,
+                ref expression,
+                ..
+            } => (
+                name,
+                AssignOperator::Equals,
+                expression,
+                Visibility::Hidden,
+                false,
+            ),
+            Statement::Provide {
+                ref name,
+                ref expression,
+                ..
+            } => (
+                name,
+                AssignOperator::Equals,
+                expression,
+                Visibility::Default,
+                true,
+            ),
+            Statement::ProvideHidden {
+                ref name,
+                ref expression,
+                ..
+            } => (
+                name,
+                AssignOperator::Equals,
+                expression,
+                Visibility::Hidden,
+                true,
+            ),
+            Statement::Assert { ref expr, .. } => return self.expression(expr, site),
+        };
+        self.expression(expression, site);
+        if let Some(symbol) = self.entry(name) {
+            // `a += 1` reads `a` before assigning to it
+            if operator != AssignOperator::Equals {
+                symbol.references.push(site);
+            }
+            symbol.definitions.push(Definition {
+                site,
+                operator,
+                expression: (**expression).clone(),
+                visibility,
+                provided,
+            });
+        }
+    }
+
+    fn command(&mut self, cmd: &Command<'a>, site: Site) {
+        if let Command::Call {
+            ref name,
+            ref arguments,
+            ..
+        } = *cmd
+        {
+            if name == "ENTRY" || name == "EXTERN" {
+                for argument in arguments {
+                    self.expression(argument, site);
+                }
+            }
+        }
+    }
+
+    fn section_command(&mut self, cmd: &SectionCommand<'a>, site: Site) {
+        match *cmd {
+            SectionCommand::Statement(ref stmt) => self.statement(stmt, site),
+            SectionCommand::Command(ref cmd) => self.command(cmd, site),
+            SectionCommand::OutputSection {
+                ref vma_address,
+                ref lma_address,
+                ref section_align,
+                ref subsection_align,
+                ref content,
+                ref fillexp,
+                ..
+            } => {
+                for expr in vma_address
+                    .iter()
+                    .chain(lma_address)
+                    .chain(section_align)
+                    .chain(subsection_align)
+                {
+                    self.expression(expr, site);
+                }
+                for (index, cmd) in content.iter().enumerate() {
+                    let site = Site::new(site.item, site.command, Some(index));
+                    match *cmd {
+                        OutputSectionCommand::Statement(ref stmt) => self.statement(stmt, site),
+                        OutputSectionCommand::Fill { ref expr, .. }
+                        | OutputSectionCommand::Data {
+                            value: ref expr, ..
+                        } => self.expression(expr, site),
+                        OutputSectionCommand::InputSection { .. }
+                        | OutputSectionCommand::KeepInputSection { .. } => {}
+                    }
+                }
+                if let Some(ref fill) = *fillexp {
+                    self.expression(fill, site);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use symbols::*;
+
+    #[test]
+    fn test_symbol_table() {
+        let items = ::parse(
+            "
+            _stack_size = 0x100;
+            SECTIONS {
+                .text : { *(.text) _etext = .; }
+                .heap : {
+                    _end = .;
+                    PROVIDE(end = .);
+                    . += _stack_size;
+                    _end = .;
+                    HIDDEN(__heap_end = _end);
+                }
+                _size = SIZEOF(.heap) + ADDR(.text) + DEFINED(__unused);
+            }
+            PROVIDE(__unused = 0);
+            PROVIDE_HIDDEN(__stack = ORIGIN(RAM) + LENGTH(RAM));
+            __stack_top = __stack;
+            _stack_size += 4;
+            ENTRY(_start)
+            ",
+        )
+        .unwrap();
+        let table = SymbolTable::new(&items);
+        let names: Vec<&str> = table.iter().map(|sym| &*sym.name).collect();
+        assert_eq!(
+            names,
+            [
+                "_stack_size",
+                "_etext",
+                "_end",
+                "end",
+                "__heap_end",
+                "_size",
+                "__unused",
+                "__stack",
+                "__stack_top",
+                "_start"
+            ]
+        );
+        assert!(table.get(".").is_none());
+        assert!(table.get(".heap").is_none());
+        assert!(table.get("RAM").is_none());
+
+        let symbol = table.symbol("_end").unwrap();
+        assert_eq!(table.names().resolve(symbol), "_end");
+        let end = table.info(symbol);
+        assert!(end.is_defined() && end.is_redefined());
+        assert_eq!(end.definitions[0].site, Site::new(1, Some(1), Some(0)));
+        assert_eq!(end.definitions[1].site, Site::new(1, Some(1), Some(3)));
+        assert_eq!(end.references, [Site::new(1, Some(1), Some(4))]);
+
+        let provided = table.get("end").unwrap();
+        assert!(provided.definitions[0].provided && !provided.takes_effect());
+        assert!(!table.get("__unused").unwrap().takes_effect());
+
+        let stack = table.get("__stack").unwrap();
+        assert!(stack.takes_effect() && !stack.is_defined());
+        assert_eq!(stack.definitions[0].visibility, Visibility::Hidden);
+        assert_eq!(stack.effective_definitions().count(), 1);
+        assert_eq!(stack.references, [Site::new(4, None, None)]);
+        assert_eq!(
+            table.get("__heap_end").unwrap().definitions[0].visibility,
+            Visibility::Hidden
+        );
+
+        let size = table.get("_stack_size").unwrap();
+        assert_eq!(size.definitions.len(), 2);
+        assert_eq!(size.definitions[1].operator, AssignOperator::Plus);
+        assert!(size.is_redefined());
+        assert_eq!(
+            size.references,
+            [Site::new(1, Some(1), Some(2)), Site::new(5, None, None)]
+        );
+
+        let start = table.get("_start").unwrap();
+        assert!(start.definitions.is_empty());
+        assert_eq!(start.references, [Site::new(6, None, None)]);
+    }
+}
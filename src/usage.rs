@@ -0,0 +1,320 @@
+//! Memory usage of the MEMORY regions.
+//!
+//! [`MemoryUsage`] is what `ld --print-memory-usage` reports, computed from
+//! a [`Layout`] before anything is linked. A region is used up to the end of
+//! the last output section placed into it with `> REGION`, or loaded into
+//! it with `AT> REGION`. A load image placed with `AT(address)`, or with
+//! the LMA offset of the section before, counts against the region it lands
+//! in. Its `Display` output is the table ld prints, the alternate form
+//! `{:#}` also lists the sections in each region.
+//!
+//! ```
+//! extern crate ldscript_parser as lds;
+//!
+//! use lds::layout::Layout;
+//! use lds::manifest::Manifest;
+//! use lds::usage::MemoryUsage;
+//!
+//! fn main() {
+//!     let items = lds::parse("
+//!         MEMORY {
+//!             RAM : ORIGIN = 0x1C00, LENGTH = 2K
+//!             ROM : ORIGIN = 0x4400, LENGTH = 0xBB80
+//!         }
+//!         SECTIONS {
+//!             .text : { *(.text) } > ROM
+//!             .data : { *(.data) } > RAM AT> ROM
+//!         }
+//!     ").unwrap();
+//!     let manifest = Manifest::parse("main.o .text 0x100\nmain.o .data 0x80").unwrap();
+//!     let usage = MemoryUsage::new(&Layout::compute(&items, &manifest).unwrap());
+//!     let ram = usage.region("RAM").unwrap();
+//!     assert_eq!((ram.used, ram.length), (0x80, 0x800));
+//!     assert!(ram.percent() < 10.0);
+//!     assert_eq!(usage.region("ROM").unwrap().used, 0x180);
+//!     assert_eq!(
+//!         usage.to_string(),
+//!         "Memory region         Used Size  Region Size  %age Used\n\
+//!         \x20            RAM:         128 B         2 KB      6.25%\n\
+//!         \x20            ROM:         384 B      48000 B      0.80%\n"
+//!     );
+//! }
+//! ```
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use layout::Layout;
+
+/// Part of a region taken up by an output section.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Contribution {
+    pub section: String,
+    /// Start of the part, the VMA or the LMA of the section
+    pub address: u64,
+    pub size: u64,
+    /// The region holds the load image of the section rather than the
+    /// section itself
+    pub load: bool,
+}
+
+/// Usage of one region.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct RegionUsage {
+    pub name: String,
+    pub origin: u64,
+    pub length: u64,
+    /// Bytes from the origin to the end of the last section, gaps between
+    /// sections included
+    pub used: u64,
+    /// Sections in the region, in script order
+    pub sections: Vec<Contribution>,
+}
+
+impl RegionUsage {
+    /// Used part of the region in percent, above 100 if it overflows
+    pub fn percent(&self) -> f64 {
+        if self.length == 0 {
+            return if self.used == 0 { 0.0 } else { 100.0 };
+        }
+        self.used as f64 * 100.0 / self.length as f64
+    }
+
+    /// Bytes the sections extend past the end of the region
+    pub fn overflow(&self) -> u64 {
+        self.used.saturating_sub(self.length)
+    }
+}
+
+/// Usage of all regions of a layout.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct MemoryUsage {
+    /// Regions in the order of the MEMORY blocks
+    pub regions: Vec<RegionUsage>,
+}
+
+impl MemoryUsage {
+    pub fn new(layout: &Layout) -> Self {
+        let mut regions: Vec<RegionUsage> = layout
+            .regions
+            .iter()
+            .map(|region| RegionUsage {
+                name: region.name.to_string(),
+                origin: region.origin,
+                length: region.length,
+                used: 0,
+                sections: Vec::new(),
+            })
+            .collect();
+        let position = |name: &String| {
+            layout
+                .regions
+                .iter()
+                .position(|region| region.name == *name)
+        };
+        let containing = |address: u64| {
+            layout.regions.iter().position(|region| {
+                address >= region.origin && address - region.origin < region.length
+            })
+        };
+        for section in layout.sections.iter().filter(|section| section.allocated) {
+            // a load image without `AT>` is charged to the region it lands in
+            let load = match section.lma_region {
+                _ if section.noload => None,
+                Some(ref name) => position(name),
+                None if section.lma != section.vma => containing(section.lma),
+                None => None,
+            };
+            let parts = section
+                .region
+                .as_ref()
+                .and_then(position)
+                .map(|index| (index, section.vma, false))
+                .into_iter()
+                .chain(load.map(|index| (index, section.lma, true)));
+            for (index, address, load) in parts {
+                let region = &mut regions[index];
+                let end = address.wrapping_add(section.size);
+                region.used = region.used.max(end.saturating_sub(region.origin));
+                region.sections.push(Contribution {
+                    section: section.name.clone(),
+                    address,
+                    size: section.size,
+                    load,
+                });
+            }
+        }
+        MemoryUsage { regions }
+    }
+
+    /// Returns the usage of the region named `name`
+    pub fn region(&self, name: &str) -> Option<&RegionUsage> {
+        self.regions.iter().find(|region| region.name == name)
+    }
+}
+
+/// A size the way ld prints it, in the largest unit that divides it
+fn size(value: u64) -> String {
+    if value != 0 && value & ((1 << 30) - 1) == 0 {
+        format!("{:>10} GB", value >> 30)
+    } else if value != 0 && value & ((1 << 20) - 1) == 0 {
+        format!("{:>10} MB", value >> 20)
+    } else if value != 0 && value & ((1 << 10) - 1) == 0 {
+        format!("{:>10} KB", value >> 10)
+    } else {
+        format!("{:>11} B", value)
+    }
+}
+
+impl fmt::Display for MemoryUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Memory region         Used Size  Region Size  %age Used")?;
+        for region in &self.regions {
+            writeln!(
+                f,
+                "{:>16}: {}{}    {:>6.2}%",
+                region.name,
+                size(region.used),
+                size(region.length),
+                region.percent()
+            )?;
+            if f.alternate() {
+                for part in &region.sections {
+                    let kind = if part.load { " (load)" } else { "" };
+                    writeln!(
+                        f,
+                        "{:>18}{:<24} {:#010x} {}{}",
+                        "",
+                        part.section,
+                        part.address,
+                        size(part.size).trim_start(),
+                        kind
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use layout::Layout;
+    use manifest::Manifest;
+    use usage::*;
+
+    #[test]
+    fn test_usage() {
+        let items = ::parse(
+            "
+            MEMORY {
+                RAM : ORIGIN = 0x1C00, LENGTH = 0x100
+                ROM : ORIGIN = 0x4400, LENGTH = 1K
+                INFO : ORIGIN = 0x1800, LENGTH = 0x80
+            }
+            SECTIONS {
+                .text : { *(.text) } > ROM
+                .data : ALIGN(0x10) { *(.data) } > RAM AT> ROM
+                .bss (NOLOAD) : { *(.bss) } > RAM AT> ROM
+                .comment 0 (INFO) : { *(.comment) }
+            }
+            ",
+        )
+        .unwrap();
+        let manifest = Manifest::parse(
+            "
+            a.o .text 0x102
+            a.o .data 0x10
+            a.o .bss 0x100
+            a.o .comment 0x40
+            ",
+        )
+        .unwrap();
+        let usage = MemoryUsage::new(&Layout::compute(&items, &manifest).unwrap());
+
+        let ram = usage.region("RAM").unwrap();
+        assert_eq!(ram.used, 0x110);
+        assert_eq!(ram.overflow(), 0x10);
+        assert_eq!(ram.percent(), 106.25);
+        let sections: Vec<(&str, bool)> = ram
+            .sections
+            .iter()
+            .map(|part| (&*part.section, part.load))
+            .collect();
+        assert_eq!(sections, [(".data", false), (".bss", false)]);
+
+        let rom = usage.region("ROM").unwrap();
+        assert_eq!(rom.used, 0x120);
+        assert_eq!(
+            rom.sections[1],
+            Contribution {
+                section: ".data".to_string(),
+                address: 0x4510,
+                size: 0x10,
+                load: true,
+            }
+        );
+        assert_eq!(usage.region("INFO").unwrap().used, 0);
+
+        assert_eq!(
+            format!("{:#}", usage),
+            "\
+Memory region         Used Size  Region Size  %age Used
+             RAM:         272 B        256 B    106.25%
+                  .data                    0x00001c00 16 B
+                  .bss                     0x00001c10 256 B
+             ROM:         288 B         1 KB     28.12%
+                  .text                    0x00004400 258 B
+                  .data                    0x00004510 16 B (load)
+            INFO:           0 B        128 B      0.00%
+"
+        );
+    }
+
+    #[test]
+    fn test_load_address() {
+        let items = ::parse(
+            "
+            MEMORY {
+                RAM : ORIGIN = 0x1000, LENGTH = 0x1000
+                ROM : ORIGIN = 0x4000, LENGTH = 0x1000
+            }
+            SECTIONS {
+                .data : { *(.data) } > RAM AT> ROM
+                .data2 : { *(.data2) } > RAM
+                .rodata : { *(.rodata) } > ROM
+                .x : AT(0x4080) { *(.x) } > RAM
+            }
+            ",
+        )
+        .unwrap();
+        let manifest = Manifest::parse(
+            "
+            a.o .data 0x10
+            a.o .data2 0x20
+            a.o .rodata 0x8
+            a.o .x 0x4
+            ",
+        )
+        .unwrap();
+        let usage = MemoryUsage::new(&Layout::compute(&items, &manifest).unwrap());
+        let rom = usage.region("ROM").unwrap();
+        assert_eq!(rom.used, 0x84);
+        let sections: Vec<(&str, u64, bool)> = rom
+            .sections
+            .iter()
+            .map(|part| (&*part.section, part.address, part.load))
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                (".data", 0x4000, true),
+                (".data2", 0x4010, true),
+                (".rodata", 0x4030, false),
+                (".x", 0x4080, true),
+            ]
+        );
+        assert_eq!(usage.region("RAM").unwrap().used, 0x34);
+    }
+}
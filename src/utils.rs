@@ -43,3 +43,16 @@ macro_rules! assert_fail {
         }
     }};
 }
+
+/// Every 16th script of the corpus in `tests/`, sorted by name. The parser
+/// tests read the whole corpus; the slower round trips through the printer
+/// and the formatter only check this sample.
+#[cfg(test)]
+pub fn corpus_sample() -> alloc::vec::Vec<std::path::PathBuf> {
+    let mut paths: alloc::vec::Vec<_> = std::fs::read_dir("tests")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    paths.into_iter().step_by(16).collect()
+}
@@ -0,0 +1,386 @@
+//! Syntax tree traversal over shared borrows of the AST nodes.
+//!
+//! Each method of the [`Visit`] trait is a hook that can be overridden to
+//! customize the behavior when visiting the corresponding type of node. By
+//! default, every method recursively visits the children of the node with the
+//! free function of the same name.
+//!
+//! ```
+//! extern crate ldscript_parser as lds;
+//!
+//! use lds::visit::{self, Visit};
+//! use lds::Expression;
+//!
+//! struct Calls(Vec<String>);
+//!
+//! impl<'ast> Visit<'ast> for Calls {
+//!     fn visit_expression(&mut self, node: &'ast Expression<'ast>) {
+//!         if let Expression::Call { ref function, .. } = *node {
+//!             self.0.push(function.to_string());
+//!         }
+//!         visit::visit_expression(self, node);
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let items = lds::parse("_stack = ORIGIN(RAM) + LENGTH(RAM);").unwrap();
+//!     let mut calls = Calls(Vec::new());
+//!     for item in &items {
+//!         calls.visit_root_item(item);
+//!     }
+//!     assert_eq!(calls.0, vec!["ORIGIN", "LENGTH"]);
+//! }
+//! ```
+
+use alloc::vec::Vec;
+use commands::{Command, InsertOrder};
+use expressions::{BinaryOperator, Expression, UnaryOperator};
+use memory::Region;
+use script::RootItem;
+use sections::{
+    DataType, OutputSectionCommand, OutputSectionConstraint, OutputSectionType, SectionCommand,
+    SectionPattern,
+};
+use statements::{AssignOperator, Statement};
+use whitespace::{Comment, CommentPosition};
+
+/// Traversal to walk a shared borrow of the syntax tree.
+pub trait Visit<'ast> {
+    fn visit_root_item(&mut self, node: &'ast RootItem<'ast>) {
+        visit_root_item(self, node)
+    }
+    fn visit_statement(&mut self, node: &'ast Statement<'ast>) {
+        visit_statement(self, node)
+    }
+    fn visit_command(&mut self, node: &'ast Command<'ast>) {
+        visit_command(self, node)
+    }
+    fn visit_region(&mut self, node: &'ast Region<'ast>) {
+        visit_region(self, node)
+    }
+    fn visit_section_command(&mut self, node: &'ast SectionCommand<'ast>) {
+        visit_section_command(self, node)
+    }
+    fn visit_output_section_command(&mut self, node: &'ast OutputSectionCommand<'ast>) {
+        visit_output_section_command(self, node)
+    }
+    fn visit_section_pattern(&mut self, node: &'ast SectionPattern<'ast>) {
+        visit_section_pattern(self, node)
+    }
+    fn visit_expression(&mut self, node: &'ast Expression<'ast>) {
+        visit_expression(self, node)
+    }
+    fn visit_comment(&mut self, node: &'ast Comment<'ast>) {
+        visit_comment(self, node)
+    }
+    fn visit_comment_position(&mut self, _node: &'ast CommentPosition) {}
+    fn visit_insert_order(&mut self, _node: &'ast InsertOrder) {}
+    fn visit_assign_operator(&mut self, _node: &'ast AssignOperator) {}
+    fn visit_unary_operator(&mut self, _node: &'ast UnaryOperator) {}
+    fn visit_binary_operator(&mut self, _node: &'ast BinaryOperator) {}
+    fn visit_data_type(&mut self, _node: &'ast DataType) {}
+    fn visit_output_section_type(&mut self, _node: &'ast OutputSectionType) {}
+    fn visit_output_section_constraint(&mut self, _node: &'ast OutputSectionConstraint) {}
+}
+
+pub fn visit_root_item<'ast, V>(v: &mut V, node: &'ast RootItem<'ast>)
+where
+    V: Visit<'ast> + ?Sized,
+{
+    match *node {
+        RootItem::Statement(ref stmt) => v.visit_statement(stmt),
+        RootItem::Command(ref cmd) => v.visit_command(cmd),
+        RootItem::Memory {
+            ref regions,
+            ref comments,
+        } => {
+            for region in regions {
+                v.visit_region(region);
+            }
+            for comment in comments {
+                v.visit_comment(comment);
+            }
+        }
+        RootItem::Sections {
+            ref list,
+            ref comments,
+        } => {
+            for command in list {
+                v.visit_section_command(command);
+            }
+            for comment in comments {
+                v.visit_comment(comment);
+            }
+        }
+    }
+}
+
+pub fn visit_statement<'ast, V>(v: &mut V, node: &'ast Statement<'ast>)
+where
+    V: Visit<'ast> + ?Sized,
+{
+    match *node {
+        Statement::Assign {
+            ref operator,
+            ref expression,
+            ..
+        } => {
+            v.visit_assign_operator(operator);
+            v.visit_expression(expression);
+        }
+        Statement::Hidden { ref expression, .. }
+        | Statement::Provide { ref expression, .. }
+        | Statement::ProvideHidden { ref expression, .. } => v.visit_expression(expression),
+        Statement::Assert { ref expr, .. } => v.visit_expression(expr),
+    }
+    for comment in node.comments() {
+        v.visit_comment(comment);
+    }
+}
+
+pub fn visit_command<'ast, V>(v: &mut V, node: &'ast Command<'ast>)
+where
+    V: Visit<'ast> + ?Sized,
+{
+    match *node {
+        Command::Call { ref arguments, .. } => {
+            for arg in arguments {
+                v.visit_expression(arg);
+            }
+        }
+        Command::Include { .. } => {}
+        Command::Insert { ref order, .. } => v.visit_insert_order(order),
+    }
+    for comment in node.comments() {
+        v.visit_comment(comment);
+    }
+}
+
+pub fn visit_region<'ast, V>(v: &mut V, node: &'ast Region<'ast>)
+where
+    V: Visit<'ast> + ?Sized,
+{
+    for comment in &node.comments {
+        v.visit_comment(comment);
+    }
+}
+
+pub fn visit_section_command<'ast, V>(v: &mut V, node: &'ast SectionCommand<'ast>)
+where
+    V: Visit<'ast> + ?Sized,
+{
+    match *node {
+        SectionCommand::Statement(ref stmt) => v.visit_statement(stmt),
+        SectionCommand::Command(ref cmd) => v.visit_command(cmd),
+        SectionCommand::OutputSection {
+            ref vma_address,
+            ref s_type,
+            ref lma_address,
+            ref section_align,
+            ref subsection_align,
+            ref constraint,
+            ref content,
+            ref fillexp,
+            ref comments,
+            ..
+        } => {
+            if let Some(ref expr) = *vma_address {
+                v.visit_expression(expr);
+            }
+            if let Some(ref s_type) = *s_type {
+                v.visit_output_section_type(s_type);
+            }
+            if let Some(ref expr) = *lma_address {
+                v.visit_expression(expr);
+            }
+            if let Some(ref expr) = *section_align {
+                v.visit_expression(expr);
+            }
+            if let Some(ref expr) = *subsection_align {
+                v.visit_expression(expr);
+            }
+            if let Some(ref constraint) = *constraint {
+                v.visit_output_section_constraint(constraint);
+            }
+            for command in content {
+                v.visit_output_section_command(command);
+            }
+            if let Some(ref expr) = *fillexp {
+                v.visit_expression(expr);
+            }
+            for comment in comments {
+                v.visit_comment(comment);
+            }
+        }
+    }
+}
+
+pub fn visit_output_section_command<'ast, V>(v: &mut V, node: &'ast OutputSectionCommand<'ast>)
+where
+    V: Visit<'ast> + ?Sized,
+{
+    match *node {
+        OutputSectionCommand::Statement(ref stmt) => {
+            v.visit_statement(stmt);
+            return;
+        }
+        OutputSectionCommand::Fill { ref expr, .. } => v.visit_expression(expr),
+        OutputSectionCommand::Data {
+            ref d_type,
+            ref value,
+            ..
+        } => {
+            v.visit_data_type(d_type);
+            v.visit_expression(value);
+        }
+        OutputSectionCommand::InputSection {
+            ref file,
+            ref sections,
+            ..
+        }
+        | OutputSectionCommand::KeepInputSection {
+            ref file,
+            ref sections,
+            ..
+        } => {
+            v.visit_section_pattern(file);
+            for pattern in sections {
+                v.visit_section_pattern(pattern);
+            }
+        }
+    }
+    for comment in node.comments() {
+        v.visit_comment(comment);
+    }
+}
+
+pub fn visit_section_pattern<'ast, V>(v: &mut V, node: &'ast SectionPattern<'ast>)
+where
+    V: Visit<'ast> + ?Sized,
+{
+    if let SectionPattern::ExcludeFile { ref pattern, .. } = *node {
+        v.visit_section_pattern(pattern);
+    }
+}
+
+pub fn visit_expression<'ast, V>(v: &mut V, node: &'ast Expression<'ast>)
+where
+    V: Visit<'ast> + ?Sized,
+{
+    match *node {
+        Expression::Ident(_) | Expression::Number(_) => {}
+        Expression::Call { ref arguments, .. } => {
+            for arg in arguments {
+                v.visit_expression(arg);
+            }
+        }
+        Expression::UnaryOp {
+            ref operator,
+            ref right,
+        } => {
+            v.visit_unary_operator(operator);
+            v.visit_expression(right);
+        }
+        Expression::BinaryOp {
+            ref left,
+            ref operator,
+            ref right,
+        } => {
+            v.visit_expression(left);
+            v.visit_binary_operator(operator);
+            v.visit_expression(right);
+        }
+        Expression::TernaryOp {
+            ref condition,
+            ref left,
+            ref right,
+        } => {
+            v.visit_expression(condition);
+            v.visit_expression(left);
+            v.visit_expression(right);
+        }
+    }
+}
+
+pub fn visit_comment<'ast, V>(v: &mut V, node: &'ast Comment<'ast>)
+where
+    V: Visit<'ast> + ?Sized,
+{
+    v.visit_comment_position(&node.position);
+}
+
+struct ExpressionCollector<'ast> {
+    found: Vec<&'ast Expression<'ast>>,
+}
+
+impl<'ast> Visit<'ast> for ExpressionCollector<'ast> {
+    fn visit_expression(&mut self, node: &'ast Expression<'ast>) {
+        self.found.push(node);
+        visit_expression(self, node);
+    }
+}
+
+/// Returns every expression of the script in source order, including the
+/// nested ones.
+pub fn expressions<'ast>(items: &'ast [RootItem<'ast>]) -> Vec<&'ast Expression<'ast>> {
+    let mut collector = ExpressionCollector { found: Vec::new() };
+    for item in items {
+        collector.visit_root_item(item);
+    }
+    collector.found
+}
+
+struct PatternCollector<'ast> {
+    found: Vec<&'ast SectionPattern<'ast>>,
+}
+
+impl<'ast> Visit<'ast> for PatternCollector<'ast> {
+    fn visit_section_pattern(&mut self, node: &'ast SectionPattern<'ast>) {
+        self.found.push(node);
+        visit_section_pattern(self, node);
+    }
+}
+
+/// Returns every section pattern of the script in source order, including
+/// the ones wrapped by `EXCLUDE_FILE`.
+pub fn section_patterns<'ast>(items: &'ast [RootItem<'ast>]) -> Vec<&'ast SectionPattern<'ast>> {
+    let mut collector = PatternCollector { found: Vec::new() };
+    for item in items {
+        collector.visit_root_item(item);
+    }
+    collector.found
+}
+
+#[cfg(test)]
+mod tests {
+    use script::parse;
+    use visit::*;
+
+    #[test]
+    fn test_collect() {
+        let input = "
+            SECTIONS {
+                .text ALIGN(4) : {
+                    KEEP(*(.init EXCLUDE_FILE(*crtend.o) .ctors))
+                    . = . + (4 * 2);
+                } > ROM
+            }
+            _end = ADDR(.text) ? 1 : 2;
+        ";
+        let (_, items) = parse(input).unwrap();
+
+        let exprs = expressions(&items);
+        assert_eq!(exprs.len(), 12);
+        assert_eq!(
+            *exprs[0],
+            Expression::Call {
+                function: "ALIGN".into(),
+                arguments: vec![Expression::Number(4)],
+            }
+        );
+
+        let patterns = section_patterns(&items);
+        assert_eq!(patterns.len(), 4);
+        assert_eq!(*patterns[3], SectionPattern::Simple(".ctors".into()));
+    }
+}
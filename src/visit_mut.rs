@@ -0,0 +1,353 @@
+//! Syntax tree traversal over mutable borrows of the AST nodes.
+//!
+//! Each method of the [`VisitMut`] trait is a hook that can be overridden to
+//! modify the corresponding type of node in place. By default, every method
+//! recursively visits the children of the node with the free function of the
+//! same name.
+//!
+//! ```
+//! extern crate ldscript_parser as lds;
+//!
+//! use lds::visit_mut::{self, VisitMut};
+//! use lds::Region;
+//!
+//! struct Rename;
+//!
+//! impl<'a> VisitMut<'a> for Rename {
+//!     fn visit_region_mut(&mut self, node: &mut Region<'a>) {
+//!         if node.name == "ROM" {
+//!             node.name = "FLASH".into();
+//!         }
+//!         visit_mut::visit_region_mut(self, node);
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let mut items = lds::parse("MEMORY { ROM : ORIGIN = 0, LENGTH = 1K }").unwrap();
+//!     for item in &mut items {
+//!         Rename.visit_root_item_mut(item);
+//!     }
+//! }
+//! ```
+
+use commands::{Command, InsertOrder};
+use expressions::{BinaryOperator, Expression, UnaryOperator};
+use memory::Region;
+use script::RootItem;
+use sections::{
+    DataType, OutputSectionCommand, OutputSectionConstraint, OutputSectionType, SectionCommand,
+    SectionPattern,
+};
+use statements::{AssignOperator, Statement};
+use whitespace::{Comment, CommentPosition, Commented};
+
+/// Traversal to walk a mutable borrow of the syntax tree.
+pub trait VisitMut<'a> {
+    fn visit_root_item_mut(&mut self, node: &mut RootItem<'a>) {
+        visit_root_item_mut(self, node)
+    }
+    fn visit_statement_mut(&mut self, node: &mut Statement<'a>) {
+        visit_statement_mut(self, node)
+    }
+    fn visit_command_mut(&mut self, node: &mut Command<'a>) {
+        visit_command_mut(self, node)
+    }
+    fn visit_region_mut(&mut self, node: &mut Region<'a>) {
+        visit_region_mut(self, node)
+    }
+    fn visit_section_command_mut(&mut self, node: &mut SectionCommand<'a>) {
+        visit_section_command_mut(self, node)
+    }
+    fn visit_output_section_command_mut(&mut self, node: &mut OutputSectionCommand<'a>) {
+        visit_output_section_command_mut(self, node)
+    }
+    fn visit_section_pattern_mut(&mut self, node: &mut SectionPattern<'a>) {
+        visit_section_pattern_mut(self, node)
+    }
+    fn visit_expression_mut(&mut self, node: &mut Expression<'a>) {
+        visit_expression_mut(self, node)
+    }
+    fn visit_comment_mut(&mut self, node: &mut Comment<'a>) {
+        visit_comment_mut(self, node)
+    }
+    fn visit_comment_position_mut(&mut self, _node: &mut CommentPosition) {}
+    fn visit_insert_order_mut(&mut self, _node: &mut InsertOrder) {}
+    fn visit_assign_operator_mut(&mut self, _node: &mut AssignOperator) {}
+    fn visit_unary_operator_mut(&mut self, _node: &mut UnaryOperator) {}
+    fn visit_binary_operator_mut(&mut self, _node: &mut BinaryOperator) {}
+    fn visit_data_type_mut(&mut self, _node: &mut DataType) {}
+    fn visit_output_section_type_mut(&mut self, _node: &mut OutputSectionType) {}
+    fn visit_output_section_constraint_mut(&mut self, _node: &mut OutputSectionConstraint) {}
+}
+
+pub fn visit_root_item_mut<'a, V>(v: &mut V, node: &mut RootItem<'a>)
+where
+    V: VisitMut<'a> + ?Sized,
+{
+    match *node {
+        RootItem::Statement(ref mut stmt) => v.visit_statement_mut(stmt),
+        RootItem::Command(ref mut cmd) => v.visit_command_mut(cmd),
+        RootItem::Memory {
+            ref mut regions,
+            ref mut comments,
+        } => {
+            for region in regions {
+                v.visit_region_mut(region);
+            }
+            for comment in comments {
+                v.visit_comment_mut(comment);
+            }
+        }
+        RootItem::Sections {
+            ref mut list,
+            ref mut comments,
+        } => {
+            for command in list {
+                v.visit_section_command_mut(command);
+            }
+            for comment in comments {
+                v.visit_comment_mut(comment);
+            }
+        }
+    }
+}
+
+pub fn visit_statement_mut<'a, V>(v: &mut V, node: &mut Statement<'a>)
+where
+    V: VisitMut<'a> + ?Sized,
+{
+    match *node {
+        Statement::Assign {
+            ref mut operator,
+            ref mut expression,
+            ..
+        } => {
+            v.visit_assign_operator_mut(operator);
+            v.visit_expression_mut(expression);
+        }
+        Statement::Hidden {
+            ref mut expression, ..
+        }
+        | Statement::Provide {
+            ref mut expression, ..
+        }
+        | Statement::ProvideHidden {
+            ref mut expression, ..
+        } => v.visit_expression_mut(expression),
+        Statement::Assert { ref mut expr, .. } => v.visit_expression_mut(expr),
+    }
+    if let Some(comments) = node.comments_mut() {
+        for comment in comments {
+            v.visit_comment_mut(comment);
+        }
+    }
+}
+
+pub fn visit_command_mut<'a, V>(v: &mut V, node: &mut Command<'a>)
+where
+    V: VisitMut<'a> + ?Sized,
+{
+    match *node {
+        Command::Call {
+            ref mut arguments, ..
+        } => {
+            for arg in arguments {
+                v.visit_expression_mut(arg);
+            }
+        }
+        Command::Include { .. } => {}
+        Command::Insert { ref mut order, .. } => v.visit_insert_order_mut(order),
+    }
+    if let Some(comments) = node.comments_mut() {
+        for comment in comments {
+            v.visit_comment_mut(comment);
+        }
+    }
+}
+
+pub fn visit_region_mut<'a, V>(v: &mut V, node: &mut Region<'a>)
+where
+    V: VisitMut<'a> + ?Sized,
+{
+    for comment in &mut node.comments {
+        v.visit_comment_mut(comment);
+    }
+}
+
+pub fn visit_section_command_mut<'a, V>(v: &mut V, node: &mut SectionCommand<'a>)
+where
+    V: VisitMut<'a> + ?Sized,
+{
+    match *node {
+        SectionCommand::Statement(ref mut stmt) => v.visit_statement_mut(stmt),
+        SectionCommand::Command(ref mut cmd) => v.visit_command_mut(cmd),
+        SectionCommand::OutputSection {
+            ref mut vma_address,
+            ref mut s_type,
+            ref mut lma_address,
+            ref mut section_align,
+            ref mut subsection_align,
+            ref mut constraint,
+            ref mut content,
+            ref mut fillexp,
+            ref mut comments,
+            ..
+        } => {
+            if let Some(ref mut expr) = *vma_address {
+                v.visit_expression_mut(expr);
+            }
+            if let Some(ref mut s_type) = *s_type {
+                v.visit_output_section_type_mut(s_type);
+            }
+            if let Some(ref mut expr) = *lma_address {
+                v.visit_expression_mut(expr);
+            }
+            if let Some(ref mut expr) = *section_align {
+                v.visit_expression_mut(expr);
+            }
+            if let Some(ref mut expr) = *subsection_align {
+                v.visit_expression_mut(expr);
+            }
+            if let Some(ref mut constraint) = *constraint {
+                v.visit_output_section_constraint_mut(constraint);
+            }
+            for command in content {
+                v.visit_output_section_command_mut(command);
+            }
+            if let Some(ref mut expr) = *fillexp {
+                v.visit_expression_mut(expr);
+            }
+            for comment in comments {
+                v.visit_comment_mut(comment);
+            }
+        }
+    }
+}
+
+pub fn visit_output_section_command_mut<'a, V>(v: &mut V, node: &mut OutputSectionCommand<'a>)
+where
+    V: VisitMut<'a> + ?Sized,
+{
+    match *node {
+        OutputSectionCommand::Statement(ref mut stmt) => {
+            v.visit_statement_mut(stmt);
+            return;
+        }
+        OutputSectionCommand::Fill { ref mut expr, .. } => v.visit_expression_mut(expr),
+        OutputSectionCommand::Data {
+            ref mut d_type,
+            ref mut value,
+            ..
+        } => {
+            v.visit_data_type_mut(d_type);
+            v.visit_expression_mut(value);
+        }
+        OutputSectionCommand::InputSection {
+            ref mut file,
+            ref mut sections,
+            ..
+        }
+        | OutputSectionCommand::KeepInputSection {
+            ref mut file,
+            ref mut sections,
+            ..
+        } => {
+            v.visit_section_pattern_mut(file);
+            for pattern in sections {
+                v.visit_section_pattern_mut(pattern);
+            }
+        }
+    }
+    if let Some(comments) = node.comments_mut() {
+        for comment in comments {
+            v.visit_comment_mut(comment);
+        }
+    }
+}
+
+pub fn visit_section_pattern_mut<'a, V>(v: &mut V, node: &mut SectionPattern<'a>)
+where
+    V: VisitMut<'a> + ?Sized,
+{
+    if let SectionPattern::ExcludeFile {
+        ref mut pattern, ..
+    } = *node
+    {
+        v.visit_section_pattern_mut(pattern);
+    }
+}
+
+pub fn visit_expression_mut<'a, V>(v: &mut V, node: &mut Expression<'a>)
+where
+    V: VisitMut<'a> + ?Sized,
+{
+    match *node {
+        Expression::Ident(_) | Expression::Number(_) => {}
+        Expression::Call {
+            ref mut arguments, ..
+        } => {
+            for arg in arguments {
+                v.visit_expression_mut(arg);
+            }
+        }
+        Expression::UnaryOp {
+            ref mut operator,
+            ref mut right,
+        } => {
+            v.visit_unary_operator_mut(operator);
+            v.visit_expression_mut(right);
+        }
+        Expression::BinaryOp {
+            ref mut left,
+            ref mut operator,
+            ref mut right,
+        } => {
+            v.visit_expression_mut(left);
+            v.visit_binary_operator_mut(operator);
+            v.visit_expression_mut(right);
+        }
+        Expression::TernaryOp {
+            ref mut condition,
+            ref mut left,
+            ref mut right,
+        } => {
+            v.visit_expression_mut(condition);
+            v.visit_expression_mut(left);
+            v.visit_expression_mut(right);
+        }
+    }
+}
+
+pub fn visit_comment_mut<'a, V>(v: &mut V, node: &mut Comment<'a>)
+where
+    V: VisitMut<'a> + ?Sized,
+{
+    v.visit_comment_position_mut(&mut node.position);
+}
+
+#[cfg(test)]
+mod tests {
+    use script::parse;
+    use visit_mut::*;
+
+    struct Double;
+
+    impl<'a> VisitMut<'a> for Double {
+        fn visit_expression_mut(&mut self, node: &mut Expression<'a>) {
+            if let Expression::Number(ref mut n) = *node {
+                *n *= 2;
+            }
+            visit_expression_mut(self, node);
+        }
+    }
+
+    #[test]
+    fn test_visit_mut() {
+        let (_, mut items) = parse("A = 1 + B(2, 3 ? 4 : 5);").unwrap();
+        let (_, expected) = parse("A = 2 + B(4, 6 ? 8 : 10);").unwrap();
+        for item in &mut items {
+            Double.visit_root_item_mut(item);
+        }
+        assert_eq!(items, expected);
+    }
+}
@@ -1,13 +1,55 @@
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_until},
-    character::complete::multispace1,
-    combinator::recognize,
-    multi::{fold_many0, fold_many1},
-    sequence::delimited,
-    IResult,
+    character::complete::{multispace1, space0},
+    combinator::{map, recognize},
+    error::{ErrorKind, ParseError},
+    multi::{fold_many0, fold_many1, many0},
+    sequence::{delimited, preceded},
+    Err, IResult,
 };
 
+/// A `/* ... */` comment attached to an AST node
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Comment<'a> {
+    /// Text between the `/*` and `*/` delimiters, not trimmed
+    pub text: Cow<'a, str>,
+    pub position: CommentPosition,
+}
+
+impl<'a> Comment<'a> {
+    /// Converts the comment into one that owns its text
+    pub fn into_owned(self) -> Comment<'static> {
+        Comment {
+            text: Cow::Owned(self.text.into_owned()),
+            position: self.position,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CommentPosition {
+    /// The comment is placed on the lines before the node
+    Leading,
+    /// The comment starts on the same line where the node ends
+    Trailing,
+    /// The comment follows the last node of a block, before the closing
+    /// brace or the end of the file
+    Dangling,
+}
+
+/// AST nodes that are able to carry comments.
+pub trait Commented<'a> {
+    /// Returns the comment list of the node, or `None` if this node does
+    /// not keep comments.
+    fn comments_mut(&mut self) -> Option<&mut Vec<Comment<'a>>>;
+}
+
 pub fn comment(input: &str) -> IResult<&str, &str> {
     delimited(tag("/*"), take_until("*/"), tag("*/"))(input)
 }
@@ -24,6 +66,115 @@ pub fn opt_space(input: &str) -> IResult<&str, &str> {
     recognize(fold_many0(space_or_comment, || (), |_, _| ()))(input)
 }
 
+pub fn leading_comments(input: &str) -> IResult<&str, Vec<&str>> {
+    map(
+        many0(alt((map(multispace1, |_| None), map(comment, Some)))),
+        |v: Vec<Option<&str>>| v.into_iter().flatten().collect(),
+    )(input)
+}
+
+fn trailing_comments(input: &str) -> IResult<&str, Vec<&str>> {
+    many0(preceded(space0, comment))(input)
+}
+
+/// Transforms a parser to consume the whitespace before the node and to
+/// attach the comments around it. Comments on the lines before the node are
+/// leading, comments that start on the line where the node ends are trailing.
+pub fn commented<'a, O, F>(mut f: F) -> impl FnMut(&'a str) -> IResult<&'a str, O>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, O>,
+    O: Commented<'a>,
+{
+    move |input: &'a str| {
+        let (input, leading) = leading_comments(input)?;
+        let (input, mut node) = f(input)?;
+        let (input, trailing) = trailing_comments(input)?;
+        if let Some(comments) = node.comments_mut() {
+            let leading = leading.into_iter().map(|text| Comment {
+                text: text.into(),
+                position: CommentPosition::Leading,
+            });
+            let trailing = trailing.into_iter().map(|text| Comment {
+                text: text.into(),
+                position: CommentPosition::Trailing,
+            });
+            comments.extend(leading.chain(trailing));
+        }
+        Ok((input, node))
+    }
+}
+
+fn commented_list<'a, O, F>(
+    mut input: &'a str,
+    f: &mut F,
+    min: usize,
+    kind: ErrorKind,
+) -> IResult<&'a str, Vec<O>>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, O>,
+    O: Commented<'a>,
+{
+    let mut items: Vec<O> = Vec::new();
+    loop {
+        match commented(&mut *f)(input) {
+            Ok((rest, item)) => {
+                if rest.len() == input.len() {
+                    return Err(Err::Error(ParseError::from_error_kind(input, kind)));
+                }
+                items.push(item);
+                input = rest;
+            }
+            Err(Err::Error(_)) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    if items.len() < min {
+        return Err(Err::Error(ParseError::from_error_kind(input, kind)));
+    }
+    if let Some(last) = items.last_mut() {
+        input = dangling_comments(input, last)?.0;
+    }
+    Ok((input, items))
+}
+
+/// Attaches the comments that follow the last node of a block to it.
+pub fn dangling_comments<'a, O>(input: &'a str, last: &mut O) -> IResult<&'a str, ()>
+where
+    O: Commented<'a>,
+{
+    let (rest, dangling) = leading_comments(input)?;
+    match last.comments_mut() {
+        Some(comments) => {
+            comments.extend(dangling.into_iter().map(|text| Comment {
+                text: text.into(),
+                position: CommentPosition::Dangling,
+            }));
+            Ok((rest, ()))
+        }
+        None => Ok((input, ())),
+    }
+}
+
+/// Parses zero or more nodes with [`commented`]. Comments after the last
+/// node are attached to it as dangling comments.
+pub fn commented_many0<'a, O, F>(mut f: F) -> impl FnMut(&'a str) -> IResult<&'a str, Vec<O>>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, O>,
+    O: Commented<'a>,
+{
+    move |input: &'a str| commented_list(input, &mut f, 0, ErrorKind::Many0)
+}
+
+/// Parses one or more nodes with [`commented`]. Comments after the last
+/// node are attached to it as dangling comments.
+pub fn commented_many1<'a, O, F>(mut f: F) -> impl FnMut(&'a str) -> IResult<&'a str, Vec<O>>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, O>,
+    O: Commented<'a>,
+{
+    move |input: &'a str| commented_list(input, &mut f, 1, ErrorKind::Many1)
+}
+
 /// Transforms a parser to automatically consume whitespace and comments
 /// between each token.
 macro_rules! wsc(